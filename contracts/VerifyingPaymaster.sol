@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.19;
+
+// Verifying paymaster for EntryPoint v0.6 that accepts the sponsorships signed by arka-light.
+//
+// paymasterAndData = paymaster (20 bytes) || abi.encode(uint48 validUntil, uint48 validAfter) || signature
+//
+// The verifying signer signs, as an EIP-191 personal message,
+//     keccak256(abi.encode(sender, nonce, keccak256(initCode), keccak256(callData), callGasLimit,
+//         verificationGasLimit, preVerificationGas, maxFeePerGas, maxPriorityFeePerGas,
+//         chainId, paymaster, validUntil, validAfter))
+// and may be an EOA or a contract wallet validating through ERC-1271. The userOpHash cannot be
+// signed: it covers paymasterAndData, which carries the signature.
+//
+// rotateVerifyingSigner switches to a new signer while still accepting the previous one's
+// signatures whose validUntil is at most overlapUntil, so rotating never invalidates
+// paymasterAndData that was already handed out.
+//
+// Build with: solc --optimize --bin -o contracts/out contracts/VerifyingPaymaster.sol
+
+struct UserOperation {
+    address sender;
+    uint256 nonce;
+    bytes initCode;
+    bytes callData;
+    uint256 callGasLimit;
+    uint256 verificationGasLimit;
+    uint256 preVerificationGas;
+    uint256 maxFeePerGas;
+    uint256 maxPriorityFeePerGas;
+    bytes paymasterAndData;
+    bytes signature;
+}
+
+interface IEntryPoint {
+    function depositTo(address account) external payable;
+    function addStake(uint32 unstakeDelaySec) external payable;
+    function unlockStake() external;
+    function withdrawStake(address payable withdrawAddress) external;
+    function withdrawTo(address payable withdrawAddress, uint256 withdrawAmount) external;
+    function balanceOf(address account) external view returns (uint256);
+}
+
+interface IERC1271 {
+    function isValidSignature(bytes32 hash, bytes memory signature) external view returns (bytes4);
+}
+
+contract VerifyingPaymaster {
+    uint256 private constant VALID_TIMESTAMP_OFFSET = 20;
+    uint256 private constant SIGNATURE_OFFSET = 84;
+    uint256 private constant SIG_VALIDATION_FAILED = 1;
+    // Upper bound of `s` for non-malleable ECDSA signatures
+    uint256 private constant MAX_S = 0x7FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF5D576E7357A4501DDFE92F46681B20A0;
+
+    IEntryPoint public immutable entryPoint;
+    address public verifyingSigner;
+    address public previousSigner;
+    uint48 public previousSignerValidUntil;
+    address public owner;
+
+    event VerifyingSignerChanged(address indexed previousSigner, address indexed newSigner);
+    event OwnershipTransferred(address indexed previousOwner, address indexed newOwner);
+
+    modifier onlyOwner() {
+        require(msg.sender == owner, "VerifyingPaymaster: caller is not the owner");
+        _;
+    }
+
+    modifier onlyEntryPoint() {
+        require(msg.sender == address(entryPoint), "VerifyingPaymaster: caller is not the EntryPoint");
+        _;
+    }
+
+    constructor(IEntryPoint _entryPoint, address _verifyingSigner) {
+        require(_verifyingSigner != address(0), "VerifyingPaymaster: zero verifying signer");
+        entryPoint = _entryPoint;
+        verifyingSigner = _verifyingSigner;
+        owner = msg.sender;
+        emit OwnershipTransferred(address(0), msg.sender);
+        emit VerifyingSignerChanged(address(0), _verifyingSigner);
+    }
+
+    function validatePaymasterUserOp(UserOperation calldata userOp, bytes32, uint256)
+        external
+        view
+        onlyEntryPoint
+        returns (bytes memory context, uint256 validationData)
+    {
+        (uint48 validUntil, uint48 validAfter, bytes calldata signature) = parsePaymasterAndData(userOp.paymasterAndData);
+        bytes32 hash = toEthSignedMessageHash(getHash(userOp, validUntil, validAfter));
+
+        // The previous signer is only trusted for signatures that expire within the overlap
+        bool sigFailed = !isValidSignature(verifyingSigner, hash, signature)
+            && !(validUntil != 0 && validUntil <= previousSignerValidUntil && isValidSignature(previousSigner, hash, signature));
+        return ("", packValidationData(sigFailed, validUntil, validAfter));
+    }
+
+    // Never called: validation returns an empty context
+    function postOp(uint8, bytes calldata, uint256) external view onlyEntryPoint {
+        revert("VerifyingPaymaster: postOp not supported");
+    }
+
+    // Every field of the operation except paymasterAndData, which holds the signature over this hash
+    function getHash(UserOperation calldata userOp, uint48 validUntil, uint48 validAfter) public view returns (bytes32) {
+        return keccak256(abi.encode(
+            userOp.sender,
+            userOp.nonce,
+            keccak256(userOp.initCode),
+            keccak256(userOp.callData),
+            userOp.callGasLimit,
+            userOp.verificationGasLimit,
+            userOp.preVerificationGas,
+            userOp.maxFeePerGas,
+            userOp.maxPriorityFeePerGas,
+            block.chainid,
+            address(this),
+            validUntil,
+            validAfter
+        ));
+    }
+
+    function parsePaymasterAndData(bytes calldata paymasterAndData)
+        public
+        pure
+        returns (uint48 validUntil, uint48 validAfter, bytes calldata signature)
+    {
+        (validUntil, validAfter) = abi.decode(paymasterAndData[VALID_TIMESTAMP_OFFSET:SIGNATURE_OFFSET], (uint48, uint48));
+        signature = paymasterAndData[SIGNATURE_OFFSET:];
+    }
+
+    function deposit() external payable {
+        entryPoint.depositTo{value: msg.value}(address(this));
+    }
+
+    function getDeposit() external view returns (uint256) {
+        return entryPoint.balanceOf(address(this));
+    }
+
+    function withdrawTo(address payable withdrawAddress, uint256 amount) external onlyOwner {
+        entryPoint.withdrawTo(withdrawAddress, amount);
+    }
+
+    function addStake(uint32 unstakeDelaySec) external payable onlyOwner {
+        entryPoint.addStake{value: msg.value}(unstakeDelaySec);
+    }
+
+    function unlockStake() external onlyOwner {
+        entryPoint.unlockStake();
+    }
+
+    function withdrawStake(address payable withdrawAddress) external onlyOwner {
+        entryPoint.withdrawStake(withdrawAddress);
+    }
+
+    // Replaces the signer outright, also ending any overlap; for a key that must stop being trusted
+    function setVerifyingSigner(address newSigner) external onlyOwner {
+        require(newSigner != address(0), "VerifyingPaymaster: zero verifying signer");
+        emit VerifyingSignerChanged(verifyingSigner, newSigner);
+        verifyingSigner = newSigner;
+        previousSigner = address(0);
+        previousSignerValidUntil = 0;
+    }
+
+    function rotateVerifyingSigner(address newSigner, uint48 overlapUntil) external onlyOwner {
+        require(newSigner != address(0), "VerifyingPaymaster: zero verifying signer");
+        require(overlapUntil >= block.timestamp, "VerifyingPaymaster: overlap already over");
+        emit VerifyingSignerChanged(verifyingSigner, newSigner);
+        previousSigner = verifyingSigner;
+        previousSignerValidUntil = overlapUntil;
+        verifyingSigner = newSigner;
+    }
+
+    function transferOwnership(address newOwner) external onlyOwner {
+        require(newOwner != address(0), "VerifyingPaymaster: zero owner");
+        emit OwnershipTransferred(owner, newOwner);
+        owner = newOwner;
+    }
+
+    function isValidSignature(address signer, bytes32 hash, bytes calldata signature) internal view returns (bool) {
+        if (signer == address(0)) {
+            return false;
+        }
+        if (signer.code.length > 0) {
+            (bool success, bytes memory result) =
+                signer.staticcall(abi.encodeCall(IERC1271.isValidSignature, (hash, signature)));
+            return success && result.length >= 32
+                && abi.decode(result, (bytes32)) == bytes32(IERC1271.isValidSignature.selector);
+        }
+
+        return signature.length == 65 && recover(hash, signature) == signer;
+    }
+
+    function recover(bytes32 hash, bytes calldata signature) internal pure returns (address) {
+        bytes32 r = bytes32(signature[0:32]);
+        bytes32 s = bytes32(signature[32:64]);
+        uint8 v = uint8(signature[64]);
+
+        if (uint256(s) > MAX_S) {
+            return address(0);
+        }
+        return ecrecover(hash, v, r, s);
+    }
+
+    function toEthSignedMessageHash(bytes32 hash) internal pure returns (bytes32) {
+        return keccak256(abi.encodePacked("\x19Ethereum Signed Message:\n32", hash));
+    }
+
+    function packValidationData(bool sigFailed, uint48 validUntil, uint48 validAfter) internal pure returns (uint256) {
+        return (sigFailed ? SIG_VALIDATION_FAILED : 0) | (uint256(validUntil) << 160) | (uint256(validAfter) << 208);
+    }
+}
@@ -0,0 +1,33 @@
+-- What each sponsorship policy has used per UTC day
+CREATE TABLE IF NOT EXISTS policy_usage (
+    chain_id BIGINT NOT NULL,
+    policy_id TEXT NOT NULL,
+    day BIGINT NOT NULL,
+    spent_wei TEXT NOT NULL,
+    reserved_wei TEXT NOT NULL,
+    ops BIGINT NOT NULL,
+    PRIMARY KEY (chain_id, policy_id, day)
+);
+
+-- Budget held for signed operations until they are included or their signature expires
+CREATE TABLE IF NOT EXISTS reservations (
+    chain_id BIGINT NOT NULL,
+    sender TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    policy_id TEXT NOT NULL,
+    day BIGINT NOT NULL,
+    amount_wei TEXT NOT NULL,
+    valid_until BIGINT NOT NULL,
+    PRIMARY KEY (chain_id, sender, nonce)
+);
+
+CREATE INDEX IF NOT EXISTS reservations_valid_until_idx ON reservations (chain_id, valid_until);
+
+-- Keys already seen, such as the nonces of included operations
+CREATE TABLE IF NOT EXISTS replay_keys (
+    chain_id BIGINT NOT NULL,
+    set_name TEXT NOT NULL,
+    replay_key TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    PRIMARY KEY (chain_id, set_name, replay_key)
+);
@@ -0,0 +1,19 @@
+-- What became of sponsored operations, keyed by the userOpHash they are submitted under and
+-- filled in from the bundler's receipts
+CREATE TABLE IF NOT EXISTS operation_statuses (
+    chain_id BIGINT NOT NULL,
+    user_op_hash TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    status TEXT NOT NULL,
+    valid_until BIGINT NOT NULL,
+    transaction_hash TEXT,
+    block_number BIGINT,
+    actual_gas_cost TEXT,
+    actual_gas_used TEXT,
+    reason TEXT,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (chain_id, user_op_hash)
+);
+
+CREATE INDEX IF NOT EXISTS operation_statuses_status_idx ON operation_statuses (chain_id, status, valid_until);
@@ -0,0 +1,12 @@
+-- Each chain's audit entries form their own HMAC chain. The head row is locked while an entry is
+-- appended, so instances sharing the database extend a chain one at a time.
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS chain_id BIGINT NOT NULL DEFAULT 0;
+UPDATE audit_log SET chain_id = (entry::json ->> 'chainId')::BIGINT;
+
+CREATE TABLE IF NOT EXISTS audit_chains (
+    chain_id BIGINT PRIMARY KEY,
+    hmac TEXT
+);
+
+INSERT INTO audit_chains (chain_id, hmac)
+SELECT chain_id, hmac FROM audit_log WHERE id IN (SELECT MAX(id) FROM audit_log GROUP BY chain_id);
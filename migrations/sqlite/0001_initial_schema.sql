@@ -0,0 +1,32 @@
+-- Sponsorships granted by the paymaster
+CREATE TABLE IF NOT EXISTS sponsorships (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_op_hash TEXT NOT NULL,
+    chain_id INTEGER NOT NULL,
+    sender TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    policy_id TEXT,
+    max_cost TEXT NOT NULL,
+    valid_after INTEGER NOT NULL,
+    valid_until INTEGER NOT NULL,
+    paymaster_and_data TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS sponsorships_user_op_hash_idx ON sponsorships (user_op_hash);
+CREATE INDEX IF NOT EXISTS sponsorships_sender_idx ON sponsorships (sender);
+
+-- Append-only record of every sponsorship decision
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at INTEGER NOT NULL,
+    user_op_hash TEXT,
+    sender TEXT NOT NULL,
+    policy_id TEXT,
+    decision TEXT NOT NULL,
+    reason TEXT,
+    signer TEXT,
+    signature_hash TEXT,
+    entry TEXT NOT NULL,
+    hmac TEXT
+);
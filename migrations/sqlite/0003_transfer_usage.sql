@@ -0,0 +1,10 @@
+-- Value moved by the operations of each sponsorship policy per UTC day, by asset; the zero
+-- address stands for the native currency
+CREATE TABLE IF NOT EXISTS transfer_usage (
+    chain_id INTEGER NOT NULL,
+    policy_id TEXT NOT NULL,
+    day INTEGER NOT NULL,
+    asset TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    PRIMARY KEY (chain_id, policy_id, day, asset)
+);
@@ -0,0 +1,2 @@
+-- Signature aggregator of the sponsored account, when simulation reported one
+ALTER TABLE sponsorships ADD COLUMN aggregator TEXT;
@@ -0,0 +1,15 @@
+-- Tokens owed and collected for operations paid for in an ERC-20 token, keyed like reservations
+-- since the on-chain userOpHash is only known once the operation is included
+CREATE TABLE IF NOT EXISTS token_debts (
+    chain_id INTEGER NOT NULL,
+    sender TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    token TEXT NOT NULL,
+    exchange_rate TEXT NOT NULL,
+    max_token_cost TEXT NOT NULL,
+    owed TEXT,
+    collected TEXT,
+    user_op_hash TEXT,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (chain_id, sender, nonce)
+);
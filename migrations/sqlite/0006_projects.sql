@@ -0,0 +1,6 @@
+-- Tenants managed through the admin API, each kept whole as JSON and shared by every chain
+CREATE TABLE IF NOT EXISTS projects (
+    id TEXT PRIMARY KEY,
+    project TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,12 @@
+-- Value each signed operation was counted as moving in transfer_usage, so signing the same
+-- sender and nonce again replaces its count instead of adding to it
+CREATE TABLE IF NOT EXISTS operation_transfers (
+    chain_id INTEGER NOT NULL,
+    sender TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    policy_id TEXT NOT NULL,
+    day INTEGER NOT NULL,
+    asset TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    PRIMARY KEY (chain_id, sender, nonce, asset)
+);
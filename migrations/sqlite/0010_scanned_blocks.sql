@@ -0,0 +1,6 @@
+-- Last block scanned for included operations on each chain, so scanning resumes there after a
+-- restart instead of at the latest block
+CREATE TABLE IF NOT EXISTS scanned_blocks (
+    chain_id INTEGER PRIMARY KEY,
+    block_number INTEGER NOT NULL
+);
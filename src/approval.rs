@@ -0,0 +1,164 @@
+// src/approval.rs
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ethers::types::{Address, H128, H256, U256};
+use serde::Serialize;
+
+use crate::config::ApprovalConfig;
+use crate::error::PaymasterError;
+use crate::types::{PaymasterResponse, SponsorshipContext, UserOperation};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum TicketStatus {
+    Pending,
+    // An operator approved the ticket and signing is in progress
+    Processing,
+    Approved(Box<PaymasterResponse>),
+    Rejected {
+        reason: String,
+    },
+    Expired,
+}
+
+// A sponsorship request parked until an operator decides on it
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ticket {
+    pub id: String,
+    pub user_op: UserOperation,
+    pub user_op_hash: H256,
+    pub max_cost: U256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+    // Token the sender pays gas in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<Address>,
+    // Verified origin of the request, kept for the approval
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    // Why the operation needs approval
+    pub reason: String,
+    pub created_at: u64,
+    pub expires_at: u64,
+    #[serde(flatten)]
+    pub status: TicketStatus,
+}
+
+pub struct ApprovalQueue {
+    config: ApprovalConfig,
+    tickets: Mutex<HashMap<String, Ticket>>,
+}
+
+impl ApprovalQueue {
+    pub fn new(config: ApprovalConfig) -> Self {
+        Self {
+            config,
+            tickets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Returns the reason an operation must be approved, if any rule matches
+    pub fn requires_approval(&self, user_op: &UserOperation, max_cost: U256) -> Option<String> {
+        let total_gas = user_op.call_gas_limit
+            .saturating_add(user_op.verification_gas_limit)
+            .saturating_add(user_op.pre_verification_gas);
+
+        if let Some(threshold) = self.config.gas_threshold {
+            if total_gas > U256::from(threshold) {
+                return Some(format!("Total gas {} exceeds approval threshold {}", total_gas, threshold));
+            }
+        }
+
+        if let Some(threshold) = self.config.max_cost_threshold {
+            if max_cost > threshold {
+                return Some(format!("Max cost {} wei exceeds approval threshold {} wei", max_cost, threshold));
+            }
+        }
+
+        None
+    }
+
+    // `context` carries the policy and origin the request resolved to, which are used again on approval
+    pub fn park(
+        &self,
+        user_op: &UserOperation,
+        user_op_hash: H256,
+        max_cost: U256,
+        context: &SponsorshipContext,
+        reason: String,
+        now: u64,
+    ) -> String {
+        let id = format!("{:x}", H128::random());
+        let ticket = Ticket {
+            id: id.clone(),
+            user_op: user_op.clone(),
+            user_op_hash,
+            max_cost,
+            policy_id: context.sponsorship_policy_id.clone(),
+            token: context.token,
+            origin: context.origin.clone(),
+            reason,
+            created_at: now,
+            expires_at: now + self.config.ticket_ttl_secs,
+            status: TicketStatus::Pending,
+        };
+
+        self.tickets.lock().unwrap().insert(id.clone(), ticket);
+        id
+    }
+
+    pub fn get(&self, id: &str, now: u64) -> Option<Ticket> {
+        let mut tickets = self.tickets.lock().unwrap();
+        self.expire(&mut tickets, now);
+        tickets.get(id).cloned()
+    }
+
+    pub fn pending(&self, now: u64) -> Vec<Ticket> {
+        let mut tickets = self.tickets.lock().unwrap();
+        self.expire(&mut tickets, now);
+
+        let mut pending: Vec<Ticket> = tickets
+            .values()
+            .filter(|ticket| matches!(ticket.status, TicketStatus::Pending))
+            .cloned()
+            .collect();
+        pending.sort_by_key(|ticket| ticket.created_at);
+        pending
+    }
+
+    // Move a pending ticket to processing so it is only signed once
+    pub fn begin_processing(&self, id: &str, now: u64) -> Result<Ticket, PaymasterError> {
+        let mut tickets = self.tickets.lock().unwrap();
+        self.expire(&mut tickets, now);
+
+        let ticket = tickets.get_mut(id)
+            .ok_or_else(|| PaymasterError::TicketNotFound(id.to_string()))?;
+        if !matches!(ticket.status, TicketStatus::Pending) {
+            return Err(PaymasterError::InvalidParameters(format!("Ticket {} is not pending", id)));
+        }
+
+        ticket.status = TicketStatus::Processing;
+        Ok(ticket.clone())
+    }
+
+    pub fn resolve(&self, id: &str, status: TicketStatus) -> Option<Ticket> {
+        let mut tickets = self.tickets.lock().unwrap();
+        let ticket = tickets.get_mut(id)?;
+        ticket.status = status;
+        Some(ticket.clone())
+    }
+
+    // Expire stale pending tickets and forget resolved ones a full TTL after they expired
+    fn expire(&self, tickets: &mut HashMap<String, Ticket>, now: u64) {
+        let ttl = self.config.ticket_ttl_secs;
+        tickets.retain(|_, ticket| now < ticket.expires_at + ttl);
+
+        for ticket in tickets.values_mut() {
+            if matches!(ticket.status, TicketStatus::Pending) && now >= ticket.expires_at {
+                ticket.status = TicketStatus::Expired;
+            }
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// src/audit.rs
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use ethers::types::{Address, H256, U256};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::db::Database;
+use crate::error::PaymasterError;
+use crate::types::{SponsorshipReceipt, UserOperation};
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Sponsored,
+    Pending,
+    Rejected,
+}
+
+impl Decision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sponsored => "sponsored",
+            Self::Pending => "pending",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+// A single sponsorship decision as written to the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub chain_id: u64,
+    pub user_op_hash: H256,
+    pub user_op: UserOperation,
+    pub policy_id: Option<String>,
+    // Token the sender paid gas in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<Address>,
+    // Verified dapp origin the request came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    pub decision: Decision,
+    pub reason: Option<String>,
+    pub signer: Option<Address>,
+    pub signature_hash: Option<H256>,
+    // Max cost charged for a sponsored operation, in wei
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<U256>,
+    // Signed receipt handed out with a sponsored operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SponsorshipReceipt>,
+    // HMAC over the previous entry's HMAC and this entry, when chaining is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hmac: Option<String>,
+}
+
+// Append-only log of every sponsorship decision, written to a JSON lines file and/or the database
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    database: Option<Database>,
+    hmac_key: Option<Vec<u8>>,
+    // HMAC of the last entry written to the file when there is no database; also serializes
+    // writers so the chain stays ordered
+    last_hmac: Mutex<Option<String>>,
+}
+
+impl AuditLog {
+    pub async fn new(path: Option<PathBuf>, database: Option<Database>, hmac_key: Option<Vec<u8>>) -> Result<Self> {
+        // With a database, each entry continues its chain from the database as it is inserted,
+        // and the file gets a copy. Otherwise the chain continues from the last entry in the file.
+        let last_hmac = match (&path, &database) {
+            (Some(path), None) if path.exists() => read_entries(path)
+                .await?
+                .last()
+                .and_then(|entry| entry.hmac.clone()),
+            _ => None,
+        };
+
+        Ok(Self {
+            path,
+            database,
+            hmac_key,
+            last_hmac: Mutex::new(last_hmac),
+        })
+    }
+
+    pub async fn append(&self, mut entry: AuditEntry) -> Result<(), PaymasterError> {
+        let mut last_hmac = self.last_hmac.lock().await;
+
+        match &self.database {
+            Some(database) => {
+                entry = database.append_audit_entry(entry, |previous, entry| self.seal(previous, entry))
+                    .await
+                    .map_err(|e| PaymasterError::AuditLogError(e.to_string()))?;
+            }
+            None => self.seal(last_hmac.as_deref(), &mut entry)?,
+        }
+
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| PaymasterError::AuditLogError(e.to_string()))?;
+
+        if let Some(path) = &self.path {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .map_err(|e| PaymasterError::AuditLogError(e.to_string()))?;
+
+            file.write_all(format!("{}\n", line).as_bytes())
+                .await
+                .and(file.flush().await)
+                .map_err(|e| PaymasterError::AuditLogError(e.to_string()))?;
+        }
+
+        *last_hmac = entry.hmac;
+        Ok(())
+    }
+
+    // Chain the entry to the one before it, when an HMAC key is configured
+    fn seal(&self, previous: Option<&str>, entry: &mut AuditEntry) -> Result<(), PaymasterError> {
+        if let Some(key) = &self.hmac_key {
+            entry.hmac = None;
+            entry.hmac = Some(chain_hmac(key, previous, entry)?);
+        }
+        Ok(())
+    }
+
+    // Wait for an append in progress to finish
+    pub async fn close(&self) {
+        drop(self.last_hmac.lock().await);
+    }
+
+    // Entries recorded since the given Unix timestamp, read from the database when one is attached
+    pub async fn entries(&self, since: u64) -> Result<Vec<AuditEntry>> {
+        if let Some(database) = &self.database {
+            return database.audit_entries(since).await;
+        }
+
+        match &self.path {
+            Some(path) if path.exists() => Ok(read_entries(path)
+                .await?
+                .into_iter()
+                .filter(|entry| entry.timestamp >= since)
+                .collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+// HMAC-SHA256 over the previous entry's HMAC followed by the entry serialized without its own HMAC
+fn chain_hmac(key: &[u8], previous: Option<&str>, entry: &AuditEntry) -> Result<String, PaymasterError> {
+    let payload = serde_json::to_vec(entry).map_err(|e| PaymasterError::AuditLogError(e.to_string()))?;
+
+    let mut mac = HmacSha256::new_from_slice(key).map_err(|e| PaymasterError::AuditLogError(e.to_string()))?;
+    mac.update(previous.unwrap_or_default().as_bytes());
+    mac.update(&payload);
+
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn read_entries(path: &Path) -> Result<Vec<AuditEntry>> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read audit log {}", path.display()))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| format!("Malformed audit entry on line {}", index + 1))
+        })
+        .collect()
+}
+
+// Recompute the HMAC chain over an audit log file, returning the number of verified entries
+pub async fn verify_file(path: &Path, key: &[u8]) -> Result<usize> {
+    let entries = read_entries(path).await?;
+    verify_chain(&entries, key, |index| format!("line {}", index + 1))
+}
+
+// Recompute the HMAC chain of every chain in the audit_log table, each in insertion order
+pub async fn verify_database(database: &Database, key: &[u8]) -> Result<usize> {
+    let mut chains: BTreeMap<u64, Vec<AuditEntry>> = BTreeMap::new();
+    for entry in database.audit_entries(0).await? {
+        chains.entry(entry.chain_id).or_default().push(entry);
+    }
+
+    let mut verified = 0;
+    for (chain_id, entries) in &chains {
+        verified += verify_chain(entries, key, |index| format!("entry {} of chain {}", index + 1, chain_id))?;
+    }
+    Ok(verified)
+}
+
+fn verify_chain(entries: &[AuditEntry], key: &[u8], position: impl Fn(usize) -> String) -> Result<usize> {
+    let mut previous: Option<String> = None;
+    for (index, mut entry) in entries.iter().cloned().enumerate() {
+        let recorded = entry.hmac.take()
+            .with_context(|| format!("Audit {} has no HMAC", position(index)))?;
+        let expected = chain_hmac(key, previous.as_deref(), &entry)?;
+
+        if recorded != expected {
+            bail!("HMAC mismatch at audit {}: the log has been modified", position(index));
+        }
+        previous = Some(recorded);
+    }
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::types::Bytes;
+
+    fn entry(timestamp: u64) -> AuditEntry {
+        AuditEntry {
+            timestamp,
+            chain_id: 1,
+            user_op_hash: H256::repeat_byte(timestamp as u8),
+            user_op: UserOperation {
+                sender: Address::repeat_byte(0x11),
+                nonce: U256::from(timestamp),
+                init_code: Bytes::default(),
+                call_data: Bytes::default(),
+                call_gas_limit: U256::zero(),
+                verification_gas_limit: U256::zero(),
+                pre_verification_gas: U256::zero(),
+                max_fee_per_gas: U256::zero(),
+                max_priority_fee_per_gas: U256::zero(),
+                paymaster_and_data: Bytes::default(),
+                signature: Bytes::default(),
+            },
+            policy_id: None,
+            token: None,
+            origin: None,
+            decision: Decision::Sponsored,
+            reason: None,
+            signer: None,
+            signature_hash: None,
+            max_cost: None,
+            receipt: None,
+            hmac: None,
+        }
+    }
+
+    // A restart must pick the chain up where the table left it, or verification fails at the
+    // first entry written after it
+    #[tokio::test]
+    async fn continues_the_database_chain_across_restarts() {
+        let path = std::env::temp_dir().join(format!("arka-light-audit-{}.db", std::process::id()));
+        let database = Database::connect(&format!("sqlite:{}", path.display())).await.unwrap();
+        database.migrate().await.unwrap();
+        let key = b"audit-key".to_vec();
+
+        let audit_log = AuditLog::new(None, Some(database.clone()), Some(key.clone())).await.unwrap();
+        audit_log.append(entry(1)).await.unwrap();
+        audit_log.append(entry(2)).await.unwrap();
+
+        let restarted = AuditLog::new(None, Some(database.clone()), Some(key.clone())).await.unwrap();
+        restarted.append(entry(3)).await.unwrap();
+        assert_eq!(verify_database(&database, &key).await.unwrap(), 3);
+        assert!(verify_database(&database, b"other-key").await.is_err());
+
+        database.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+
+    // A file added next to a database that already has entries must not restart the chain
+    #[tokio::test]
+    async fn continues_the_database_chain_when_the_file_is_new() {
+        let path = std::env::temp_dir().join(format!("arka-light-audit-file-{}.db", std::process::id()));
+        let file = std::env::temp_dir().join(format!("arka-light-audit-{}.jsonl", std::process::id()));
+        let database = Database::connect(&format!("sqlite:{}", path.display())).await.unwrap();
+        database.migrate().await.unwrap();
+        let key = b"audit-key".to_vec();
+
+        let audit_log = AuditLog::new(None, Some(database.clone()), Some(key.clone())).await.unwrap();
+        audit_log.append(entry(1)).await.unwrap();
+
+        let restarted = AuditLog::new(Some(file.clone()), Some(database.clone()), Some(key.clone())).await.unwrap();
+        restarted.append(entry(2)).await.unwrap();
+        assert_eq!(verify_database(&database, &key).await.unwrap(), 2);
+        assert_eq!(read_entries(&file).await.unwrap()[0].hmac, database.audit_entries(0).await.unwrap()[1].hmac);
+
+        database.close().await;
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(file);
+    }
+
+    // Instances sharing a database extend one chain per chain ID between them
+    #[tokio::test]
+    async fn shares_the_database_chain_between_instances() {
+        let path = std::env::temp_dir().join(format!("arka-light-audit-shared-{}.db", std::process::id()));
+        let database = Database::connect(&format!("sqlite:{}", path.display())).await.unwrap();
+        database.migrate().await.unwrap();
+        let key = b"audit-key".to_vec();
+
+        let first = AuditLog::new(None, Some(database.clone()), Some(key.clone())).await.unwrap();
+        let second = AuditLog::new(None, Some(database.clone()), Some(key.clone())).await.unwrap();
+        first.append(entry(1)).await.unwrap();
+        second.append(entry(2)).await.unwrap();
+        second.append(AuditEntry { chain_id: 10, ..entry(3) }).await.unwrap();
+        first.append(entry(4)).await.unwrap();
+        first.append(AuditEntry { chain_id: 10, ..entry(5) }).await.unwrap();
+        assert_eq!(verify_database(&database, &key).await.unwrap(), 5);
+
+        database.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,307 @@
+// src/auth.rs
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_TYPE, UPGRADE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::{json, Value};
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::config::{ApiKeyConfig, Permission};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// JSON-RPC error code for requests without a key, or calling methods the key does not cover
+const UNAUTHORIZED_CODE: i32 = -32003;
+
+const ALL_PERMISSIONS: [Permission; 4] = [Permission::Sponsor, Permission::Validate, Permission::Stats, Permission::Admin];
+
+// Methods taking a sponsorship context, with its position among their parameters
+const CONTEXT_PARAMS: [(&str, usize); 4] = [
+    ("pm_sponsorUserOperation", 1),
+    ("pm_validateUserOperation", 1),
+    ("pm_getSponsorshipQuota", 0),
+    ("pm_createSessionGrant", 1),
+];
+
+// Permissions that let a key call the method; empty when any known key may
+fn allowed_by(method: &str) -> &'static [Permission] {
+    match method {
+        "pm_sponsorUserOperation" | "pm_getSponsorshipTicket" | "pm_getSponsoredOperationStatus" | "pm_createSessionGrant" | "pm_revokeSessionGrant" => {
+            &[Permission::Sponsor]
+        }
+        "pm_validateUserOperation" => &[Permission::Sponsor, Permission::Validate],
+        "pm_getSponsorshipQuota" => &[Permission::Sponsor, Permission::Validate, Permission::Stats],
+        "pm_supportedEntryPoints" | "pm_getCapabilities" | "system_health" | "system_attestation" => &[],
+        "system_metrics" | "admin_getDashboard" | "admin_getSpendReport" | "admin_getRecentSponsorships" => {
+            &[Permission::Stats, Permission::Admin]
+        }
+        _ => &[Permission::Admin],
+    }
+}
+
+fn permits(permissions: &HashSet<Permission>, method: &str) -> bool {
+    let allowed_by = allowed_by(method);
+    allowed_by.is_empty() || allowed_by.iter().any(|permission| permissions.contains(permission))
+}
+
+// Checks every JSON-RPC call against the permissions of the API key it was sent with, and makes
+// that key the `apiKey` of every sponsorship context in it. Passes every request through when no
+// keys are configured.
+#[derive(Debug, Clone)]
+pub struct ApiKeyLayer {
+    keys: Arc<HashMap<String, HashSet<Permission>>>,
+    // HTTP routes translated into calls further in, with the method each calls
+    routes: Vec<(Method, &'static str, &'static str)>,
+    max_body_size: usize,
+}
+
+impl ApiKeyLayer {
+    pub fn new(api_keys: &[ApiKeyConfig], routes: Vec<(Method, &'static str, &'static str)>, max_body_size: u32) -> Self {
+        let keys = api_keys
+            .iter()
+            .map(|api_key| (api_key.key.clone(), api_key.permissions.iter().copied().collect()))
+            .collect();
+
+        Self { keys: Arc::new(keys), routes, max_body_size: max_body_size as usize }
+    }
+}
+
+impl<S> Layer<S> for ApiKeyLayer {
+    type Service = ApiKeys<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiKeys { inner, layer: self.clone() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKeys<S> {
+    inner: S,
+    layer: ApiKeyLayer,
+}
+
+impl<S> Service<Request<Body>> for ApiKeys<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if self.layer.keys.is_empty() {
+            let response = self.inner.call(request);
+            return Box::pin(async move { response.await.map_err(Into::into) });
+        }
+
+        // The clone that was polled ready serves this request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let key = request
+                .headers()
+                .get(API_KEY_HEADER)
+                .and_then(|key| key.to_str().ok())
+                .and_then(|key| layer.keys.get_key_value(key));
+            let Some((api_key, permissions)) = key else {
+                debug!("Refused request without a known API key");
+                return reject(StatusCode::UNAUTHORIZED, Value::Null, "A valid API key is required".to_string());
+            };
+
+            // Calls over a WebSocket are not seen here, so only keys that may call anything can open one
+            if request.headers().contains_key(UPGRADE) {
+                if !ALL_PERMISSIONS.iter().all(|permission| permissions.contains(permission)) {
+                    return reject(
+                        StatusCode::FORBIDDEN,
+                        Value::Null,
+                        "WebSocket connections require an API key with every permission".to_string(),
+                    );
+                }
+                return inner.call(request).await.map_err(Into::into);
+            }
+
+            let route = layer
+                .routes
+                .iter()
+                .find(|(method, path, _)| request.method() == method && request.uri().path() == *path);
+            if let Some((_, _, method)) = route {
+                if !permits(permissions, method) {
+                    debug!("Refused call to {} not covered by the API key", method);
+                    return reject(StatusCode::FORBIDDEN, Value::Null, format!("API key may not call {}", method));
+                }
+                return inner.call(request).await.map_err(Into::into);
+            }
+
+            let (parts, body) = request.into_parts();
+            let Some(body) = read_body(body, layer.max_body_size).await? else {
+                return Response::builder()
+                    .status(StatusCode::PAYLOAD_TOO_LARGE)
+                    .body(Body::empty())
+                    .map_err(Into::into);
+            };
+
+            for (id, method) in calls(&body) {
+                if !permits(permissions, &method) {
+                    debug!("Refused call to {} not covered by the API key", method);
+                    return reject(StatusCode::FORBIDDEN, id, format!("API key may not call {}", method));
+                }
+            }
+            let body = match bind_api_key(&body, api_key) {
+                Ok(Some(bound)) => bound,
+                Ok(None) => body,
+                Err(id) => {
+                    debug!("Refused a sponsorship context naming another API key");
+                    return reject(StatusCode::FORBIDDEN, id, format!("apiKey must match the {} header", API_KEY_HEADER));
+                }
+            };
+
+            inner.call(Request::from_parts(parts, Body::from(body))).await.map_err(Into::into)
+        })
+    }
+}
+
+// Buffer the request body, or None if it is larger than `limit`
+async fn read_body(mut body: Body, limit: usize) -> Result<Option<Vec<u8>>, hyper::Error> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+// ID and method name of every call in a single or batch request. A body that does not parse is
+// let through for the server to answer with a parse error.
+fn calls(body: &[u8]) -> Vec<(Value, String)> {
+    let calls = match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Array(calls)) => calls,
+        Ok(call) => vec![call],
+        Err(_) => return Vec::new(),
+    };
+
+    calls
+        .into_iter()
+        .map(|call| {
+            let id = call.get("id").cloned().unwrap_or(Value::Null);
+            let method = call.get("method").and_then(Value::as_str).unwrap_or_default().to_string();
+            (id, method)
+        })
+        .collect()
+}
+
+// Set the `apiKey` of every sponsorship context to the key the request was sent with, so that
+// policies, origins and the maintenance allowlist only ever see a key the caller holds. Returns
+// None when there is nothing to bind, or the ID of the first call whose context names another key.
+fn bind_api_key(body: &[u8], api_key: &str) -> Result<Option<Vec<u8>>, Value> {
+    let Ok(mut request) = serde_json::from_slice::<Value>(body) else {
+        return Ok(None);
+    };
+    let calls: Vec<&mut Value> = match &mut request {
+        Value::Array(calls) => calls.iter_mut().collect(),
+        call => vec![call],
+    };
+
+    let mut bound = false;
+    for call in calls {
+        let position = call
+            .get("method")
+            .and_then(Value::as_str)
+            .and_then(|method| CONTEXT_PARAMS.iter().find(|(name, _)| *name == method))
+            .map(|(_, position)| *position);
+        let id = call.get("id").cloned().unwrap_or(Value::Null);
+        let Some((position, Some(Value::Array(params)))) = position.map(|position| (position, call.get_mut("params"))) else {
+            continue;
+        };
+
+        if params.len() == position {
+            params.push(Value::Null);
+        }
+        match params.get_mut(position) {
+            Some(context @ Value::Null) => *context = json!({ "apiKey": api_key }),
+            Some(Value::Object(context)) => {
+                if context.get("apiKey").and_then(Value::as_str).is_some_and(|sent| sent != api_key) {
+                    return Err(id);
+                }
+                context.insert("apiKey".to_string(), Value::String(api_key.to_string()));
+            }
+            // Left for the server to refuse as invalid parameters
+            _ => continue,
+        }
+        bound = true;
+    }
+
+    match bound {
+        true => Ok(serde_json::to_vec(&request).ok()),
+        false => Ok(None),
+    }
+}
+
+fn reject(status: StatusCode, id: Value, message: String) -> Result<Response<Body>, Box<dyn Error + Send + Sync>> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": UNAUTHORIZED_CODE, "message": message },
+    });
+
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(request: Value) -> Result<Option<Value>, Value> {
+        bind_api_key(request.to_string().as_bytes(), "header-key")
+            .map(|bound| bound.map(|body| serde_json::from_slice(&body).unwrap()))
+    }
+
+    #[test]
+    fn binds_contexts_to_the_header_key() {
+        let bound = bind(json!({"jsonrpc": "2.0", "id": 1, "method": "pm_sponsorUserOperation", "params": [{}]})).unwrap();
+        assert_eq!(bound.unwrap()["params"][1], json!({"apiKey": "header-key"}));
+
+        let bound = bind(json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "pm_getSponsorshipQuota", "params": [{"sponsorshipPolicyId": "open"}]},
+            {"jsonrpc": "2.0", "id": 2, "method": "pm_createSessionGrant", "params": [{}, null]},
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(bound[0]["params"][0], json!({"sponsorshipPolicyId": "open", "apiKey": "header-key"}));
+        assert_eq!(bound[1]["params"][1], json!({"apiKey": "header-key"}));
+
+        let same = json!({"jsonrpc": "2.0", "id": 1, "method": "pm_validateUserOperation", "params": [{}, {"apiKey": "header-key"}]});
+        assert_eq!(bind(same.clone()).unwrap(), Some(same));
+        assert_eq!(bind(json!({"jsonrpc": "2.0", "id": 1, "method": "system_health", "params": []})).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_contexts_naming_another_key() {
+        let request = json!([
+            {"jsonrpc": "2.0", "id": 1, "method": "pm_getSponsorshipQuota", "params": []},
+            {"jsonrpc": "2.0", "id": 2, "method": "pm_sponsorUserOperation", "params": [{}, {"apiKey": "other-key"}]},
+        ]);
+        assert_eq!(bind(request), Err(json!(2)));
+    }
+}
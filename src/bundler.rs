@@ -0,0 +1,45 @@
+// src/bundler.rs
+use ethers::types::{H256, U256, U64};
+use serde::{Deserialize, Serialize};
+
+use crate::error::PaymasterError;
+use crate::provider::EthProvider;
+
+// What a bundler reports for an included operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    pub actual_gas_cost: U256,
+    pub actual_gas_used: U256,
+    pub success: bool,
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub receipt: BundleReceipt,
+}
+
+// The part of the bundle transaction's receipt that is kept
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleReceipt {
+    pub transaction_hash: H256,
+    pub block_number: U64,
+}
+
+// ERC-4337 bundler the chain's sponsored operations are submitted through
+pub struct Bundler {
+    provider: EthProvider,
+}
+
+impl Bundler {
+    pub fn new(provider: EthProvider) -> Self {
+        Self { provider }
+    }
+
+    // The operation's receipt, or None while the bundler has not seen it included
+    pub async fn user_operation_receipt(&self, user_op_hash: H256) -> Result<Option<UserOperationReceipt>, PaymasterError> {
+        self.provider
+            .request("eth_getUserOperationReceipt", [user_op_hash])
+            .await
+            .map_err(|e| PaymasterError::BundlerError(e.to_string()))
+    }
+}
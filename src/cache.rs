@@ -0,0 +1,46 @@
+// src/cache.rs
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type Slot<V> = Arc<tokio::sync::Mutex<Option<(Instant, V)>>>;
+
+// Provider reads reused for a short TTL. Concurrent misses for the same key wait for a single
+// fetch instead of all hitting the provider; failed fetches are not cached.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    slots: Mutex<HashMap<K, Slot<V>>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    // A zero TTL disables caching
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn get_or_fetch<E, Fut>(&self, key: K, fetch: impl FnOnce() -> Fut) -> Result<V, E>
+    where
+        Fut: Future<Output = Result<V, E>>,
+    {
+        if self.ttl.is_zero() {
+            return fetch().await;
+        }
+
+        let slot = self.slots.lock().unwrap().entry(key).or_default().clone();
+        let mut slot = slot.lock().await;
+        if let Some((fetched_at, value)) = slot.as_ref() {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(value.clone());
+            }
+        }
+
+        let value = fetch().await?;
+        *slot = Some((Instant::now(), value.clone()));
+        Ok(value)
+    }
+}
@@ -0,0 +1,114 @@
+// src/calldata.rs
+use ethers::abi::{self, ParamType, Token};
+use ethers::types::{Address, Bytes, U256};
+use ethers::utils::id;
+
+// Account entry points whose calls can be read out of an operation's callData
+const EXECUTE: &str = "execute(address,uint256,bytes)";
+const EXECUTE_BATCH: &str = "executeBatch(address[],bytes[])";
+const EXECUTE_BATCH_WITH_VALUE: &str = "executeBatch(address[],uint256[],bytes[])";
+
+// ERC-20 functions whose amounts count as value moved
+const TRANSFER: &str = "transfer(address,uint256)";
+const TRANSFER_FROM: &str = "transferFrom(address,address,uint256)";
+
+// A single call made by the account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub target: Address,
+    pub value: U256,
+    pub data: Bytes,
+}
+
+// The calls an operation makes, or None when its callData uses an unknown execute function
+pub fn decode_calls(call_data: &[u8]) -> Option<Vec<Call>> {
+    if call_data.is_empty() {
+        return Some(Vec::new());
+    }
+    let (selector, args) = call_data.split_first_chunk::<4>()?;
+
+    match *selector {
+        s if s == id(EXECUTE) => {
+            let tokens = abi::decode(&[ParamType::Address, ParamType::Uint(256), ParamType::Bytes], args).ok()?;
+            let [target, value, data] = <[Token; 3]>::try_from(tokens).ok()?;
+            Some(vec![Call {
+                target: target.into_address()?,
+                value: value.into_uint()?,
+                data: data.into_bytes()?.into(),
+            }])
+        }
+        s if s == id(EXECUTE_BATCH) => {
+            let tokens = abi::decode(
+                &[ParamType::Array(Box::new(ParamType::Address)), ParamType::Array(Box::new(ParamType::Bytes))],
+                args,
+            ).ok()?;
+            let [targets, data] = <[Token; 2]>::try_from(tokens).ok()?;
+            let targets = targets.into_array()?;
+            let data = data.into_array()?;
+            // A batch without data entries calls each target with empty data
+            if !data.is_empty() && data.len() != targets.len() {
+                return None;
+            }
+            targets
+                .into_iter()
+                .enumerate()
+                .map(|(i, target)| {
+                    Some(Call {
+                        target: target.into_address()?,
+                        value: U256::zero(),
+                        data: data.get(i).cloned().map_or(Some(Vec::new()), Token::into_bytes)?.into(),
+                    })
+                })
+                .collect()
+        }
+        s if s == id(EXECUTE_BATCH_WITH_VALUE) => {
+            let tokens = abi::decode(
+                &[
+                    ParamType::Array(Box::new(ParamType::Address)),
+                    ParamType::Array(Box::new(ParamType::Uint(256))),
+                    ParamType::Array(Box::new(ParamType::Bytes)),
+                ],
+                args,
+            ).ok()?;
+            let [targets, values, data] = <[Token; 3]>::try_from(tokens).ok()?;
+            let targets = targets.into_array()?;
+            let values = values.into_array()?;
+            let data = data.into_array()?;
+            // Empty value arrays mean no value is sent
+            if data.len() != targets.len() || (!values.is_empty() && values.len() != targets.len()) {
+                return None;
+            }
+            targets
+                .into_iter()
+                .zip(data)
+                .enumerate()
+                .map(|(i, (target, data))| {
+                    Some(Call {
+                        target: target.into_address()?,
+                        value: values.get(i).cloned().map_or(Some(U256::zero()), Token::into_uint)?,
+                        data: data.into_bytes()?.into(),
+                    })
+                })
+                .collect()
+        }
+        _ => None,
+    }
+}
+
+// Whether callData uses one of the execute functions `decode_calls` reads
+pub fn is_execute(call_data: &[u8]) -> bool {
+    call_data
+        .get(..4)
+        .is_some_and(|selector| [EXECUTE, EXECUTE_BATCH, EXECUTE_BATCH_WITH_VALUE].iter().any(|function| selector == id(function)))
+}
+
+// Amount of the token at the call's target moved by an ERC-20 `transfer` or `transferFrom`
+pub fn token_amount(call: &Call) -> Option<U256> {
+    let (selector, args) = call.data.split_first_chunk::<4>()?;
+    let params = match *selector {
+        s if s == id(TRANSFER) => vec![ParamType::Address, ParamType::Uint(256)],
+        s if s == id(TRANSFER_FROM) => vec![ParamType::Address, ParamType::Address, ParamType::Uint(256)],
+        _ => return None,
+    };
+    abi::decode(&params, args).ok()?.pop()?.into_uint()
+}
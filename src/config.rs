@@ -0,0 +1,938 @@
+// src/config.rs
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ethers::types::{Address, U256};
+use ethers::utils::{id, parse_ether, parse_units};
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer};
+
+use crate::project::POLICY_SEPARATOR;
+
+// Settings loaded from the optional TOML file passed with `--config`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub access: AccessConfig,
+    pub signing: SigningConfig,
+    pub audit: AuditConfig,
+    pub replay: ReplayConfig,
+    pub approval: ApprovalConfig,
+    pub limits: LimitsConfig,
+    pub paymaster_gas: PaymasterGasConfig,
+    pub fees: FeesConfig,
+    pub cache: CacheConfig,
+    pub provider: ProviderConfig,
+    pub validity: ValidityConfig,
+    pub simulation: SimulationConfig,
+    pub sessions: SessionConfig,
+    pub rebalance: RebalanceConfig,
+    pub settlement: SettlementConfig,
+    pub bundler: BundlerConfig,
+    pub self_test: SelfTestConfig,
+    pub degradation: DegradationConfig,
+    pub maintenance: MaintenanceConfig,
+    pub store: StoreConfig,
+    pub projects: ProjectsConfig,
+    // Per-chain settings keyed by chain ID
+    pub chains: HashMap<String, ChainConfig>,
+    // Sponsorship policies keyed by policy ID
+    pub policies: HashMap<String, PolicyConfig>,
+    // Dapp origins requests may be attributed to, keyed by domain
+    pub origins: HashMap<String, OriginConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    // How long in-flight requests may run after a shutdown signal, in seconds
+    pub shutdown_grace_period_secs: u64,
+    // Largest accepted request body, in bytes
+    pub max_request_body_size: u32,
+    // Serve the operator dashboard on GET /dashboard
+    pub dashboard: bool,
+    // Serve the REST facade under /v1
+    pub rest_api: bool,
+    // Longest deadline a client may set, in milliseconds; also applied to requests without one
+    pub max_request_timeout_ms: Option<u64>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            shutdown_grace_period_secs: 30,
+            max_request_body_size: 1024 * 1024,
+            dashboard: false,
+            rest_api: false,
+            max_request_timeout_ms: None,
+        }
+    }
+}
+
+// Client address filters applied before a request reaches the RPC methods
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    // Every method outside the `admin_` namespace
+    pub public: AccessList,
+    // The `admin_` namespace; when empty, no address is let into it under access lists
+    pub admin: AccessList,
+    // When any are listed, every request must present one of these keys in the `x-api-key`
+    // header and may only call the methods its permissions cover
+    pub api_keys: Vec<ApiKeyConfig>,
+}
+
+impl AccessConfig {
+    pub fn enabled(&self) -> bool {
+        !self.public.is_empty() || !self.admin.is_empty()
+    }
+
+    // Whether anything stands between TCP clients and the `admin_` namespace; public lists do not
+    pub fn guards_admin(&self) -> bool {
+        !self.admin.is_empty() || !self.api_keys.is_empty()
+    }
+
+    pub fn permits_admin(&self, ip: IpAddr) -> bool {
+        !self.admin.is_empty() && self.admin.permits(ip)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApiKeyConfig {
+    pub key: String,
+    pub permissions: Vec<Permission>,
+}
+
+// Groups of RPC methods an API key may be allowed to call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // Sign sponsorships and manage session grants
+    Sponsor,
+    // Check operations and quotas without signing
+    Validate,
+    // Metrics, dashboard and spend reports
+    Stats,
+    // Every `admin_` method
+    Admin,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AccessList {
+    // CIDR ranges let in; when empty every address is
+    pub allow: Vec<IpNet>,
+    // CIDR ranges kept out, even if they are also allowed
+    pub deny: Vec<IpNet>,
+}
+
+impl AccessList {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack listener show up as IPv4-mapped IPv6 addresses
+        let ip = ip.to_canonical();
+        (self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip)))
+            && !self.deny.iter().any(|net| net.contains(&ip))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SigningConfig {
+    // How often each signer's health is probed, in seconds
+    pub health_check_interval_secs: u64,
+    // Signers in priority order; the first one is the primary
+    pub signers: Vec<SignerConfig>,
+    // Standby signers that fresh signatures move to on a key rotation
+    pub next_signers: Vec<SignerConfig>,
+    pub queue: SigningQueueConfig,
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            health_check_interval_secs: 30,
+            signers: Vec::new(),
+            next_signers: Vec::new(),
+            queue: SigningQueueConfig::default(),
+        }
+    }
+}
+
+// Queue in front of the signers, for network HSMs and KMS with high latency
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SigningQueueConfig {
+    pub enabled: bool,
+    // Requests waiting beyond this are rejected immediately
+    pub capacity: usize,
+    // Most requests handed to the signer together, and batches in flight at once
+    pub batch_size: usize,
+    pub concurrency: usize,
+    // How long a request may wait and sign before it fails, in milliseconds
+    pub deadline_ms: u64,
+}
+
+impl Default for SigningQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 256,
+            batch_size: 16,
+            concurrency: 4,
+            deadline_ms: 2000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+    // Raw private key, read from the named environment variable
+    Local { private_key_env: String },
+    // Encrypted JSON keystore, unlocked with the password in the named environment variable
+    Keystore { path: PathBuf, password_env: String },
+    // Remote signing service (KMS bridge, Web3Signer, Clef) speaking `eth_sign`
+    Remote { url: String, address: Address },
+    // Smart contract wallet (multisig) validated through ERC-1271; `threshold` of the
+    // co-signers sign and their signatures are assembled as `scheme` expects
+    Contract {
+        address: Address,
+        threshold: usize,
+        co_signers: Vec<SignerConfig>,
+        #[serde(default)]
+        scheme: ContractSignatureScheme,
+    },
+}
+
+// What a contract signer's isValidSignature checks, and so how co-signer signatures are assembled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractSignatureScheme {
+    // Safe v1.3 and later: owners sign the Safe's EIP-712 SafeMessage over the hash, sent as
+    // eth_sign signatures
+    #[default]
+    Safe,
+    // Multisigs that recover each 65-byte signature against the hash they are given
+    Concatenated,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    // JSON lines file that every decision is appended to
+    pub path: Option<PathBuf>,
+    // Also record decisions in the `audit_log` table (requires `--database-url`)
+    pub database: bool,
+    // Environment variable holding the key for the HMAC chain over entries
+    pub hmac_key_env: Option<String>,
+}
+
+impl AuditConfig {
+    pub fn enabled(&self) -> bool {
+        self.path.is_some() || self.database
+    }
+
+    pub fn hmac_key(&self) -> Result<Option<Vec<u8>>> {
+        self.hmac_key_env
+            .as_ref()
+            .map(|name| {
+                std::env::var(name)
+                    .map(String::into_bytes)
+                    .with_context(|| format!("Environment variable {} is not set", name))
+            })
+            .transpose()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    // JSON lines file that every sponsorship request is appended to, for the `replay` subcommand
+    pub capture_path: Option<PathBuf>,
+}
+
+// Operations matching any of these thresholds are held for operator approval
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ApprovalConfig {
+    // Combined call, verification and pre-verification gas limit
+    pub gas_threshold: Option<u64>,
+    // Maximum cost of the operation, in ETH
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max_cost_threshold: Option<U256>,
+    // How long a ticket waits for a decision, in seconds
+    pub ticket_ttl_secs: u64,
+}
+
+impl Default for ApprovalConfig {
+    fn default() -> Self {
+        Self {
+            gas_threshold: None,
+            max_cost_threshold: None,
+            ticket_ttl_secs: 3600,
+        }
+    }
+}
+
+impl ApprovalConfig {
+    pub fn enabled(&self) -> bool {
+        self.gas_threshold.is_some() || self.max_cost_threshold.is_some()
+    }
+}
+
+// Hard caps on what a single operation may ask for; anything above is rejected outright
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub max_call_gas_limit: u64,
+    pub max_verification_gas_limit: u64,
+    pub max_pre_verification_gas: u64,
+    // Byte lengths of the operation's payload fields
+    pub max_call_data_size: usize,
+    pub max_init_code_size: usize,
+    // Maximum cost of a single operation, in ETH
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max_cost: Option<U256>,
+    // Check the structure of initCode, callData and signature before the operation is hashed or
+    // decoded, and reject anything malformed
+    pub strict_decoding: bool,
+    // Caps applied in strict decoding only
+    pub max_signature_size: usize,
+    pub max_batch_calls: usize,
+    // Functions callData may start with, as selectors or signatures; any function when empty
+    #[serde(deserialize_with = "deserialize_selectors")]
+    pub call_data_selectors: Vec<[u8; 4]>,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_call_gas_limit: 30_000_000,
+            max_verification_gas_limit: 10_000_000,
+            max_pre_verification_gas: 5_000_000,
+            max_call_data_size: 128 * 1024,
+            max_init_code_size: 64 * 1024,
+            max_cost: None,
+            strict_decoding: false,
+            max_signature_size: 4096,
+            max_batch_calls: 64,
+            call_data_selectors: Vec::new(),
+        }
+    }
+}
+
+// Gas EntryPoint v0.7 and later grant the paymaster, set in paymasterAndData and counted in the
+// max cost. Token mode settles the payment in postOp; verifying mode has next to nothing to do there.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PaymasterGasConfig {
+    pub verifying: ModeGasConfig,
+    pub token: ModeGasConfig,
+}
+
+impl Default for PaymasterGasConfig {
+    fn default() -> Self {
+        Self {
+            verifying: ModeGasConfig { verification_gas_limit: 50_000, post_op_gas_limit: 1 },
+            token: ModeGasConfig { verification_gas_limit: 100_000, post_op_gas_limit: 75_000 },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ModeGasConfig {
+    pub verification_gas_limit: u64,
+    pub post_op_gas_limit: u64,
+}
+
+// Checks that an operation pays enough to be included before its validity window runs out
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FeesConfig {
+    // Lowest accepted maxPriorityFeePerGas, in gwei
+    #[serde(deserialize_with = "deserialize_gwei")]
+    pub min_priority_fee: Option<U256>,
+    // Also require the priority fee to reach this percentile of recent blocks' priority fees,
+    // and the max fee to cover the next block's base fee
+    pub priority_fee_percentile: Option<f64>,
+    // Number of recent blocks sampled for the percentile
+    pub fee_history_blocks: u64,
+    // Gas price that max costs are reckoned at, for budgets and deposit holds
+    pub gas_price_buffer: GasPriceBuffer,
+}
+
+impl Default for FeesConfig {
+    fn default() -> Self {
+        Self {
+            min_priority_fee: None,
+            priority_fee_percentile: None,
+            fee_history_blocks: 10,
+            gas_price_buffer: GasPriceBuffer::default(),
+        }
+    }
+}
+
+// How the gas price of an operation's max cost is worked out. Operations never pay more than
+// their maxFeePerGas, so the strategies that follow recent fees are capped at it.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum GasPriceBuffer {
+    // maxFeePerGas raised by a flat percentage
+    Percent { percent: u64 },
+    // The next block's base fee times `multiplier`, plus the operation's maxPriorityFeePerGas
+    BaseFeeMultiplier { multiplier: f64 },
+    // The highest price paid across the last `blocks` blocks, at their base fee plus the
+    // priority fee at `percentile`
+    Percentile { percentile: f64, blocks: u64 },
+}
+
+impl Default for GasPriceBuffer {
+    fn default() -> Self {
+        Self::Percent { percent: 10 }
+    }
+}
+
+// How long provider reads (paymaster deposit, fee history, token prices) are reused
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    // 0 disables caching
+    pub ttl_ms: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { ttl_ms: 2000 }
+    }
+}
+
+// Keeps each paymaster deposit within a band, topped up from and drained to wallets outside the service
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RebalanceConfig {
+    // Environment variable holding the funding wallet's key; rebalancing is off without it
+    pub funding_key_env: Option<String>,
+    pub interval_secs: u64,
+    // A deposit below `min_deposit` is topped up to `target_deposit`, in ETH
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub min_deposit: Option<U256>,
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub target_deposit: Option<U256>,
+    // A deposit above `max_deposit` is withdrawn to `treasury` down to `target_deposit`, in ETH.
+    // The funding wallet must own the paymaster contracts.
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max_deposit: Option<U256>,
+    pub treasury: Option<Address>,
+    // Funding wallet balance below which `fundingBalanceLow` is raised, in ETH
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub min_funding_balance: Option<U256>,
+}
+
+impl Default for RebalanceConfig {
+    fn default() -> Self {
+        Self {
+            funding_key_env: None,
+            interval_secs: 300,
+            min_deposit: None,
+            target_deposit: None,
+            max_deposit: None,
+            treasury: None,
+            min_funding_balance: None,
+        }
+    }
+}
+
+impl RebalanceConfig {
+    pub fn enabled(&self) -> bool {
+        self.funding_key_env.is_some()
+    }
+
+    pub fn funding_key(&self) -> Result<Option<String>> {
+        self.funding_key_env
+            .as_ref()
+            .map(|name| std::env::var(name).with_context(|| format!("Environment variable {} is not set", name)))
+            .transpose()
+    }
+}
+
+// Tenants with their own API keys, policies and webhooks, managed through the admin API and
+// kept in the sponsorship store
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProjectsConfig {
+    pub enabled: bool,
+    // How often projects are reloaded from the store, to pick up changes made through other instances
+    pub sync_interval_secs: u64,
+}
+
+impl Default for ProjectsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sync_interval_secs: 30,
+        }
+    }
+}
+
+// Polling of the chain's bundler for the receipts of sponsored operations
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BundlerConfig {
+    pub receipt_poll_interval_secs: u64,
+}
+
+impl Default for BundlerConfig {
+    fn default() -> Self {
+        Self { receipt_poll_interval_secs: 12 }
+    }
+}
+
+// Signs a synthetic operation with every shard before the server accepts requests, and refuses
+// to start when its paymasterAndData does not decode back to what was signed
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SelfTestConfig {
+    pub enabled: bool,
+    // Also have each paymaster contract validate the signature with validatePaymasterUserOp,
+    // called through eth_call from the EntryPoint
+    pub on_chain: bool,
+}
+
+impl Default for SelfTestConfig {
+    fn default() -> Self {
+        Self { enabled: true, on_chain: false }
+    }
+}
+
+// Sweeps the tokens paymasters collect from senders paying in tokens to a wallet outside the service
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SettlementConfig {
+    // Environment variable holding the paymaster owner's key; settlement is off without it
+    pub owner_key_env: Option<String>,
+    pub treasury: Option<Address>,
+    pub interval_secs: u64,
+}
+
+impl Default for SettlementConfig {
+    fn default() -> Self {
+        Self {
+            owner_key_env: None,
+            treasury: None,
+            interval_secs: 3600,
+        }
+    }
+}
+
+impl SettlementConfig {
+    pub fn owner_key(&self) -> Result<Option<String>> {
+        self.owner_key_env
+            .as_ref()
+            .map(|name| std::env::var(name).with_context(|| format!("Environment variable {} is not set", name)))
+            .transpose()
+    }
+}
+
+// Deposit levels below which sponsorship narrows, so a draining deposit is rationed instead of
+// running dry. Levels are in ETH and compared with the total deposit of every paymaster.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DegradationConfig {
+    // Only operations under policies marked `priority` are sponsored
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub priority_only_below: Option<U256>,
+    // Only operations that were already signed and not yet included are signed again
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub renewal_only_below: Option<U256>,
+    // Nothing is sponsored
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub pause_below: Option<U256>,
+}
+
+// Only the listed API keys and senders are served while maintenance mode is on
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    // Start in maintenance mode; `--maintenance` does the same
+    pub enabled: bool,
+    // Access API keys that are still served
+    pub api_keys: Vec<String>,
+    pub senders: Vec<Address>,
+    // Unix timestamp sponsorship is expected to resume at, reported to turned-away callers
+    pub eta: Option<u64>,
+}
+
+// Where policy budgets, reservations and the sponsorship history are kept
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    pub backend: StoreBackend,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreBackend {
+    // Process memory, lost on restart
+    #[default]
+    Memory,
+    // The database given with `--database-url`
+    Sqlite,
+    Postgres,
+}
+
+// Upper bounds on what a session grant may ask for
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub max_ttl_secs: u64,
+    pub max_ops: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            max_ttl_secs: 3600,
+            max_ops: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SimulationConfig {
+    // Run EntryPoint.simulateValidation with the signed paymaster data before handing it out
+    pub enabled: bool,
+    // Hand out paymaster data for operations whose signature simulation reports as failed, for
+    // wallets that sign only once they have it. The paymaster's own signature is then unchecked.
+    pub allow_failed_signatures: bool,
+}
+
+// Sponsorship validity windows start from the latest block timestamp instead of the host clock
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ValidityConfig {
+    // How far validAfter is moved back from the block timestamp, in seconds
+    pub skew_secs: u64,
+    // Largest accepted difference between the host clock and the latest block timestamp, in seconds
+    pub max_clock_drift_secs: u64,
+    // How often the clock and the provider's sync status are checked in the background, in seconds
+    pub monitor_interval_secs: u64,
+    // Blocks a syncing provider may be behind head before no more validity windows are issued
+    pub max_blocks_behind: u64,
+}
+
+impl Default for ValidityConfig {
+    fn default() -> Self {
+        Self {
+            skew_secs: 0,
+            max_clock_drift_secs: 120,
+            monitor_interval_secs: 15,
+            max_blocks_behind: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProviderConfig {
+    // Per-call timeout for Ethereum RPC requests, in milliseconds
+    pub timeout_ms: u64,
+    // Consecutive failed calls that open the circuit
+    pub failure_threshold: u32,
+    // How long an open circuit rejects calls before a trial request, in seconds
+    pub cooldown_secs: u64,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 10_000,
+            failure_threshold: 5,
+            cooldown_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PolicyConfig {
+    // API keys whose requests are sponsored under this policy
+    pub api_keys: Vec<String>,
+    // Total max cost sponsored per UTC day
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub daily_budget: Option<U256>,
+    // Operations sponsored per UTC day
+    pub max_ops_per_day: Option<u64>,
+    // Unix timestamp after which the policy no longer sponsors
+    pub expires_at: Option<u64>,
+    // Cap on verificationGasLimit, meant to be tighter than the call gas cap: gas spent on an
+    // operation that then fails validation is paid for without anything to show for it
+    pub max_verification_gas_limit: Option<u64>,
+    // Tighter cap still for senders the EntryPoint has not yet run an operation for
+    pub new_sender_max_verification_gas_limit: Option<u64>,
+    // Calls the policy sponsors. When any are listed, every call the operation makes, each call
+    // of a batch included, must match one.
+    pub allowed_calls: Vec<CallRule>,
+    // Most native value the calls of one operation may send together, in ETH
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max_batch_value: Option<U256>,
+    // Most native value the policy's operations may send per UTC day, in ETH
+    #[serde(deserialize_with = "deserialize_ether")]
+    pub max_daily_value: Option<U256>,
+    // Caps on the ERC-20 `transfer` and `transferFrom` amounts of the policy's operations
+    pub token_limits: Vec<TokenLimit>,
+    // Keep sponsoring under this policy while a low deposit restricts the others
+    pub priority: bool,
+    // Signature aggregators the policy's accounts may use. Accounts using any other aggregator,
+    // as reported by simulation, are refused.
+    pub allowed_aggregators: Vec<Address>,
+    // Account factories, and the implementations behind them, whose deployments the policy
+    // sponsors. When any are listed, operations carrying initCode must match one.
+    pub allowed_accounts: Vec<AccountRule>,
+    // Dapp origins the policy sponsors. When any are listed, requests must come from one of them,
+    // proven as set under `[origins]`.
+    pub allowed_origins: Vec<String>,
+}
+
+// How a request proves it comes from a dapp origin: with one of the origin's API keys, or with a
+// signature by the origin's key over the operation
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OriginConfig {
+    pub api_keys: Vec<String>,
+    pub signer: Option<Address>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountRule {
+    pub factory: Address,
+    // Account implementation the factory deploys proxies of, as reported by its
+    // `accountImplementation()`; any when not set
+    pub implementation: Option<Address>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CallRule {
+    pub target: Address,
+    // Functions the call may use, as selectors or signatures; any function when empty
+    #[serde(default, deserialize_with = "deserialize_selectors")]
+    pub selectors: Vec<[u8; 4]>,
+    // Most native value a single call may send, in ETH
+    #[serde(default, deserialize_with = "deserialize_ether")]
+    pub max_value: Option<U256>,
+}
+
+// Amounts in the token's base units
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenLimit {
+    pub token: Address,
+    // Most one operation may move
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub max_per_op: Option<U256>,
+    // Most the policy's operations may move per UTC day
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub max_per_day: Option<U256>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ChainConfig {
+    // Paymaster contract used when `--paymaster-address` is not given
+    pub paymaster_address: Option<Address>,
+    // Outbound proxy for requests to this chain's Ethereum provider
+    pub proxy: Option<ProxyConfig>,
+    // ERC-20 tokens accepted for gas payment, keyed by symbol
+    pub tokens: HashMap<String, TokenConfig>,
+    // The paymaster contracts charge tokens in postOp; the bundled VerifyingPaymaster does not
+    pub token_paymaster: bool,
+    // Further funded paymaster contracts that sponsorships are spread across
+    pub shards: Vec<ShardConfig>,
+    // Replaces `fees.gas_price_buffer` on this chain
+    pub gas_price_buffer: Option<GasPriceBuffer>,
+    // ERC-4337 bundler polled for the receipts of sponsored operations
+    pub bundler_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShardConfig {
+    pub paymaster_address: Address,
+    // Signers for this paymaster's verifying key; the `[signing]` signers when empty
+    #[serde(default)]
+    pub signers: Vec<SignerConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenConfig {
+    pub address: Address,
+    // Chainlink-compatible feed pricing the token in the chain's native currency
+    pub price_feed: Address,
+    // Read from the token contract when not set
+    pub decimals: Option<u8>,
+    // Added on top of the gas cost converted at the feed price
+    #[serde(default)]
+    pub markup_percent: u64,
+    // Balance the sender must hold, in whole tokens such as "5"
+    pub min_balance: Option<String>,
+    // Oldest feed update still used for pricing, in seconds (default one day)
+    pub max_price_age_secs: Option<u64>,
+    // Smallest collected balance worth a sweep to the treasury, in whole tokens such as "100"
+    pub min_sweep: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProxyConfig {
+    // `http://`, `https://`, `socks5://` or `socks5h://` (DNS resolved by the proxy)
+    pub url: String,
+    pub username: Option<String>,
+    // Environment variable holding the proxy password
+    pub password_env: Option<String>,
+}
+
+impl ProxyConfig {
+    pub fn password(&self) -> Result<Option<String>> {
+        self.password_env
+            .as_ref()
+            .map(|name| std::env::var(name).with_context(|| format!("Environment variable {} is not set", name)))
+            .transpose()
+    }
+}
+
+// Parse an optional ETH amount such as "0.05" into wei
+fn deserialize_ether<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<U256>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|amount| parse_ether(&amount).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+// Parse an optional amount in base units, such as "1000000" for one USDC
+fn deserialize_amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<U256>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|amount| U256::from_dec_str(&amount).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+// Parse function selectors given as hex ("0xa9059cbb") or as signatures ("transfer(address,uint256)")
+fn deserialize_selectors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<[u8; 4]>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|selector| match selector.strip_prefix("0x") {
+            Some(digits) => hex::decode(digits)
+                .ok()
+                .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+                .ok_or_else(|| serde::de::Error::custom(format!("Invalid function selector {}", selector))),
+            None => Ok(id(&selector)),
+        })
+        .collect()
+}
+
+// Parse an optional gas price such as "1.5" gwei into wei
+fn deserialize_gwei<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<U256>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|amount| {
+            parse_units(&amount, "gwei")
+                .map(U256::from)
+                .map_err(serde::de::Error::custom)
+        })
+        .transpose()
+}
+
+impl Config {
+    pub fn chain(&self, chain_id: u64) -> ChainConfig {
+        self.chains.get(&chain_id.to_string()).cloned().unwrap_or_default()
+    }
+
+    // Fee settings with the chain's overrides applied
+    pub fn fees(&self, chain_id: u64) -> FeesConfig {
+        let mut fees = self.fees.clone();
+        if let Some(gas_price_buffer) = self.chain(chain_id).gas_price_buffer {
+            fees.gas_price_buffer = gas_price_buffer;
+        }
+        fees
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+
+        let config: Self = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        config.validate().with_context(|| format!("Invalid config file {}", path.display()))?;
+        Ok(config)
+    }
+
+    // Settings that parse but cannot be served
+    fn validate(&self) -> Result<()> {
+        // Projects own every ID with the separator, and replace whatever is served under theirs
+        if let Some(policy_id) = self.policies.keys().find(|policy_id| policy_id.contains(POLICY_SEPARATOR)) {
+            anyhow::bail!("Policy ID {} must not contain '{}'", policy_id, POLICY_SEPARATOR);
+        }
+        // Token mode signs terms that only a token-charging paymaster contract can verify
+        if let Some(chain) = self.chains.iter().find(|(_, chain)| !chain.tokens.is_empty() && !chain.token_paymaster).map(|(chain, _)| chain) {
+            anyhow::bail!("Chain {} accepts tokens but its paymaster is not declared a token paymaster (token_paymaster = true)", chain);
+        }
+        let buffers = self.chains.iter()
+            .filter_map(|(chain, config)| config.gas_price_buffer.map(|buffer| (format!("chains.{}.gas_price_buffer", chain), buffer)))
+            .chain([("fees.gas_price_buffer".to_string(), self.fees.gas_price_buffer)]);
+        for (name, buffer) in buffers {
+            match buffer {
+                GasPriceBuffer::BaseFeeMultiplier { multiplier } if !(multiplier.is_finite() && multiplier > 0.0) => {
+                    anyhow::bail!("{} multiplier must be a positive number, got {}", name, multiplier);
+                }
+                GasPriceBuffer::Percentile { percentile, .. } if !(0.0..=100.0).contains(&percentile) => {
+                    anyhow::bail!("{} percentile must be between 0 and 100, got {}", name, percentile);
+                }
+                _ => {}
+            }
+        }
+        if let Some(percentile) = self.fees.priority_fee_percentile.filter(|percentile| !(0.0..=100.0).contains(percentile)) {
+            anyhow::bail!("fees.priority_fee_percentile must be between 0 and 100, got {}", percentile);
+        }
+        // Only keys checked against the x-api-key header are bound to the sponsorship context;
+        // any other apiKey is the caller's unchecked claim
+        if let Some(api_key) = self
+            .maintenance
+            .api_keys
+            .iter()
+            .find(|api_key| !self.access.api_keys.iter().any(|access| &access.key == *api_key))
+        {
+            anyhow::bail!("Maintenance API key {} must also be listed under [[access.api_keys]]", api_key);
+        }
+        Ok(())
+    }
+
+    // Record a chain's paymaster address in the config file, keeping the rest of it untouched
+    pub fn write_paymaster_address(path: &Path, chain_id: u64, address: Address) -> Result<()> {
+        let contents = match path.exists() {
+            true => std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file {}", path.display()))?,
+            false => String::new(),
+        };
+        let mut document: toml_edit::DocumentMut = contents
+            .parse()
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+
+        let chains = document
+            .entry("chains")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .context("`chains` in the config file is not a table")?;
+        chains.set_implicit(true);
+
+        let chain = chains
+            .entry(&chain_id.to_string())
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .with_context(|| format!("`chains.{}` in the config file is not a table", chain_id))?;
+        chain.insert("paymaster_address", toml_edit::value(format!("{:?}", address)));
+
+        std::fs::write(path, document.to_string())
+            .with_context(|| format!("Failed to write config file {}", path.display()))
+    }
+}
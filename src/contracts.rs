@@ -0,0 +1,107 @@
+// src/contracts.rs
+use std::fmt;
+use std::str::FromStr;
+
+use ethers::prelude::abigen;
+use ethers::types::Address;
+
+// Canonical EntryPoint deployments (same address on all supported chains)
+pub const ENTRY_POINT_V06: &str = "0x5FF137D4b0FDCD49DcA30c7CF57E578a026d2789";
+pub const ENTRY_POINT_V07: &str = "0x0000000071727De22E5E9d8BAf0edAc6f37da032";
+pub const ENTRY_POINT_V08: &str = "0x4337084D9E255Ff0702461CF8895CE9E3b5Ff108";
+
+// EntryPoint release, which decides how user operations are hashed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntryPointVersion {
+    #[default]
+    V06,
+    V07,
+    V08,
+}
+
+impl EntryPointVersion {
+    // Version of a canonical deployment
+    pub fn of(entry_point: Address) -> Option<Self> {
+        [(ENTRY_POINT_V06, Self::V06), (ENTRY_POINT_V07, Self::V07), (ENTRY_POINT_V08, Self::V08)]
+            .into_iter()
+            .find(|(address, _)| address.parse::<Address>().ok() == Some(entry_point))
+            .map(|(_, version)| version)
+    }
+}
+
+impl fmt::Display for EntryPointVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::V06 => "0.6",
+            Self::V07 => "0.7",
+            Self::V08 => "0.8",
+        })
+    }
+}
+
+impl FromStr for EntryPointVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim_start_matches('v') {
+            "0.6" => Ok(Self::V06),
+            "0.7" => Ok(Self::V07),
+            "0.8" => Ok(Self::V08),
+            _ => Err(format!("unsupported EntryPoint version {}, expected 0.6, 0.7 or 0.8", s)),
+        }
+    }
+}
+
+abigen!(
+    EntryPoint,
+    r#"[
+        struct UserOperation { address sender; uint256 nonce; bytes initCode; bytes callData; uint256 callGasLimit; uint256 verificationGasLimit; uint256 preVerificationGas; uint256 maxFeePerGas; uint256 maxPriorityFeePerGas; bytes paymasterAndData; bytes signature; }
+        function balanceOf(address account) external view returns (uint256)
+        function depositTo(address account) external payable
+        function addStake(uint32 unstakeDelaySec) external payable
+        function getNonce(address sender, uint192 key) external view returns (uint256 nonce)
+        function simulateValidation(UserOperation calldata userOp) external
+        function getSenderAddress(bytes initCode) external
+        event UserOperationEvent(bytes32 indexed userOpHash, address indexed sender, address indexed paymaster, uint256 nonce, bool success, uint256 actualGasCost, uint256 actualGasUsed)
+    ]"#;
+
+    VerifyingPaymaster,
+    r#"[
+        constructor(address entryPoint, address verifyingSigner)
+        function getDeposit() external view returns (uint256)
+        function deposit() external payable
+        function addStake(uint32 unstakeDelaySec) external payable
+        function withdrawTo(address withdrawAddress, uint256 amount) external
+        function entryPoint() external view returns (address)
+        function verifyingSigner() external view returns (address)
+        function rotateVerifyingSigner(address newSigner, uint48 overlapUntil) external
+    ]"#;
+
+    // What a paymaster taking payment in ERC-20 tokens must add: postOp emits `TokenCharged` for
+    // what it took from the sender, and the owner sweeps what it collected with `withdrawToken`
+    TokenPaymaster,
+    r#"[
+        event TokenCharged(address indexed sender, address indexed token, uint256 nonce, uint256 amount)
+        function withdrawToken(address token, address to, uint256 amount) external
+    ]"#;
+
+    // Account factories following the reference SimpleAccountFactory
+    AccountFactory,
+    r#"[
+        function accountImplementation() external view returns (address)
+    ]"#;
+
+    ERC20,
+    r#"[
+        function balanceOf(address account) external view returns (uint256)
+        function allowance(address owner, address spender) external view returns (uint256)
+        function decimals() external view returns (uint8)
+    ]"#;
+
+    // Chainlink AggregatorV3Interface
+    PriceFeed,
+    r#"[
+        function decimals() external view returns (uint8)
+        function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+    ]"#;
+);
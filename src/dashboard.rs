@@ -0,0 +1,74 @@
+// src/dashboard.rs
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response};
+use tower::{Layer, Service};
+
+// Page served on GET /dashboard; it reads the JSON endpoints below
+const INDEX_HTML: &str = include_str!("../assets/dashboard.html");
+
+pub const PATH: &str = "/dashboard";
+// GET routes onto admin methods, so access lists guard them like the rest of the admin namespace
+pub const API_ROUTES: [(&str, &str); 2] = [
+    ("/dashboard/api/summary", "admin_getDashboard"),
+    ("/dashboard/api/sponsorships", "admin_getRecentSponsorships"),
+];
+
+// Serves the embedded dashboard page, when enabled, and passes every other request through
+#[derive(Debug, Clone, Copy)]
+pub struct DashboardLayer {
+    enabled: bool,
+}
+
+impl DashboardLayer {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl<S> Layer<S> for DashboardLayer {
+    type Service = Dashboard<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Dashboard { inner, enabled: self.enabled }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dashboard<S> {
+    inner: S,
+    enabled: bool,
+}
+
+impl<S> Service<Request<Body>> for Dashboard<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if self.enabled && request.method() == Method::GET && request.uri().path().trim_end_matches('/') == PATH {
+            let response = Response::builder()
+                .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                .header(CACHE_CONTROL, "no-cache")
+                .body(Body::from(INDEX_HTML))
+                .map_err(Into::into);
+            return Box::pin(async move { response });
+        }
+
+        let response = self.inner.call(request);
+        Box::pin(async move { response.await.map_err(Into::into) })
+    }
+}
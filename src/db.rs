@@ -0,0 +1,753 @@
+// src/db.rs
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use ethers::types::{Address, Bytes, H256, U256};
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgPool;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use tracing::info;
+
+use crate::audit::AuditEntry;
+use crate::error::PaymasterError;
+use crate::project::Project;
+use crate::store::{DailyUsage, OperationState, OperationStatus, Reservation, SponsorshipRecord, TokenDebt, Transfer, UsageCheck};
+
+// Migrations are embedded at build time, one directory per SQL dialect
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+// Run the same statement against whichever backend is connected
+macro_rules! execute {
+    ($db:expr, $sql:expr $(, $bind:expr)* $(,)?) => {
+        match $db {
+            Database::Sqlite(pool) => sqlx::query($sql)$(.bind($bind))*.execute(pool).await.map(|_| ()),
+            Database::Postgres(pool) => sqlx::query($sql)$(.bind($bind))*.execute(pool).await.map(|_| ()),
+        }
+    };
+}
+
+// Run the block in a transaction on whichever backend is connected. The block commits
+// explicitly; the transaction is rolled back if it returns without doing so.
+macro_rules! transaction {
+    ($db:expr, |$tx:ident| $body:block) => {
+        match $db {
+            Database::Sqlite(pool) => {
+                let mut $tx = pool.begin().await?;
+                $body
+            }
+            Database::Postgres(pool) => {
+                let mut $tx = pool.begin().await?;
+                $body
+            }
+        }
+    };
+}
+
+// Remove an operation's reservation inside a transaction, returning it
+macro_rules! take_reservation {
+    ($tx:ident, $chain_id:expr, $sender:expr, $nonce:expr) => {
+        sqlx::query_as::<_, (String, i64, String, i64)>(
+            "DELETE FROM reservations WHERE chain_id = $1 AND sender = $2 AND nonce = $3 \
+             RETURNING policy_id, day, amount_wei, valid_until",
+        )
+        .bind($chain_id as i64)
+        .bind(format!("{:?}", $sender))
+        .bind($nonce.to_string())
+        .fetch_optional(&mut *$tx)
+        .await?
+        .map(reservation_from_row)
+        .transpose()?
+    };
+}
+
+// Apply `$update` to a policy's usage for a day inside a transaction, evaluating to its result.
+// The row is written before it is read, which locks it in Postgres and takes the write lock in
+// SQLite, so concurrent reservations cannot both pass the budget check.
+macro_rules! update_usage {
+    ($tx:ident, $chain_id:expr, $policy_id:expr, $day:expr, |$usage:ident| $update:expr) => {{
+        sqlx::query(
+            "INSERT INTO policy_usage (chain_id, policy_id, day, spent_wei, reserved_wei, ops) \
+             VALUES ($1, $2, $3, '0', '0', 0) ON CONFLICT (chain_id, policy_id, day) DO UPDATE SET ops = policy_usage.ops",
+        )
+        .bind($chain_id as i64)
+        .bind($policy_id)
+        .bind($day as i64)
+        .execute(&mut *$tx)
+        .await?;
+
+        let row = sqlx::query_as::<_, (String, String, i64)>(
+            "SELECT spent_wei, reserved_wei, ops FROM policy_usage WHERE chain_id = $1 AND policy_id = $2 AND day = $3",
+        )
+        .bind($chain_id as i64)
+        .bind($policy_id)
+        .bind($day as i64)
+        .fetch_one(&mut *$tx)
+        .await?;
+        let mut $usage = usage_from_row(row)?;
+        let result = $update;
+
+        sqlx::query(
+            "UPDATE policy_usage SET spent_wei = $4, reserved_wei = $5, ops = $6 \
+             WHERE chain_id = $1 AND policy_id = $2 AND day = $3",
+        )
+        .bind($chain_id as i64)
+        .bind($policy_id)
+        .bind($day as i64)
+        .bind($usage.spent.to_string())
+        .bind($usage.reserved.to_string())
+        .bind($usage.ops as i64)
+        .execute(&mut *$tx)
+        .await?;
+        result
+    }};
+}
+
+// Apply `$update` to what a policy moved of an asset on a day inside a transaction, evaluating to
+// its result. The row is written before it is read to lock it, as in `update_usage!`.
+macro_rules! update_transfers {
+    ($tx:ident, $chain_id:expr, $policy_id:expr, $day:expr, $asset:expr, |$transferred:ident| $update:expr) => {{
+        sqlx::query(
+            "INSERT INTO transfer_usage (chain_id, policy_id, day, asset, amount) VALUES ($1, $2, $3, $4, '0') \
+             ON CONFLICT (chain_id, policy_id, day, asset) DO UPDATE SET amount = transfer_usage.amount",
+        )
+        .bind($chain_id as i64)
+        .bind($policy_id)
+        .bind($day as i64)
+        .bind($asset)
+        .execute(&mut *$tx)
+        .await?;
+
+        let amount = sqlx::query_scalar::<_, String>(
+            "SELECT amount FROM transfer_usage WHERE chain_id = $1 AND policy_id = $2 AND day = $3 AND asset = $4",
+        )
+        .bind($chain_id as i64)
+        .bind($policy_id)
+        .bind($day as i64)
+        .bind($asset)
+        .fetch_one(&mut *$tx)
+        .await?;
+        let mut $transferred = parse_decimal(&amount)?;
+        let result = $update;
+
+        sqlx::query("UPDATE transfer_usage SET amount = $5 WHERE chain_id = $1 AND policy_id = $2 AND day = $3 AND asset = $4")
+            .bind($chain_id as i64)
+            .bind($policy_id)
+            .bind($day as i64)
+            .bind($asset)
+            .bind($transferred.to_string())
+            .execute(&mut *$tx)
+            .await?;
+        result
+    }};
+}
+
+#[derive(Clone)]
+pub enum Database {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+impl Database {
+    // Connect to the database, selecting the backend from the URL scheme
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        if database_url.starts_with("sqlite:") {
+            let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+            Ok(Self::Sqlite(SqlitePool::connect_with(options).await?))
+        } else if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+            Ok(Self::Postgres(PgPool::connect(database_url).await?))
+        } else {
+            bail!("Unsupported database URL: {}", database_url)
+        }
+    }
+
+    // Apply any pending schema migrations
+    pub async fn migrate(&self) -> Result<()> {
+        match self {
+            Self::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await?,
+            Self::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await?,
+        }
+
+        info!("Database schema is up to date");
+        Ok(())
+    }
+
+    // Wait for connections in use to be returned, then close the pool
+    pub async fn close(&self) {
+        match self {
+            Self::Sqlite(pool) => pool.close().await,
+            Self::Postgres(pool) => pool.close().await,
+        }
+    }
+
+    // Append an entry to its chain's audit log. `seal` is given the HMAC the chain ends with and
+    // fills in the entry's own; the chain head is locked until the entry is in, so writers sharing
+    // the database extend each chain one at a time.
+    pub async fn append_audit_entry(
+        &self,
+        mut entry: AuditEntry,
+        seal: impl FnOnce(Option<&str>, &mut AuditEntry) -> Result<(), PaymasterError>,
+    ) -> Result<AuditEntry> {
+        transaction!(self, |tx| {
+            sqlx::query(
+                "INSERT INTO audit_chains (chain_id, hmac) VALUES ($1, NULL) \
+                 ON CONFLICT (chain_id) DO UPDATE SET hmac = audit_chains.hmac",
+            )
+            .bind(entry.chain_id as i64)
+            .execute(&mut *tx)
+            .await?;
+
+            let previous = sqlx::query_scalar::<_, Option<String>>("SELECT hmac FROM audit_chains WHERE chain_id = $1")
+                .bind(entry.chain_id as i64)
+                .fetch_one(&mut *tx)
+                .await?;
+            seal(previous.as_deref(), &mut entry)?;
+
+            sqlx::query(
+                "INSERT INTO audit_log (created_at, chain_id, user_op_hash, sender, policy_id, decision, reason, signer, signature_hash, entry, hmac) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            )
+            .bind(entry.timestamp as i64)
+            .bind(entry.chain_id as i64)
+            .bind(format!("{:?}", entry.user_op_hash))
+            .bind(format!("{:?}", entry.user_op.sender))
+            .bind(entry.policy_id.clone())
+            .bind(entry.decision.as_str())
+            .bind(entry.reason.clone())
+            .bind(entry.signer.map(|signer| format!("{:?}", signer)))
+            .bind(entry.signature_hash.map(|hash| format!("{:?}", hash)))
+            .bind(serde_json::to_string(&entry)?)
+            .bind(entry.hmac.clone())
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query("UPDATE audit_chains SET hmac = $2 WHERE chain_id = $1")
+                .bind(entry.chain_id as i64)
+                .bind(entry.hmac.clone())
+                .execute(&mut *tx)
+                .await?;
+
+            tx.commit().await?;
+            Ok(entry)
+        })
+    }
+
+    pub async fn audit_entries(&self, since: u64) -> Result<Vec<AuditEntry>> {
+        const QUERY: &str = "SELECT entry FROM audit_log WHERE created_at >= $1 ORDER BY id";
+
+        let rows: Vec<String> = match self {
+            Self::Sqlite(pool) => sqlx::query_scalar(QUERY).bind(since as i64).fetch_all(pool).await?,
+            Self::Postgres(pool) => sqlx::query_scalar(QUERY).bind(since as i64).fetch_all(pool).await?,
+        };
+
+        rows.iter()
+            .map(|row| serde_json::from_str(row).map_err(Into::into))
+            .collect()
+    }
+
+    pub async fn upsert_sponsorship(&self, record: &SponsorshipRecord) -> Result<()> {
+        execute!(
+            self,
+            "INSERT INTO sponsorships (user_op_hash, chain_id, sender, nonce, policy_id, max_cost, valid_after, valid_until, paymaster_and_data, created_at, aggregator) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) \
+             ON CONFLICT (user_op_hash) DO UPDATE SET policy_id = excluded.policy_id, max_cost = excluded.max_cost, \
+             valid_after = excluded.valid_after, valid_until = excluded.valid_until, \
+             paymaster_and_data = excluded.paymaster_and_data, created_at = excluded.created_at, aggregator = excluded.aggregator",
+            format!("{:?}", record.user_op_hash),
+            record.chain_id as i64,
+            format!("{:?}", record.sender),
+            record.nonce.to_string(),
+            record.policy_id.clone(),
+            record.max_cost.to_string(),
+            record.valid_after as i64,
+            record.valid_until as i64,
+            record.paymaster_and_data.to_string(),
+            record.created_at as i64,
+            record.aggregator.map(|aggregator| format!("{:?}", aggregator)),
+        )?;
+
+        Ok(())
+    }
+
+    pub async fn sponsorship(&self, user_op_hash: H256) -> Result<Option<SponsorshipRecord>> {
+        const QUERY: &str = "SELECT user_op_hash, chain_id, sender, nonce, policy_id, max_cost, valid_after, valid_until, paymaster_and_data, created_at, aggregator \
+                             FROM sponsorships WHERE user_op_hash = $1";
+        type Row = (String, i64, String, String, Option<String>, String, i64, i64, String, i64, Option<String>);
+
+        let row: Option<Row> = match self {
+            Self::Sqlite(pool) => sqlx::query_as(QUERY).bind(format!("{:?}", user_op_hash)).fetch_optional(pool).await?,
+            Self::Postgres(pool) => sqlx::query_as(QUERY).bind(format!("{:?}", user_op_hash)).fetch_optional(pool).await?,
+        };
+
+        row.map(|(user_op_hash, chain_id, sender, nonce, policy_id, max_cost, valid_after, valid_until, paymaster_and_data, created_at, aggregator)| {
+            Ok(SponsorshipRecord {
+                user_op_hash: user_op_hash.parse()?,
+                chain_id: chain_id as u64,
+                sender: sender.parse()?,
+                nonce: parse_decimal(&nonce)?,
+                policy_id,
+                max_cost: parse_decimal(&max_cost)?,
+                valid_after: valid_after as u64,
+                valid_until: valid_until as u64,
+                paymaster_and_data: paymaster_and_data.parse::<Bytes>()?,
+                created_at: created_at as u64,
+                aggregator: aggregator.map(|aggregator| aggregator.parse()).transpose()?,
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn policy_usage(&self, chain_id: u64, policy_id: &str, day: u64) -> Result<DailyUsage> {
+        const QUERY: &str = "SELECT spent_wei, reserved_wei, ops FROM policy_usage WHERE chain_id = $1 AND policy_id = $2 AND day = $3";
+
+        let row: Option<(String, String, i64)> = match self {
+            Self::Sqlite(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(policy_id).bind(day as i64).fetch_optional(pool).await?,
+            Self::Postgres(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(policy_id).bind(day as i64).fetch_optional(pool).await?,
+        };
+
+        Ok(row.map(usage_from_row).transpose()?.unwrap_or_default())
+    }
+
+    // Hold budget for an operation, replacing its earlier reservation. The outer error is a
+    // database failure, the inner one the budget check turning the reservation down.
+    pub async fn reserve(
+        &self,
+        chain_id: u64,
+        sender: Address,
+        nonce: U256,
+        reservation: &Reservation,
+        check: &UsageCheck<'_>,
+    ) -> Result<Result<(), PaymasterError>> {
+        transaction!(self, |tx| {
+            if let Some(previous) = take_reservation!(tx, chain_id, sender, nonce) {
+                update_usage!(tx, chain_id, &previous.policy_id, previous.day, |usage| usage.unreserve(previous.amount, true));
+            }
+
+            let checked = update_usage!(tx, chain_id, &reservation.policy_id, reservation.day, |usage| {
+                check(&usage).map(|()| usage.reserve(reservation.amount))
+            });
+            if checked.is_err() {
+                return Ok(checked);
+            }
+
+            sqlx::query(
+                "INSERT INTO reservations (chain_id, sender, nonce, policy_id, day, amount_wei, valid_until) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(chain_id as i64)
+            .bind(format!("{:?}", sender))
+            .bind(nonce.to_string())
+            .bind(&reservation.policy_id)
+            .bind(reservation.day as i64)
+            .bind(reservation.amount.to_string())
+            .bind(reservation.valid_until as i64)
+            .execute(&mut *tx)
+            .await?;
+
+            tx.commit().await?;
+            Ok(Ok(()))
+        })
+    }
+
+    pub async fn release_reservation(&self, chain_id: u64, sender: Address, nonce: U256) -> Result<()> {
+        transaction!(self, |tx| {
+            if let Some(reservation) = take_reservation!(tx, chain_id, sender, nonce) {
+                update_usage!(tx, chain_id, &reservation.policy_id, reservation.day, |usage| usage.unreserve(reservation.amount, true));
+            }
+            tx.commit().await?;
+            Ok(())
+        })
+    }
+
+    // Charge an included operation's actual cost in place of its reservation
+    pub async fn settle_reservation(&self, chain_id: u64, sender: Address, nonce: U256, actual_cost: U256) -> Result<Option<String>> {
+        transaction!(self, |tx| {
+            let Some(reservation) = take_reservation!(tx, chain_id, sender, nonce) else {
+                return Ok(None);
+            };
+            update_usage!(tx, chain_id, &reservation.policy_id, reservation.day, |usage| {
+                usage.unreserve(reservation.amount, false);
+                usage.spent = usage.spent.saturating_add(actual_cost);
+            });
+            tx.commit().await?;
+            Ok(Some(reservation.policy_id))
+        })
+    }
+
+    pub async fn release_expired_reservations(&self, chain_id: u64, block_timestamp: u64) -> Result<usize> {
+        transaction!(self, |tx| {
+            let rows = sqlx::query_as::<_, (String, i64, String, i64)>(
+                "DELETE FROM reservations WHERE chain_id = $1 AND valid_until < $2 \
+                 RETURNING policy_id, day, amount_wei, valid_until",
+            )
+            .bind(chain_id as i64)
+            .bind(block_timestamp as i64)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            let released = rows.len();
+            for row in rows {
+                let reservation = reservation_from_row(row)?;
+                update_usage!(tx, chain_id, &reservation.policy_id, reservation.day, |usage| usage.unreserve(reservation.amount, true));
+            }
+            tx.commit().await?;
+            Ok(released)
+        })
+    }
+
+    // Returns false if the key was already in the set
+    pub async fn insert_replay_key(&self, chain_id: u64, set: &str, key: &str, expires_at: u64) -> Result<bool> {
+        const QUERY: &str = "INSERT INTO replay_keys (chain_id, set_name, replay_key, expires_at) VALUES ($1, $2, $3, $4) \
+                             ON CONFLICT (chain_id, set_name, replay_key) DO NOTHING";
+
+        let inserted = match self {
+            Self::Sqlite(pool) => sqlx::query(QUERY)
+                .bind(chain_id as i64).bind(set).bind(key).bind(expires_at as i64)
+                .execute(pool).await?.rows_affected(),
+            Self::Postgres(pool) => sqlx::query(QUERY)
+                .bind(chain_id as i64).bind(set).bind(key).bind(expires_at as i64)
+                .execute(pool).await?.rows_affected(),
+        };
+
+        Ok(inserted > 0)
+    }
+
+    pub async fn contains_replay_key(&self, chain_id: u64, set: &str, key: &str) -> Result<bool> {
+        const QUERY: &str = "SELECT expires_at FROM replay_keys WHERE chain_id = $1 AND set_name = $2 AND replay_key = $3";
+
+        let row: Option<i64> = match self {
+            Self::Sqlite(pool) => sqlx::query_scalar(QUERY).bind(chain_id as i64).bind(set).bind(key).fetch_optional(pool).await?,
+            Self::Postgres(pool) => sqlx::query_scalar(QUERY).bind(chain_id as i64).bind(set).bind(key).fetch_optional(pool).await?,
+        };
+
+        Ok(row.is_some())
+    }
+
+    pub async fn prune_replay_keys(&self, chain_id: u64, now: u64) -> Result<()> {
+        execute!(
+            self,
+            "DELETE FROM replay_keys WHERE chain_id = $1 AND expires_at < $2",
+            chain_id as i64,
+            now as i64,
+        )?;
+
+        Ok(())
+    }
+
+    pub async fn transferred(&self, chain_id: u64, policy_id: &str, day: u64, asset: Address) -> Result<U256> {
+        const QUERY: &str = "SELECT amount FROM transfer_usage WHERE chain_id = $1 AND policy_id = $2 AND day = $3 AND asset = $4";
+
+        let row: Option<String> = match self {
+            Self::Sqlite(pool) => sqlx::query_scalar(QUERY)
+                .bind(chain_id as i64).bind(policy_id).bind(day as i64).bind(format!("{:?}", asset))
+                .fetch_optional(pool).await?,
+            Self::Postgres(pool) => sqlx::query_scalar(QUERY)
+                .bind(chain_id as i64).bind(policy_id).bind(day as i64).bind(format!("{:?}", asset))
+                .fetch_optional(pool).await?,
+        };
+
+        row.map(|amount| parse_decimal(&amount)).transpose().map(Option::unwrap_or_default)
+    }
+
+    // Add an operation's transfers to the day's totals, all or none, replacing what an earlier
+    // signature of the same sender and nonce was counted for. The outer error is a database
+    // failure, the inner one a transfer going over its daily limit.
+    pub async fn record_transfers(
+        &self,
+        chain_id: u64,
+        sender: Address,
+        nonce: U256,
+        policy_id: &str,
+        day: u64,
+        transfers: &[Transfer],
+    ) -> Result<Result<(), PaymasterError>> {
+        transaction!(self, |tx| {
+            let previous = sqlx::query_as::<_, (String, i64, String, String)>(
+                "DELETE FROM operation_transfers WHERE chain_id = $1 AND sender = $2 AND nonce = $3 \
+                 RETURNING policy_id, day, asset, amount",
+            )
+            .bind(chain_id as i64)
+            .bind(format!("{:?}", sender))
+            .bind(nonce.to_string())
+            .fetch_all(&mut *tx)
+            .await?;
+            for (previous_policy_id, previous_day, asset, amount) in previous {
+                let amount = parse_decimal(&amount)?;
+                update_transfers!(tx, chain_id, &previous_policy_id, previous_day, &asset, |transferred| {
+                    transferred = transferred.saturating_sub(amount)
+                });
+            }
+
+            for transfer in transfers {
+                let asset = format!("{:?}", transfer.asset);
+                let checked = update_transfers!(tx, chain_id, policy_id, day, &asset, |transferred| {
+                    transfer.check(policy_id, transferred).map(|_| transferred = transferred.saturating_add(transfer.amount))
+                });
+                if let Err(e) = checked {
+                    return Ok(Err(e));
+                }
+
+                sqlx::query(
+                    "INSERT INTO operation_transfers (chain_id, sender, nonce, policy_id, day, asset, amount) \
+                     VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                )
+                .bind(chain_id as i64)
+                .bind(format!("{:?}", sender))
+                .bind(nonce.to_string())
+                .bind(policy_id)
+                .bind(day as i64)
+                .bind(&asset)
+                .bind(transfer.amount.to_string())
+                .execute(&mut *tx)
+                .await?;
+            }
+
+            tx.commit().await?;
+            Ok(Ok(()))
+        })
+    }
+
+    // Operations are only replaced on the day they were counted
+    pub async fn prune_operation_transfers(&self, chain_id: u64, day: u64) -> Result<()> {
+        execute!(
+            self,
+            "DELETE FROM operation_transfers WHERE chain_id = $1 AND day < $2",
+            chain_id as i64,
+            day as i64,
+        )?;
+
+        Ok(())
+    }
+
+    pub async fn upsert_token_debt(&self, chain_id: u64, debt: &TokenDebt) -> Result<()> {
+        execute!(
+            self,
+            "INSERT INTO token_debts (chain_id, sender, nonce, token, exchange_rate, max_token_cost, owed, collected, user_op_hash, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+             ON CONFLICT (chain_id, sender, nonce) DO UPDATE SET token = excluded.token, exchange_rate = excluded.exchange_rate, \
+             max_token_cost = excluded.max_token_cost, owed = excluded.owed, collected = excluded.collected, \
+             user_op_hash = excluded.user_op_hash, created_at = excluded.created_at",
+            chain_id as i64,
+            format!("{:?}", debt.sender),
+            debt.nonce.to_string(),
+            format!("{:?}", debt.token),
+            debt.exchange_rate.to_string(),
+            debt.max_token_cost.to_string(),
+            debt.owed.map(|owed| owed.to_string()),
+            debt.collected.map(|collected| collected.to_string()),
+            debt.user_op_hash.map(|hash| format!("{:?}", hash)),
+            debt.created_at as i64,
+        )?;
+
+        Ok(())
+    }
+
+    pub async fn token_debt(&self, chain_id: u64, sender: Address, nonce: U256) -> Result<Option<TokenDebt>> {
+        const QUERY: &str = "SELECT sender, nonce, token, exchange_rate, max_token_cost, owed, collected, user_op_hash, created_at \
+                             FROM token_debts WHERE chain_id = $1 AND sender = $2 AND nonce = $3";
+
+        let row: Option<TokenDebtRow> = match self {
+            Self::Sqlite(pool) => sqlx::query_as(QUERY)
+                .bind(chain_id as i64).bind(format!("{:?}", sender)).bind(nonce.to_string())
+                .fetch_optional(pool).await?,
+            Self::Postgres(pool) => sqlx::query_as(QUERY)
+                .bind(chain_id as i64).bind(format!("{:?}", sender)).bind(nonce.to_string())
+                .fetch_optional(pool).await?,
+        };
+
+        row.map(token_debt_from_row).transpose()
+    }
+
+    pub async fn token_debts(&self, chain_id: u64, since: u64) -> Result<Vec<TokenDebt>> {
+        const QUERY: &str = "SELECT sender, nonce, token, exchange_rate, max_token_cost, owed, collected, user_op_hash, created_at \
+                             FROM token_debts WHERE chain_id = $1 AND created_at >= $2 ORDER BY created_at";
+
+        let rows: Vec<TokenDebtRow> = match self {
+            Self::Sqlite(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(since as i64).fetch_all(pool).await?,
+            Self::Postgres(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(since as i64).fetch_all(pool).await?,
+        };
+
+        rows.into_iter().map(token_debt_from_row).collect()
+    }
+
+    pub async fn upsert_operation_status(&self, chain_id: u64, status: &OperationStatus) -> Result<()> {
+        execute!(
+            self,
+            "INSERT INTO operation_statuses (chain_id, user_op_hash, sender, nonce, status, valid_until, transaction_hash, \
+             block_number, actual_gas_cost, actual_gas_used, reason, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) \
+             ON CONFLICT (chain_id, user_op_hash) DO UPDATE SET status = excluded.status, valid_until = excluded.valid_until, \
+             transaction_hash = excluded.transaction_hash, block_number = excluded.block_number, \
+             actual_gas_cost = excluded.actual_gas_cost, actual_gas_used = excluded.actual_gas_used, reason = excluded.reason",
+            chain_id as i64,
+            format!("{:?}", status.user_op_hash),
+            format!("{:?}", status.sender),
+            status.nonce.to_string(),
+            status.status.as_str(),
+            status.valid_until as i64,
+            status.transaction_hash.map(|hash| format!("{:?}", hash)),
+            status.block_number.map(|block_number| block_number as i64),
+            status.actual_gas_cost.map(|cost| cost.to_string()),
+            status.actual_gas_used.map(|gas| gas.to_string()),
+            status.reason.clone(),
+            status.created_at as i64,
+        )?;
+
+        Ok(())
+    }
+
+    pub async fn operation_status(&self, chain_id: u64, user_op_hash: H256) -> Result<Option<OperationStatus>> {
+        const QUERY: &str = "SELECT user_op_hash, sender, nonce, status, valid_until, transaction_hash, block_number, \
+                             actual_gas_cost, actual_gas_used, reason, created_at \
+                             FROM operation_statuses WHERE chain_id = $1 AND user_op_hash = $2";
+
+        let row: Option<OperationStatusRow> = match self {
+            Self::Sqlite(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(format!("{:?}", user_op_hash)).fetch_optional(pool).await?,
+            Self::Postgres(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(format!("{:?}", user_op_hash)).fetch_optional(pool).await?,
+        };
+
+        row.map(operation_status_from_row).transpose()
+    }
+
+    pub async fn pending_operations(&self, chain_id: u64, valid_from: u64) -> Result<Vec<OperationStatus>> {
+        const QUERY: &str = "SELECT user_op_hash, sender, nonce, status, valid_until, transaction_hash, block_number, \
+                             actual_gas_cost, actual_gas_used, reason, created_at \
+                             FROM operation_statuses WHERE chain_id = $1 AND status = 'pending' AND valid_until >= $2 \
+                             ORDER BY created_at";
+
+        let rows: Vec<OperationStatusRow> = match self {
+            Self::Sqlite(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(valid_from as i64).fetch_all(pool).await?,
+            Self::Postgres(pool) => sqlx::query_as(QUERY).bind(chain_id as i64).bind(valid_from as i64).fetch_all(pool).await?,
+        };
+
+        rows.into_iter().map(operation_status_from_row).collect()
+    }
+
+    pub async fn upsert_project(&self, project: &Project) -> Result<()> {
+        execute!(
+            self,
+            "INSERT INTO projects (id, project, updated_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (id) DO UPDATE SET project = excluded.project, updated_at = excluded.updated_at",
+            project.id.clone(),
+            serde_json::to_string(project)?,
+            project.updated_at as i64,
+        )?;
+
+        Ok(())
+    }
+
+    // Returns false if there was no project with the ID
+    pub async fn delete_project(&self, project_id: &str) -> Result<bool> {
+        const QUERY: &str = "DELETE FROM projects WHERE id = $1";
+
+        let deleted = match self {
+            Self::Sqlite(pool) => sqlx::query(QUERY).bind(project_id).execute(pool).await?.rows_affected(),
+            Self::Postgres(pool) => sqlx::query(QUERY).bind(project_id).execute(pool).await?.rows_affected(),
+        };
+
+        Ok(deleted > 0)
+    }
+
+    pub async fn projects(&self) -> Result<Vec<Project>> {
+        const QUERY: &str = "SELECT project FROM projects ORDER BY id";
+
+        let rows: Vec<String> = match self {
+            Self::Sqlite(pool) => sqlx::query_scalar(QUERY).fetch_all(pool).await?,
+            Self::Postgres(pool) => sqlx::query_scalar(QUERY).fetch_all(pool).await?,
+        };
+
+        rows.iter()
+            .map(|row| serde_json::from_str(row).map_err(Into::into))
+            .collect()
+    }
+
+    pub async fn scanned_block(&self, chain_id: u64) -> Result<Option<u64>> {
+        const QUERY: &str = "SELECT block_number FROM scanned_blocks WHERE chain_id = $1";
+
+        let block_number: Option<i64> = match self {
+            Self::Sqlite(pool) => sqlx::query_scalar(QUERY).bind(chain_id as i64).fetch_optional(pool).await?,
+            Self::Postgres(pool) => sqlx::query_scalar(QUERY).bind(chain_id as i64).fetch_optional(pool).await?,
+        };
+        Ok(block_number.map(|block_number| block_number as u64))
+    }
+
+    // Only moves forward, as instances sharing the database may be scanning behind one another
+    pub async fn record_scanned_block(&self, chain_id: u64, block_number: u64) -> Result<()> {
+        execute!(
+            self,
+            "INSERT INTO scanned_blocks (chain_id, block_number) VALUES ($1, $2) \
+             ON CONFLICT (chain_id) DO UPDATE SET block_number = excluded.block_number \
+             WHERE excluded.block_number > scanned_blocks.block_number",
+            chain_id as i64,
+            block_number as i64,
+        )?;
+
+        Ok(())
+    }
+}
+
+type TokenDebtRow = (String, String, String, String, String, Option<String>, Option<String>, Option<String>, i64);
+
+type OperationStatusRow =
+    (String, String, String, String, i64, Option<String>, Option<i64>, Option<String>, Option<String>, Option<String>, i64);
+
+fn parse_decimal(value: &str) -> Result<U256> {
+    U256::from_dec_str(value).with_context(|| format!("Invalid amount {} in the database", value))
+}
+
+fn usage_from_row((spent, reserved, ops): (String, String, i64)) -> Result<DailyUsage> {
+    Ok(DailyUsage {
+        spent: parse_decimal(&spent)?,
+        reserved: parse_decimal(&reserved)?,
+        ops: ops as u64,
+    })
+}
+
+fn reservation_from_row((policy_id, day, amount, valid_until): (String, i64, String, i64)) -> Result<Reservation> {
+    Ok(Reservation {
+        policy_id,
+        day: day as u64,
+        amount: parse_decimal(&amount)?,
+        valid_until: valid_until as u64,
+    })
+}
+
+fn token_debt_from_row(
+    (sender, nonce, token, exchange_rate, max_token_cost, owed, collected, user_op_hash, created_at): TokenDebtRow,
+) -> Result<TokenDebt> {
+    Ok(TokenDebt {
+        sender: sender.parse()?,
+        nonce: parse_decimal(&nonce)?,
+        token: token.parse()?,
+        exchange_rate: parse_decimal(&exchange_rate)?,
+        max_token_cost: parse_decimal(&max_token_cost)?,
+        owed: owed.as_deref().map(parse_decimal).transpose()?,
+        collected: collected.as_deref().map(parse_decimal).transpose()?,
+        user_op_hash: user_op_hash.map(|hash| hash.parse()).transpose()?,
+        created_at: created_at as u64,
+    })
+}
+
+fn operation_status_from_row(
+    (user_op_hash, sender, nonce, status, valid_until, transaction_hash, block_number, actual_gas_cost, actual_gas_used, reason, created_at): OperationStatusRow,
+) -> Result<OperationStatus> {
+    let status = match status.as_str() {
+        "pending" => OperationState::Pending,
+        "included" => OperationState::Included,
+        "failed" => OperationState::Failed,
+        _ => bail!("Invalid operation status {} in the database", status),
+    };
+    Ok(OperationStatus {
+        user_op_hash: user_op_hash.parse()?,
+        sender: sender.parse()?,
+        nonce: parse_decimal(&nonce)?,
+        status,
+        valid_until: valid_until as u64,
+        transaction_hash: transaction_hash.map(|hash| hash.parse()).transpose()?,
+        block_number: block_number.map(|block_number| block_number as u64),
+        actual_gas_cost: actual_gas_cost.as_deref().map(parse_decimal).transpose()?,
+        actual_gas_used: actual_gas_used.as_deref().map(parse_decimal).transpose()?,
+        reason,
+        created_at: created_at as u64,
+    })
+}
@@ -0,0 +1,116 @@
+// src/deadline.rs
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use hyper::header::{CONTENT_TYPE, UPGRADE};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::json;
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::metrics::Metrics;
+
+// Milliseconds the client is willing to wait for the response
+pub const TIMEOUT_HEADER: &str = "x-request-timeout-ms";
+
+// JSON-RPC error code for requests abandoned at their deadline
+pub const DEADLINE_EXCEEDED_CODE: i32 = -32005;
+
+// The deadline for a request: what the client asked for, capped at `max`, or `max` itself
+pub fn timeout(requested_ms: Option<u64>, max: Option<Duration>) -> Option<Duration> {
+    match (requested_ms.map(Duration::from_millis), max) {
+        (Some(requested), Some(max)) => Some(requested.min(max)),
+        (requested, max) => requested.or(max),
+    }
+}
+
+// Abandons HTTP requests that run past their deadline. Dropping the request's future cancels the
+// provider calls, simulation and signing it is waiting on. WebSocket connections outlive any single
+// call, so they are passed through and bounded per call instead.
+#[derive(Debug, Clone)]
+pub struct DeadlineLayer {
+    max: Option<Duration>,
+    metrics: Arc<Metrics>,
+}
+
+impl DeadlineLayer {
+    pub fn new(max: Option<Duration>, metrics: Arc<Metrics>) -> Self {
+        Self { max, metrics }
+    }
+}
+
+impl<S> Layer<S> for DeadlineLayer {
+    type Service = Deadline<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Deadline { inner, layer: self.clone() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Deadline<S> {
+    inner: S,
+    layer: DeadlineLayer,
+}
+
+impl<S> Service<Request<Body>> for Deadline<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let requested_ms = request
+            .headers()
+            .get(TIMEOUT_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        let timeout = timeout(requested_ms, self.layer.max).filter(|_| !request.headers().contains_key(UPGRADE));
+
+        let response = self.inner.call(request);
+        let Some(timeout) = timeout else {
+            return Box::pin(async move { response.await.map_err(Into::into) });
+        };
+
+        let metrics = self.layer.metrics.clone();
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, response).await {
+                Ok(response) => response.map_err(Into::into),
+                Err(_) => {
+                    debug!("Abandoned a request after its {}ms deadline", timeout.as_millis());
+                    Metrics::increment(&metrics.request_timeouts);
+                    deadline_exceeded(timeout)
+                }
+            }
+        })
+    }
+}
+
+fn deadline_exceeded(timeout: Duration) -> Result<Response<Body>, Box<dyn Error + Send + Sync>> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": {
+            "code": DEADLINE_EXCEEDED_CODE,
+            "message": format!("Request did not finish within {}ms", timeout.as_millis()),
+        },
+    });
+
+    Response::builder()
+        .status(StatusCode::GATEWAY_TIMEOUT)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(Into::into)
+}
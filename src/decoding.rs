@@ -0,0 +1,180 @@
+// src/decoding.rs
+use ethers::types::Address;
+
+use crate::calldata;
+use crate::config::LimitsConfig;
+use crate::error::PaymasterError;
+use crate::types::UserOperation;
+
+const ABI_WORD: usize = 32;
+
+// Strict checks on the operation's untrusted byte fields. They run before the operation is hashed
+// or decoded, so payloads crafted to cost allocations or hashing are turned away cheaply, naming
+// the field and what is wrong with it. Lengths are checked before structure.
+pub fn check_fields(limits: &LimitsConfig, user_op: &UserOperation) -> Result<(), PaymasterError> {
+    let size_caps = [
+        ("initCode", user_op.init_code.len(), limits.max_init_code_size),
+        ("callData", user_op.call_data.len(), limits.max_call_data_size),
+        ("signature", user_op.signature.len(), limits.max_signature_size),
+    ];
+    for (field, size, cap) in size_caps {
+        if size > cap {
+            return Err(PaymasterError::MalformedField(field, format!("{} bytes, limit is {} bytes", size, cap)));
+        }
+    }
+
+    check_init_code(&user_op.init_code)?;
+    check_call_data(limits, &user_op.call_data)
+}
+
+// initCode is empty, or a factory address followed by the call that deploys the account
+fn check_init_code(init_code: &[u8]) -> Result<(), PaymasterError> {
+    let malformed = |reason: &str| Err(PaymasterError::MalformedField("initCode", reason.to_string()));
+    if init_code.is_empty() {
+        return Ok(());
+    }
+    let Some((factory, factory_call)) = init_code.split_first_chunk::<20>() else {
+        return malformed("shorter than a factory address");
+    };
+    if Address::from(*factory).is_zero() {
+        return malformed("factory address is zero");
+    }
+    if factory_call.len() < 4 {
+        return malformed("factory call has no function selector");
+    }
+    if (factory_call.len() - 4) % ABI_WORD != 0 {
+        return malformed("factory call arguments are not ABI-encoded");
+    }
+    Ok(())
+}
+
+// callData is empty, or a function selector followed by ABI-encoded arguments. Calls to the
+// execute functions must decode, within the batch cap.
+fn check_call_data(limits: &LimitsConfig, call_data: &[u8]) -> Result<(), PaymasterError> {
+    let malformed = |reason: String| Err(PaymasterError::MalformedField("callData", reason));
+    if call_data.is_empty() {
+        return Ok(());
+    }
+    let Some((selector, args)) = call_data.split_first_chunk::<4>() else {
+        return malformed("shorter than a function selector".to_string());
+    };
+    if !limits.call_data_selectors.is_empty() && !limits.call_data_selectors.contains(selector) {
+        return malformed(format!("function selector 0x{} is not allowed", hex::encode(selector)));
+    }
+    if args.len() % ABI_WORD != 0 {
+        return malformed("arguments are not ABI-encoded".to_string());
+    }
+    if calldata::is_execute(call_data) {
+        let Some(calls) = calldata::decode_calls(call_data) else {
+            return malformed("execute arguments do not decode".to_string());
+        };
+        if calls.len() > limits.max_batch_calls {
+            return malformed(format!("batch of {} calls, limit is {} calls", calls.len(), limits.max_batch_calls));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::abi::{self, Token};
+    use ethers::types::{Bytes, U256};
+    use ethers::utils::id;
+
+    fn user_op(init_code: Vec<u8>, call_data: Vec<u8>) -> UserOperation {
+        UserOperation {
+            sender: Address::repeat_byte(0x11),
+            nonce: U256::zero(),
+            init_code: Bytes::from(init_code),
+            call_data: Bytes::from(call_data),
+            call_gas_limit: U256::from(100_000),
+            verification_gas_limit: U256::from(100_000),
+            pre_verification_gas: U256::from(50_000),
+            max_fee_per_gas: U256::from(1_000_000_000u64),
+            max_priority_fee_per_gas: U256::from(1_000_000_000u64),
+            paymaster_and_data: Bytes::default(),
+            signature: Bytes::default(),
+        }
+    }
+
+    // The field and reason an operation is refused for
+    fn refusal(limits: &LimitsConfig, user_op: &UserOperation) -> (&'static str, String) {
+        match check_fields(limits, user_op) {
+            Err(PaymasterError::MalformedField(field, reason)) => (field, reason),
+            other => panic!("expected a malformed field, got {:?}", other),
+        }
+    }
+
+    fn init_code(factory: Address, call: &[u8]) -> Vec<u8> {
+        [factory.as_bytes(), call].concat()
+    }
+
+    // executeBatch(address[],bytes[]) calling each target with empty data
+    fn execute_batch(calls: usize) -> Vec<u8> {
+        let targets = (0..calls).map(|i| Token::Address(Address::repeat_byte(i as u8 + 1))).collect();
+        [id("executeBatch(address[],bytes[])").as_slice(), &abi::encode(&[Token::Array(targets), Token::Array(Vec::new())])].concat()
+    }
+
+    #[test]
+    fn accepts_empty_and_well_formed_fields() {
+        let limits = LimitsConfig::default();
+        assert!(check_fields(&limits, &user_op(Vec::new(), Vec::new())).is_ok());
+
+        let deploy = init_code(Address::repeat_byte(0xfa), &[[0x5f; 4].as_slice(), &[0; 64]].concat());
+        let call = [id("transfer(address,uint256)").as_slice(), &[0; 64]].concat();
+        assert!(check_fields(&limits, &user_op(deploy, call)).is_ok());
+    }
+
+    #[test]
+    fn refuses_malformed_init_code() {
+        let limits = LimitsConfig::default();
+        let refused = |init_code: Vec<u8>| refusal(&limits, &user_op(init_code, Vec::new()));
+
+        assert_eq!(refused(vec![0xfa; 19]), ("initCode", "shorter than a factory address".to_string()));
+        assert_eq!(refused(init_code(Address::zero(), &[0x5f; 4])), ("initCode", "factory address is zero".to_string()));
+        assert_eq!(
+            refused(init_code(Address::repeat_byte(0xfa), &[0x5f; 3])),
+            ("initCode", "factory call has no function selector".to_string())
+        );
+        assert_eq!(
+            refused(init_code(Address::repeat_byte(0xfa), &[0x5f; 4 + 31])),
+            ("initCode", "factory call arguments are not ABI-encoded".to_string())
+        );
+    }
+
+    #[test]
+    fn refuses_malformed_call_data() {
+        let limits = LimitsConfig::default();
+        let refused = |call_data: Vec<u8>| refusal(&limits, &user_op(Vec::new(), call_data));
+
+        assert_eq!(refused(vec![0xb6; 3]), ("callData", "shorter than a function selector".to_string()));
+        assert_eq!(refused(vec![0xb6; 4 + 33]), ("callData", "arguments are not ABI-encoded".to_string()));
+        assert_eq!(
+            refused([id("execute(address,uint256,bytes)").as_slice(), &[0; 32]].concat()),
+            ("callData", "execute arguments do not decode".to_string())
+        );
+
+        let limits = LimitsConfig { call_data_selectors: vec![[0xb6, 0x1d, 0x27, 0xf6]], ..LimitsConfig::default() };
+        assert_eq!(
+            refusal(&limits, &user_op(Vec::new(), vec![0x12, 0x34, 0x56, 0x78])),
+            ("callData", "function selector 0x12345678 is not allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn caps_batches_and_sizes() {
+        let limits = LimitsConfig { max_batch_calls: 2, max_init_code_size: 24, ..LimitsConfig::default() };
+
+        assert!(check_fields(&limits, &user_op(Vec::new(), execute_batch(2))).is_ok());
+        assert_eq!(
+            refusal(&limits, &user_op(Vec::new(), execute_batch(3))),
+            ("callData", "batch of 3 calls, limit is 2 calls".to_string())
+        );
+        // Sizes are checked before structure
+        assert_eq!(
+            refusal(&limits, &user_op(vec![0; 25], Vec::new())),
+            ("initCode", "25 bytes, limit is 24 bytes".to_string())
+        );
+    }
+}
@@ -0,0 +1,66 @@
+// src/deploy.rs
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use ethers::prelude::*;
+use tracing::info;
+
+use crate::contracts::{EntryPointVersion, VERIFYINGPAYMASTER_ABI};
+use crate::provider::EthProvider;
+
+// Default location of the bytecode compiled from the bundled contract for the EntryPoint's
+// release: contracts/VerifyingPaymaster.sol for v0.6, contracts/VerifyingPaymasterV07.sol after
+pub fn default_bytecode_path(version: EntryPointVersion) -> &'static Path {
+    match version {
+        EntryPointVersion::V06 => Path::new("contracts/out/VerifyingPaymaster.bin"),
+        EntryPointVersion::V07 | EntryPointVersion::V08 => Path::new("contracts/out/VerifyingPaymasterV07.bin"),
+    }
+}
+
+// Read contract creation bytecode from a `solc --bin` output file or a Foundry/Hardhat JSON artifact
+pub fn load_bytecode(path: &Path) -> Result<Bytes> {
+    let contents = std::fs::read_to_string(path).with_context(|| {
+        format!("Failed to read contract bytecode {}; compile the bundled contract for the EntryPoint first", path.display())
+    })?;
+
+    let hex = match serde_json::from_str::<serde_json::Value>(&contents) {
+        Ok(artifact) => artifact["bytecode"]["object"]
+            .as_str()
+            .or_else(|| artifact["bytecode"].as_str())
+            .with_context(|| format!("No bytecode found in artifact {}", path.display()))?
+            .to_string(),
+        Err(_) => contents.trim().to_string(),
+    };
+
+    let bytecode: Bytes = hex.parse().with_context(|| format!("Invalid bytecode in {}", path.display()))?;
+    if bytecode.is_empty() {
+        bail!("Contract bytecode in {} is empty", path.display());
+    }
+    Ok(bytecode)
+}
+
+// Deploy a VerifyingPaymaster bound to `entry_point` and `verifying_signer`, waiting for it to be mined
+pub async fn deploy_verifying_paymaster(
+    client: EthProvider,
+    wallet: LocalWallet,
+    bytecode: Bytes,
+    entry_point: Address,
+    verifying_signer: Address,
+) -> Result<(Address, TxHash)> {
+    let client = Arc::new(SignerMiddleware::new(client, wallet));
+    let factory = ContractFactory::new(VERIFYINGPAYMASTER_ABI.clone(), bytecode, client);
+
+    let (contract, receipt) = factory
+        .deploy((entry_point, verifying_signer))?
+        .send_with_receipt()
+        .await
+        .context("Paymaster deployment failed")?;
+
+    if receipt.status != Some(1.into()) {
+        bail!("Paymaster deployment reverted in transaction {:?}", receipt.transaction_hash);
+    }
+
+    info!("Deployed VerifyingPaymaster {:?} in transaction {:?}", contract.address(), receipt.transaction_hash);
+    Ok((contract.address(), receipt.transaction_hash))
+}
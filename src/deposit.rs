@@ -0,0 +1,99 @@
+// src/deposit.rs
+use std::sync::Arc;
+
+use ethers::prelude::*;
+use tracing::info;
+
+use crate::contracts::{EntryPoint, VerifyingPaymaster};
+use crate::error::PaymasterError;
+use crate::provider::EthProvider;
+
+pub type SignerClient = SignerMiddleware<EthProvider, LocalWallet>;
+
+// Manages the paymaster's deposit and stake held by the EntryPoint
+pub struct DepositManager {
+    entry_point: EntryPoint<SignerClient>,
+    paymaster: VerifyingPaymaster<SignerClient>,
+}
+
+impl DepositManager {
+    pub fn new(
+        client: EthProvider,
+        wallet: LocalWallet,
+        entry_point: Address,
+        paymaster_address: Address,
+    ) -> Self {
+        let client = Arc::new(SignerMiddleware::new(client, wallet));
+
+        Self {
+            entry_point: EntryPoint::new(entry_point, client.clone()),
+            paymaster: VerifyingPaymaster::new(paymaster_address, client),
+        }
+    }
+
+    // Current deposit of the paymaster as reported by the paymaster contract
+    pub async fn deposit_balance(&self) -> Result<U256, PaymasterError> {
+        self.paymaster.get_deposit()
+            .call()
+            .await
+            .map_err(|e| PaymasterError::EthereumProviderError(e.to_string()))
+    }
+
+    // Add funds to the paymaster's deposit in the EntryPoint
+    pub async fn deposit(&self, amount: U256) -> Result<TxHash, PaymasterError> {
+        let call = self.entry_point.deposit_to(self.paymaster.address()).value(amount);
+        let tx_hash = send_and_confirm(call).await?;
+
+        info!("Deposited {} wei for paymaster {}", amount, self.paymaster.address());
+        Ok(tx_hash)
+    }
+
+    // Move funds out of the deposit to `to`; only the paymaster owner may do this
+    pub async fn withdraw_to(&self, to: Address, amount: U256) -> Result<TxHash, PaymasterError> {
+        let call = self.paymaster.withdraw_to(to, amount);
+        let tx_hash = send_and_confirm(call).await?;
+
+        info!("Withdrew {} wei from the deposit of paymaster {} to {}", amount, self.paymaster.address(), to);
+        Ok(tx_hash)
+    }
+
+    // Balance of the wallet that funds deposits and pays for these transactions
+    pub async fn wallet_balance(&self) -> Result<U256, PaymasterError> {
+        let client = self.entry_point.client();
+        client.get_balance(client.address(), None)
+            .await
+            .map_err(|e| PaymasterError::EthereumProviderError(e.to_string()))
+    }
+
+    pub fn paymaster_address(&self) -> Address {
+        self.paymaster.address()
+    }
+
+    // Stake funds for the paymaster; only the paymaster owner may do this
+    pub async fn add_stake(&self, amount: U256, unstake_delay_sec: u32) -> Result<TxHash, PaymasterError> {
+        let call = self.paymaster.add_stake(unstake_delay_sec).value(amount);
+        let tx_hash = send_and_confirm(call).await?;
+
+        info!("Staked {} wei for paymaster {} with unstake delay {}s", amount, self.paymaster.address(), unstake_delay_sec);
+        Ok(tx_hash)
+    }
+}
+
+// Send a contract transaction and wait until it is mined successfully
+pub async fn send_and_confirm(call: ContractCall<SignerClient, ()>) -> Result<TxHash, PaymasterError> {
+    let pending = call.send()
+        .await
+        .map_err(|e| PaymasterError::EthereumProviderError(e.to_string()))?;
+    let tx_hash = pending.tx_hash();
+
+    let receipt = pending
+        .await
+        .map_err(|e| PaymasterError::EthereumProviderError(e.to_string()))?
+        .ok_or_else(|| PaymasterError::TransactionReverted(format!("{:?} was dropped", tx_hash)))?;
+
+    if receipt.status != Some(1.into()) {
+        return Err(PaymasterError::TransactionReverted(format!("{:?}", tx_hash)));
+    }
+
+    Ok(tx_hash)
+}
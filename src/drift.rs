@@ -0,0 +1,100 @@
+// src/drift.rs
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ethers::prelude::*;
+use tracing::{info, warn};
+
+use crate::config::ValidityConfig;
+use crate::error::PaymasterError;
+use crate::metrics::Metrics;
+use crate::provider::EthProvider;
+
+// Whether the provider was last seen too far behind head to anchor validity windows to, shared
+// between the monitor and the paymaster
+#[derive(Debug, Default)]
+pub struct SyncState {
+    lagging: AtomicBool,
+    // How long until the monitor looks again, in seconds
+    retry_after: u64,
+}
+
+impl SyncState {
+    pub fn new(retry_after: u64) -> Self {
+        Self { lagging: AtomicBool::new(false), retry_after }
+    }
+
+    pub fn check(&self) -> Result<(), PaymasterError> {
+        match self.lagging.load(Ordering::Relaxed) {
+            true => Err(PaymasterError::ProviderUnavailable(self.retry_after)),
+            false => Ok(()),
+        }
+    }
+}
+
+// Compares the host clock with the latest block timestamp and follows the provider's
+// `eth_syncing` status, exporting both as metrics
+pub struct DriftMonitor {
+    client: EthProvider,
+    max_clock_drift_secs: u64,
+    max_blocks_behind: u64,
+    state: Arc<SyncState>,
+    metrics: Arc<Metrics>,
+}
+
+impl DriftMonitor {
+    pub fn new(client: EthProvider, config: &ValidityConfig, state: Arc<SyncState>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            client,
+            max_clock_drift_secs: config.max_clock_drift_secs,
+            max_blocks_behind: config.max_blocks_behind,
+            state,
+            metrics,
+        }
+    }
+
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.check_clock().await {
+                warn!("Failed to compare the host clock with the chain: {}", e);
+            }
+            if let Err(e) = self.check_sync().await {
+                warn!("Failed to read the provider's sync status: {}", e);
+            }
+        }
+    }
+
+    async fn check_clock(&self) -> Result<(), ProviderError> {
+        let block = self.client.get_block(BlockNumber::Latest).await?
+            .ok_or_else(|| ProviderError::CustomError("Latest block not found".to_string()))?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        let drift = now.abs_diff(block.timestamp.low_u64());
+        self.metrics.clock_drift_secs.store(drift, Ordering::Relaxed);
+        if drift > self.max_clock_drift_secs {
+            warn!("Host clock is {}s away from the latest block timestamp {}", drift, block.timestamp);
+        }
+        Ok(())
+    }
+
+    async fn check_sync(&self) -> Result<(), ProviderError> {
+        let (syncing, behind) = match self.client.syncing().await? {
+            SyncingStatus::IsFalse => (false, 0),
+            SyncingStatus::IsSyncing(progress) => (true, progress.highest_block.saturating_sub(progress.current_block).as_u64()),
+        };
+        self.metrics.provider_syncing.store(syncing as u64, Ordering::Relaxed);
+        self.metrics.blocks_behind.store(behind, Ordering::Relaxed);
+
+        let lagging = syncing && behind > self.max_blocks_behind;
+        let was_lagging = self.state.lagging.swap(lagging, Ordering::Relaxed);
+        if lagging && !was_lagging {
+            warn!("Provider is syncing {} blocks behind head; no validity windows are issued until it catches up", behind);
+        } else if was_lagging && !lagging {
+            info!("Provider caught up with head; issuing validity windows again");
+        }
+        Ok(())
+    }
+}
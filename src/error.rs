@@ -1,26 +1,82 @@
 // src/error.rs
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+use crate::revert::Revert;
+
+#[derive(Error, Debug, Clone)]
 pub enum PaymasterError {
     #[error("Invalid UserOperation: {0}")]
     InvalidUserOperation(String),
     
+    #[error("Malformed {0}: {1}")]
+    MalformedField(&'static str, String),
+    
     #[error("Insufficient funds for sponsoring transaction")]
     InsufficientFunds,
     
+    #[error("Paymaster deposit is low, {0}")]
+    DepositLow(String),
+    
     #[error("Signature verification failed")]
     SignatureVerificationFailed,
     
     #[error("Transaction reverted: {0}")]
     TransactionReverted(String),
     
+    #[error("Simulation failed: {0}")]
+    SimulationFailed(Revert),
+    
     #[error("Ethereum provider error: {0}")]
     EthereumProviderError(String),
     
+    #[error("Ethereum provider unavailable, retry after {0}s")]
+    ProviderUnavailable(u64),
+    
+    #[error("Sponsorship is temporarily paused for maintenance")]
+    Paused(Option<u64>),
+    
+    #[error("Host clock is {0}s away from the latest block timestamp")]
+    ClockDrift(u64),
+    
+    #[error("Request did not finish within {0}ms")]
+    DeadlineExceeded(u64),
+    
     #[error("Invalid parameters: {0}")]
     InvalidParameters(String),
     
+    #[error("Operation requires operator approval, ticket {0}")]
+    ApprovalRequired(String),
+    
+    #[error("Rejected by operator: {0}")]
+    RejectedByOperator(String),
+    
+    #[error("Unknown sponsorship ticket: {0}")]
+    TicketNotFound(String),
+    
+    #[error("Unknown sponsored operation: {0}")]
+    OperationNotFound(String),
+    
+    #[error("Unknown project: {0}")]
+    ProjectNotFound(String),
+    
+    #[error("Sponsorship policy limit reached: {0}")]
+    PolicyLimitExceeded(String),
+    
+    #[error("Bundler error: {0}")]
+    BundlerError(String),
+    
+    #[error("Self-test failed: {0}")]
+    SelfTestFailed(String),
+    
+    #[error("Price feed error: {0}")]
+    PriceFeedError(String),
+    
+    #[error("Audit log error: {0}")]
+    AuditLogError(String),
+    
+    #[error("Storage error: {0}")]
+    StorageError(String),
+    
     #[error("Unsupported operation")]
     UnsupportedOperation,
 }
\ No newline at end of file
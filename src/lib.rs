@@ -0,0 +1,43 @@
+// src/lib.rs
+// ERC-4337 verifying paymaster, usable as a library or through the `arka-light` binary
+pub mod approval;
+pub mod audit;
+pub mod auth;
+pub mod bundler;
+pub mod cache;
+pub mod calldata;
+pub mod config;
+pub mod contracts;
+pub mod dashboard;
+pub mod deadline;
+pub mod decoding;
+pub mod db;
+pub mod deploy;
+pub mod deposit;
+pub mod drift;
+pub mod error;
+pub mod maintenance;
+pub mod metrics;
+pub mod origin;
+pub mod paymaster;
+pub mod policy;
+pub mod project;
+pub mod provider;
+pub mod rebalance;
+pub mod replay;
+pub mod report;
+pub mod rest;
+pub mod revert;
+pub mod rotation;
+pub mod rpc;
+pub mod self_test;
+pub mod server;
+pub mod session;
+pub mod settlement;
+pub mod shard;
+pub mod signer;
+pub mod signing_queue;
+pub mod store;
+pub mod token;
+pub mod types;
+pub mod user_op_hash;
@@ -1,36 +1,169 @@
 // src/main.rs
-use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use clap::Parser;
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use jsonrpsee::server::{ServerBuilder, ServerHandle};
+use ethers::prelude::*;
+use ethers::utils::{format_ether, parse_ether};
 use jsonrpsee::RpcModule;
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
-mod error;
-mod paymaster;
-mod rpc;
-mod types;
+use arka_light::{audit, deploy, provider, replay, rpc, server, signer};
+use arka_light::approval::ApprovalQueue;
+use arka_light::audit::AuditLog;
+use arka_light::bundler::Bundler;
+use arka_light::config::{Config, SigningQueueConfig, StoreBackend};
+use arka_light::contracts::{EntryPointVersion, ENTRY_POINT_V06};
+use arka_light::db::Database;
+use arka_light::deposit::DepositManager;
+use arka_light::drift::{DriftMonitor, SyncState};
+use arka_light::metrics::Metrics;
+use arka_light::paymaster::Paymaster;
+use arka_light::rebalance::Rebalancer;
+use arka_light::replay::ReplayCapture;
+use arka_light::report::{self, Period, ReportFormat};
+use arka_light::rpc::PaymasterRpcImpl;
+use arka_light::server::ListenAddr;
+use arka_light::settlement::Settlement;
+use arka_light::shard::Shard;
+use arka_light::signer::{PaymasterSigner, SignerChain};
+use arka_light::signing_queue::SigningQueue;
+use arka_light::store::{DatabaseStore, MemoryStore, SponsorshipStore};
 
-use crate::paymaster::Paymaster;
-use crate::rpc::PaymasterRpcImpl;
+// How often the EntryPoint is polled for included operations, about once per block
+const INCLUSION_POLL_INTERVAL: Duration = Duration::from_secs(12);
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
-    #[clap(short, long, default_value = "127.0.0.1:8545")]
-    rpc_server_addr: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
     
+    /// Addresses to listen on: IPv4 or IPv6 socket addresses, or `unix:/path/to.sock`.
+    /// Repeat the flag or separate addresses with commas.
+    #[clap(short, long, default_value = "127.0.0.1:8545", value_delimiter = ',')]
+    rpc_server_addr: Vec<ListenAddr>,
+    
+    /// Local signer key; not needed when signers are set in the config file
     #[clap(short, long)]
-    private_key: String,
+    private_key: Option<String>,
     
     #[clap(short, long)]
     chain_id: u64,
     
     #[clap(short, long)]
     eth_rpc_url: String,
+    
+    #[clap(long, default_value = ENTRY_POINT_V06)]
+    entry_point: Address,
+    
+    /// EntryPoint release (0.6, 0.7 or 0.8); detected for the canonical deployments
+    #[clap(long)]
+    entry_point_version: Option<EntryPointVersion>,
+    
+    /// Paymaster contract; defaults to `chains.<chain-id>.paymaster_address` in the config file
+    #[clap(long)]
+    paymaster_address: Option<Address>,
+    
+    /// SQLite or Postgres URL used for persistent storage
+    #[clap(long)]
+    database_url: Option<String>,
+    
+    /// TOML file with additional settings
+    #[clap(long)]
+    config: Option<PathBuf>,
+    
+    /// Run every check but sign with a throwaway key and flag responses with `dryRun`.
+    /// Configured signers and deposit rebalancing are not used.
+    #[clap(long)]
+    dry_run: bool,
+    
+    /// Start in maintenance mode, serving only the API keys and senders listed under `[maintenance]`
+    #[clap(long)]
+    maintenance: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run the paymaster JSON-RPC server (default)
+    Serve,
+    /// Show the paymaster's deposit in the EntryPoint
+    Balance,
+    /// Deposit ETH into the EntryPoint for the paymaster
+    Deposit {
+        /// Amount in ETH
+        #[clap(long)]
+        amount: String,
+    },
+    /// Stake ETH in the EntryPoint for the paymaster
+    Stake {
+        /// Amount in ETH
+        #[clap(long)]
+        amount: String,
+        
+        #[clap(long, default_value = "86400")]
+        unstake_delay_sec: u32,
+    },
+    /// Apply pending database schema migrations and exit
+    Migrate,
+    /// Check the HMAC chain of an audit log file, or of the audit_log table in --database-url
+    /// when no path is given
+    VerifyAuditLog {
+        #[clap(long)]
+        path: Option<PathBuf>,
+    },
+    /// Deploy the bundled VerifyingPaymaster for the configured EntryPoint and verifying signer
+    Deploy {
+        /// Compiled contract: `solc --bin` output or a Foundry/Hardhat JSON artifact; defaults to
+        /// the bundled contract for the EntryPoint's release under contracts/out
+        #[clap(long)]
+        bytecode: Option<PathBuf>,
+        
+        /// Verifying signer; defaults to the primary configured signer
+        #[clap(long)]
+        verifying_signer: Option<Address>,
+        
+        /// ETH to deposit into the EntryPoint after deployment
+        #[clap(long)]
+        deposit: Option<String>,
+        
+        /// ETH to stake after deployment
+        #[clap(long)]
+        stake: Option<String>,
+        
+        #[clap(long, default_value = "86400")]
+        unstake_delay_sec: u32,
+    },
+    /// Summarize sponsored spend from the audit log per period, chain, policy and sender
+    Report {
+        #[clap(long, value_enum, default_value = "daily")]
+        period: Period,
+        
+        #[clap(long, value_enum, default_value = "csv")]
+        format: ReportFormat,
+        
+        /// First day to include (YYYY-MM-DD, UTC)
+        #[clap(long)]
+        since: Option<NaiveDate>,
+        
+        /// Last day to include (YYYY-MM-DD, UTC)
+        #[clap(long)]
+        until: Option<NaiveDate>,
+        
+        /// Write the report to a file instead of stdout
+        #[clap(long)]
+        output: Option<PathBuf>,
+    },
+    /// Rerun requests captured with `replay.capture_path` and report decisions that changed.
+    /// Nothing is signed with the configured signers or stored outside the run.
+    Replay {
+        #[clap(long)]
+        path: PathBuf,
+    },
 }
 
 #[tokio::main]
@@ -46,41 +179,464 @@ async fn main() -> anyhow::Result<()> {
     // Parse command line arguments
     let args = Args::parse();
     
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+    config.maintenance.enabled |= args.maintenance;
+    
+    match &args.command {
+        None | Some(Command::Serve) => {}
+        Some(Command::Migrate) => return run_migrations(&args).await.map(|_| ()),
+        Some(Command::VerifyAuditLog { path }) => return verify_audit_log(path.as_deref(), &args, &config).await,
+        Some(command @ Command::Report { .. }) => return run_report(command, &args, &config).await,
+        Some(command @ Command::Deploy { .. }) => return run_deploy(command, &args, &config).await,
+        Some(Command::Replay { path }) => return run_replay(path, &args, &config).await,
+        Some(command) => return run_deposit_command(command, &args, &config).await,
+    }
+    
+    // Bring the database schema up to date before serving
+    let database = match &args.database_url {
+        Some(_) => Some(run_migrations(&args).await?),
+        None => None,
+    };
+    
+    // Keep budgets, reservations and the sponsorship history in memory or in the database. A dry
+    // run keeps them in memory, so its reservations and records never reach a shared store.
+    let store: Arc<dyn SponsorshipStore> = match (config.store.backend, &database) {
+        _ if args.dry_run => Arc::new(MemoryStore::default()),
+        (StoreBackend::Memory, _) => Arc::new(MemoryStore::default()),
+        (StoreBackend::Sqlite, Some(database @ Database::Sqlite(_)))
+        | (StoreBackend::Postgres, Some(database @ Database::Postgres(_))) => {
+            Arc::new(DatabaseStore::new(database.clone(), args.chain_id))
+        }
+        (StoreBackend::Sqlite, _) => anyhow::bail!("The sqlite store requires a sqlite: --database-url"),
+        (StoreBackend::Postgres, _) => anyhow::bail!("The postgres store requires a postgres: --database-url"),
+    };
+    
+    let metrics = Arc::new(Metrics::default());
+    
+    // Set up the signer chain and keep probing its health in the background. A dry run signs
+    // with a key generated for the run, so nothing it hands out is accepted on chain.
+    let signer = match args.dry_run {
+        true => {
+            let wallet = LocalWallet::new(&mut rand::thread_rng()).with_chain_id(args.chain_id);
+            warn!("Dry run: signing with throwaway key {:?} instead of the configured signers", Signer::address(&wallet));
+            Arc::new(SignerChain::new(vec![Arc::new(wallet)], metrics.clone())?)
+        }
+        false => Arc::new(SignerChain::from_config(
+            &config.signing,
+            args.private_key.as_deref(),
+            args.chain_id,
+            metrics.clone(),
+        )?),
+    };
+    let health_check_interval = Duration::from_secs(config.signing.health_check_interval_secs);
+    tokio::spawn(signer.clone().run_health_checks(health_check_interval));
+    let signer = queued(signer, &config.signing.queue, &metrics);
+    
+    // Further paymaster deposits, each signed for by its own signers or the main chain
+    let mut shards = Vec::new();
+    for shard in &config.chain(args.chain_id).shards {
+        let shard_signer: Arc<dyn PaymasterSigner> = match shard.signers.is_empty() || args.dry_run {
+            true => signer.clone(),
+            false => {
+                let signers = shard.signers
+                    .iter()
+                    .map(|signer_config| signer::from_config(signer_config, args.chain_id))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                // Kept out of the exported metrics, which follow the main signer chain
+                let chain = Arc::new(SignerChain::new(signers, Arc::new(Metrics::default()))?);
+                tokio::spawn(chain.clone().run_health_checks(health_check_interval));
+                queued(chain, &config.signing.queue, &metrics)
+            }
+        };
+        shards.push(Shard { paymaster_address: shard.paymaster_address, signer: shard_signer });
+    }
+    
+    // Standby key for rotating the main chain's verifying key
+    let next_signer = match config.signing.next_signers.is_empty() || args.dry_run {
+        true => None,
+        false => {
+            let signers = config.signing.next_signers
+                .iter()
+                .map(|signer_config| signer::from_config(signer_config, args.chain_id))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            let chain = Arc::new(SignerChain::new(signers, Arc::new(Metrics::default()))?);
+            tokio::spawn(chain.clone().run_health_checks(health_check_interval));
+            Some(queued(chain, &config.signing.queue, &metrics))
+        }
+    };
+    
+    // Follow the host clock's drift from the chain and the provider's sync status; validity
+    // windows are not issued while the provider is too far behind head
+    let provider = provider::connect(&args.eth_rpc_url, config.chain(args.chain_id).proxy.as_ref(), &config.provider)?;
+    let sync = Arc::new(SyncState::new(config.validity.monitor_interval_secs));
+    let monitor = DriftMonitor::new(provider.clone(), &config.validity, sync.clone(), metrics.clone());
+    tokio::spawn(monitor.run(Duration::from_secs(config.validity.monitor_interval_secs)));
+    
     // Create the paymaster service
     let paymaster = Paymaster::new(
-        args.private_key,
+        signer,
         args.chain_id,
-        args.eth_rpc_url,
+        provider,
+        args.entry_point,
+        paymaster_address(&args, &config)?,
     ).await?;
+    let paymaster = configured(paymaster, &args, &config)?
+        .with_dry_run(args.dry_run)
+        .with_maintenance(&config.maintenance)
+        .with_sync_state(sync)
+        .with_shards(shards)
+        .with_store(store);
+    let paymaster = match next_signer {
+        Some(next_signer) => paymaster.with_next_signer(next_signer),
+        None => paymaster,
+    };
+    
+    // Record every sponsorship decision when auditing is enabled; a dry run's decisions are not
+    // real ones and stay out of the audit log
+    let paymaster = if config.audit.enabled() && !args.dry_run {
+        let audit_database = match (config.audit.database, &database) {
+            (false, _) => None,
+            (true, Some(database)) => Some(database.clone()),
+            (true, None) => anyhow::bail!("Database auditing requires --database-url"),
+        };
+        let audit_log = AuditLog::new(config.audit.path.clone(), audit_database, config.audit.hmac_key()?).await?;
+        paymaster.with_audit_log(audit_log)
+    } else {
+        paymaster
+    };
+    
+    // Capture sponsorship requests for the replay subcommand
+    let paymaster = match &config.replay.capture_path {
+        Some(path) => paymaster.with_replay_capture(ReplayCapture::new(path.clone())),
+        None => paymaster,
+    };
+    
+    // Hold operations above the configured thresholds for operator approval
+    let paymaster = if config.approval.enabled() {
+        paymaster.with_approval_queue(ApprovalQueue::new(config.approval.clone()))
+    } else {
+        paymaster
+    };
+    
+    // Serve the policies of projects managed through the admin API
+    let paymaster = if config.projects.enabled {
+        paymaster.with_projects()
+    } else {
+        paymaster
+    };
+    
+    // Follow sponsored operations through the chain's bundler
+    let paymaster = match &config.chain(args.chain_id).bundler_url {
+        Some(bundler_url) => {
+            let provider = provider::connect(bundler_url, config.chain(args.chain_id).proxy.as_ref(), &config.provider)?;
+            paymaster.with_bundler(Bundler::new(provider))
+        }
+        None => paymaster,
+    };
+    
+    // Refuse to serve when a signed synthetic operation does not round-trip; a dry run signs with
+    // a throwaway key the contracts do not verify with, so it is only checked locally
+    if config.self_test.enabled {
+        let on_chain = config.self_test.on_chain && !args.dry_run;
+        paymaster.self_test(on_chain).await.map_err(|e| anyhow::anyhow!("Refusing to serve: {}", e))?;
+        info!("Self-test passed{}", if on_chain { ", including on-chain validation" } else { "" });
+    }
     
     // Create the JSON-RPC server
-    let server_addr: SocketAddr = args.rpc_server_addr.parse()?;
-    let paymaster_rpc = PaymasterRpcImpl::new(Arc::new(paymaster));
+    let paymaster = Arc::new(paymaster);
+    if config.projects.enabled {
+        let loaded = paymaster.load_projects().await?;
+        info!("Serving {} projects", loaded);
+        tokio::spawn(paymaster.clone().run_project_sync(Duration::from_secs(config.projects.sync_interval_secs)));
+    }
+    
+    if config.chain(args.chain_id).bundler_url.is_some() {
+        tokio::spawn(paymaster.clone().run_receipt_poller(Duration::from_secs(config.bundler.receipt_poll_interval_secs)));
+    }
+    
+    // Settle policy reservations and deposit holds as sponsored operations are included or expire
+    if paymaster.tracks_inclusions() {
+        tokio::spawn(paymaster.clone().run_inclusion_tracker(INCLUSION_POLL_INTERVAL));
+    }
+    
+    // Keep every paymaster deposit within the configured band; a dry run sends no transactions
+    if let Some(funding_key) = config.rebalance.funding_key()?.filter(|_| !args.dry_run) {
+        let wallet = funding_key.parse::<LocalWallet>()?.with_chain_id(args.chain_id);
+        let provider = provider::connect(&args.eth_rpc_url, config.chain(args.chain_id).proxy.as_ref(), &config.provider)?;
+        let managers = std::iter::once(paymaster_address(&args, &config)?)
+            .chain(config.chain(args.chain_id).shards.iter().map(|shard| shard.paymaster_address))
+            .map(|address| DepositManager::new(provider.clone(), wallet.clone(), args.entry_point, address))
+            .collect();
+        let rebalancer = Rebalancer::new(&config.rebalance, managers, metrics.clone())?;
+        info!("Rebalancing paymaster deposits from funding wallet {:?}", Signer::address(&wallet));
+        tokio::spawn(rebalancer.run(Duration::from_secs(config.rebalance.interval_secs)));
+    }
+    
+    // Sweep the tokens collected from senders paying in tokens to the treasury
+    if let Some(owner_key) = config.settlement.owner_key()?.filter(|_| !args.dry_run) {
+        let owner = owner_key.parse::<LocalWallet>()?.with_chain_id(args.chain_id);
+        let provider = provider::connect(&args.eth_rpc_url, config.chain(args.chain_id).proxy.as_ref(), &config.provider)?;
+        let paymasters = std::iter::once(paymaster_address(&args, &config)?)
+            .chain(config.chain(args.chain_id).shards.iter().map(|shard| shard.paymaster_address))
+            .collect();
+        let owner_address = Signer::address(&owner);
+        let settlement = Settlement::new(&config.settlement, provider, owner, paymasters, config.chain(args.chain_id).tokens, metrics.clone())?;
+        info!("Sweeping collected tokens with paymaster owner {:?}", owner_address);
+        tokio::spawn(settlement.run(Duration::from_secs(config.settlement.interval_secs)));
+    }
+    let paymaster_rpc = PaymasterRpcImpl::new(paymaster.clone(), metrics.clone())
+        .with_max_timeout(config.server.max_request_timeout_ms.map(Duration::from_millis));
+    let mut module = RpcModule::new(paymaster_rpc);
+    rpc::register_methods(&mut module)?;
     
-    info!("Starting ERC-4337 Paymaster RPC server on {}", server_addr);
+    info!("Starting ERC-4337 Paymaster RPC server");
     
-    // Start the JSON-RPC server
-    let server_handle = start_server(server_addr, paymaster_rpc).await?;
+    // Start the JSON-RPC server on every listen address
+    let server = server::start(&args.rpc_server_addr, module.into(), &config.server, &config.access, &metrics).await?;
     
-    // Keep the server running until Ctrl+C is pressed
-    tokio::signal::ctrl_c().await?;
-    server_handle.stop()?;
+    // Keep the server running until SIGTERM or SIGINT
+    shutdown_signal().await?;
+    
+    // Let in-flight requests finish, then flush the audit log and database
+    let grace_period = Duration::from_secs(config.server.shutdown_grace_period_secs);
+    info!("Shutting down, waiting up to {}s for in-flight requests", grace_period.as_secs());
+    if !server.shutdown(grace_period).await? {
+        warn!("Grace period elapsed with requests still in flight");
+    }
+    
+    paymaster.close().await;
+    if let Some(database) = &database {
+        database.close().await;
+    }
     info!("Server stopped");
     
     Ok(())
 }
 
-async fn start_server(
-    server_addr: SocketAddr,
-    paymaster_rpc: PaymasterRpcImpl
-) -> anyhow::Result<ServerHandle> {
-    let server = ServerBuilder::default()
-        .build(server_addr)
-        .await?;
+async fn shutdown_signal() -> anyhow::Result<()> {
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
     
-    let mut module = RpcModule::new(paymaster_rpc);
-    rpc::register_methods(&mut module)?;
-    let server_handle = server.start(module);
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = sigterm.recv() => {}
+    }
+    
+    Ok(())
+}
+
+async fn run_deposit_command(command: &Command, args: &Args, config: &Config) -> anyhow::Result<()> {
+    let private_key = args.private_key.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("--private-key is required for deposit management"))?;
+    let wallet = private_key.parse::<LocalWallet>()?.with_chain_id(args.chain_id);
+    let provider = provider::connect(&args.eth_rpc_url, config.chain(args.chain_id).proxy.as_ref(), &config.provider)?;
+    let paymaster_address = paymaster_address(args, config)?;
+    let manager = DepositManager::new(provider, wallet, args.entry_point, paymaster_address);
+    
+    match command {
+        Command::Balance => {
+            let balance = manager.deposit_balance().await?;
+            info!("Paymaster {} deposit: {} ETH", paymaster_address, format_ether(balance));
+        }
+        Command::Deposit { amount } => {
+            let tx_hash = manager.deposit(parse_ether(amount)?).await?;
+            info!("Deposit confirmed in transaction {:?}", tx_hash);
+        }
+        Command::Stake { amount, unstake_delay_sec } => {
+            let tx_hash = manager.add_stake(parse_ether(amount)?, *unstake_delay_sec).await?;
+            info!("Stake confirmed in transaction {:?}", tx_hash);
+        }
+        Command::Serve
+        | Command::Migrate
+        | Command::VerifyAuditLog { .. }
+        | Command::Report { .. }
+        | Command::Deploy { .. }
+        | Command::Replay { .. } => unreachable!("handled by main"),
+    }
+    
+    Ok(())
+}
+
+// Apply the settings a paymaster is given both when serving and when replaying
+fn configured(paymaster: Paymaster, args: &Args, config: &Config) -> anyhow::Result<Paymaster> {
+    Ok(paymaster
+        .with_entry_point_version(entry_point_version(args)?)
+        .with_limits(config.limits.clone())
+        .with_paymaster_gas(config.paymaster_gas.clone())
+        .with_fees(config.fees(args.chain_id))
+        .with_validity(config.validity.clone())
+        .with_simulation(config.simulation.clone())
+        .with_sessions(config.sessions.clone())
+        .with_degradation(config.degradation.clone())
+        .with_cache(config.cache.clone())
+        .with_tokens(config.chain(args.chain_id).tokens)
+        .with_policies(config.policies.clone())
+        .with_origins(config.origins.clone()))
+}
+
+fn paymaster_address(args: &Args, config: &Config) -> anyhow::Result<Address> {
+    args.paymaster_address
+        .or(config.chain(args.chain_id).paymaster_address)
+        .ok_or_else(|| anyhow::anyhow!("--paymaster-address is required unless set in the config file"))
+}
+
+// Put a signer behind the signing queue when it is enabled
+fn queued(signer: Arc<dyn PaymasterSigner>, config: &SigningQueueConfig, metrics: &Arc<Metrics>) -> Arc<dyn PaymasterSigner> {
+    match config.enabled {
+        true => Arc::new(SigningQueue::new(signer, config, metrics.clone())),
+        false => signer,
+    }
+}
+
+fn entry_point_version(args: &Args) -> anyhow::Result<EntryPointVersion> {
+    args.entry_point_version
+        .or_else(|| EntryPointVersion::of(args.entry_point))
+        .ok_or_else(|| anyhow::anyhow!("--entry-point-version is required for EntryPoint {:?}", args.entry_point))
+}
+
+async fn run_deploy(command: &Command, args: &Args, config: &Config) -> anyhow::Result<()> {
+    let Command::Deploy { bytecode, verifying_signer, deposit, stake, unstake_delay_sec } = command else {
+        unreachable!("not a deploy command");
+    };
+    
+    let private_key = args.private_key.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("--private-key is required to deploy the paymaster"))?;
+    let wallet = private_key.parse::<LocalWallet>()?.with_chain_id(args.chain_id);
+    let provider = provider::connect(&args.eth_rpc_url, config.chain(args.chain_id).proxy.as_ref(), &config.provider)?;
+    
+    let verifying_signer = match verifying_signer {
+        Some(address) => *address,
+        None => SignerChain::from_config(
+            &config.signing,
+            args.private_key.as_deref(),
+            args.chain_id,
+            Arc::new(Metrics::default()),
+        )?.address(),
+    };
+    
+    let (paymaster_address, _) = deploy::deploy_verifying_paymaster(
+        provider.clone(),
+        wallet.clone(),
+        deploy::load_bytecode(bytecode.as_deref().unwrap_or(deploy::default_bytecode_path(entry_point_version(args)?)))?,
+        args.entry_point,
+        verifying_signer,
+    ).await?;
+    
+    // The deployer owns the contract, so it can also stake and fund it
+    let manager = DepositManager::new(provider, wallet, args.entry_point, paymaster_address);
+    if let Some(amount) = stake {
+        let tx_hash = manager.add_stake(parse_ether(amount)?, *unstake_delay_sec).await?;
+        info!("Stake confirmed in transaction {:?}", tx_hash);
+    }
+    if let Some(amount) = deposit {
+        let tx_hash = manager.deposit(parse_ether(amount)?).await?;
+        info!("Deposit confirmed in transaction {:?}", tx_hash);
+    }
+    
+    match &args.config {
+        Some(path) => {
+            Config::write_paymaster_address(path, args.chain_id, paymaster_address)?;
+            info!("Recorded paymaster {:?} for chain {} in {}", paymaster_address, args.chain_id, path.display());
+        }
+        None => info!("Paymaster deployed at {:?}; pass --config to record it automatically", paymaster_address),
+    }
+    
+    Ok(())
+}
+
+async fn run_migrations(args: &Args) -> anyhow::Result<Database> {
+    let database_url = args.database_url.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("--database-url is required to run migrations"))?;
     
-    Ok(server_handle)
-}
\ No newline at end of file
+    let database = Database::connect(database_url).await?;
+    database.migrate().await?;
+    Ok(database)
+}
+
+async fn verify_audit_log(path: Option<&std::path::Path>, args: &Args, config: &Config) -> anyhow::Result<()> {
+    let key = config.audit.hmac_key()?
+        .ok_or_else(|| anyhow::anyhow!("audit.hmac_key_env must be configured to verify the audit log"))?;
+    
+    match (path, &args.database_url) {
+        (Some(path), _) => {
+            let entries = audit::verify_file(path, &key).await?;
+            info!("Verified {} audit log entries in {}", entries, path.display());
+        }
+        (None, Some(database_url)) => {
+            let database = Database::connect(database_url).await?;
+            let verified = audit::verify_database(&database, &key).await;
+            database.close().await;
+            info!("Verified {} audit log entries in the database", verified?);
+        }
+        (None, None) => anyhow::bail!("Pass --path to verify an audit log file, or --database-url to verify the audit_log table"),
+    }
+    
+    Ok(())
+}
+
+async fn run_report(command: &Command, args: &Args, config: &Config) -> anyhow::Result<()> {
+    let Command::Report { period, format, since, until, output } = command else {
+        unreachable!("not a report command");
+    };
+    
+    // The audit log is the sponsorship history; prefer the database copy when there is one
+    let database = match (&args.database_url, config.audit.database) {
+        (Some(database_url), true) => Some(Database::connect(database_url).await?),
+        _ => None,
+    };
+    if database.is_none() && config.audit.path.is_none() {
+        anyhow::bail!("The report is built from the audit log; configure audit.path or audit.database");
+    }
+    
+    let audit_log = AuditLog::new(config.audit.path.clone(), database, None).await?;
+    let entries = audit_log.entries(since.map(report::day_start).unwrap_or_default()).await?;
+    let rows = report::aggregate(&entries, *period, *since, *until);
+    let rendered = report::render(&rows, *format)?;
+    
+    match output {
+        Some(path) => {
+            std::fs::write(path, rendered)?;
+            info!("Wrote {} report rows to {}", rows.len(), path.display());
+        }
+        None => print!("{}", rendered),
+    }
+    
+    Ok(())
+}
+
+async fn run_replay(path: &std::path::Path, args: &Args, config: &Config) -> anyhow::Result<()> {
+    let records = replay::read_records(path).await?;
+    
+    // Sign with a throwaway key and keep budgets in memory, so the replay leaves no trace. The
+    // provider may be a fork from when the requests were captured, so the host clock is not held
+    // against its block timestamps.
+    let mut config = config.clone();
+    config.validity.max_clock_drift_secs = u64::MAX;
+    let wallet = LocalWallet::new(&mut rand::thread_rng()).with_chain_id(args.chain_id);
+    let signer: Arc<dyn PaymasterSigner> = Arc::new(SignerChain::new(vec![Arc::new(wallet)], Arc::new(Metrics::default()))?);
+    let shards = config.chain(args.chain_id).shards
+        .iter()
+        .map(|shard| Shard { paymaster_address: shard.paymaster_address, signer: signer.clone() })
+        .collect();
+    
+    let provider = provider::connect(&args.eth_rpc_url, config.chain(args.chain_id).proxy.as_ref(), &config.provider)?;
+    let paymaster = Paymaster::new(signer, args.chain_id, provider, args.entry_point, paymaster_address(args, &config)?).await?;
+    let paymaster = configured(paymaster, args, &config)?
+        .with_dry_run(true)
+        .with_shards(shards);
+    let paymaster = match config.approval.enabled() {
+        true => paymaster.with_approval_queue(ApprovalQueue::new(config.approval.clone())),
+        false => paymaster,
+    };
+    
+    let requests = records.len();
+    let diffs = replay::replay(&paymaster, records).await;
+    for diff in &diffs {
+        println!("{}", diff);
+    }
+    info!("Replayed {} requests from {}, {} came out differently", requests, path.display(), diffs.len());
+    
+    Ok(())
+}
@@ -0,0 +1,60 @@
+// src/maintenance.rs
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use ethers::types::Address;
+use serde::Serialize;
+
+use crate::config::MaintenanceConfig;
+use crate::error::PaymasterError;
+use crate::types::SponsorshipContext;
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    // When sponsorship is expected to resume, as a Unix timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eta: Option<u64>,
+}
+
+// While enabled, only requests authenticated with an allowlisted API key or coming from an
+// allowlisted sender are served, for controlled rollouts and incident response. Switched on at startup or
+// through the admin methods.
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    api_keys: HashSet<String>,
+    senders: HashSet<Address>,
+    status: Mutex<MaintenanceStatus>,
+}
+
+impl Maintenance {
+    pub fn new(config: &MaintenanceConfig) -> Self {
+        Self {
+            api_keys: config.api_keys.iter().cloned().collect(),
+            senders: config.senders.iter().copied().collect(),
+            status: Mutex::new(MaintenanceStatus { enabled: config.enabled, eta: config.eta }),
+        }
+    }
+
+    pub fn status(&self) -> MaintenanceStatus {
+        *self.status.lock().unwrap()
+    }
+
+    pub fn set(&self, enabled: bool, eta: Option<u64>) -> MaintenanceStatus {
+        let mut status = self.status.lock().unwrap();
+        *status = MaintenanceStatus { enabled, eta: eta.filter(|_| enabled) };
+        *status
+    }
+
+    pub fn check(&self, sender: Option<Address>, context: &SponsorshipContext) -> Result<(), PaymasterError> {
+        let status = self.status();
+        let allowed = !status.enabled
+            || context.api_key.as_ref().is_some_and(|api_key| self.api_keys.contains(api_key))
+            || sender.is_some_and(|sender| self.senders.contains(&sender));
+        match allowed {
+            true => Ok(()),
+            false => Err(PaymasterError::Paused(status.eta)),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+// src/metrics.rs
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+// Process-wide counters and gauges exposed through `system_metrics`
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub signatures: AtomicU64,
+    pub fallback_signatures: AtomicU64,
+    pub signer_failures: AtomicU64,
+    pub active_signer: AtomicU64,
+    pub deposit_top_ups: AtomicU64,
+    pub deposit_withdrawals: AtomicU64,
+    pub rebalance_failures: AtomicU64,
+    // 1 while the funding wallet is below its configured minimum
+    pub funding_balance_low: AtomicU64,
+    // Signing requests waiting in or being served by the signing queue
+    pub signing_queue_depth: AtomicU64,
+    pub signing_queue_full: AtomicU64,
+    pub signing_deadline_exceeded: AtomicU64,
+    // Seconds between the host clock and the latest block timestamp, either way
+    pub clock_drift_secs: AtomicU64,
+    // 1 while the provider reports it is syncing
+    pub provider_syncing: AtomicU64,
+    // Blocks the provider is behind the highest block it knows of
+    pub blocks_behind: AtomicU64,
+    // Requests abandoned at their deadline
+    pub request_timeouts: AtomicU64,
+    pub token_sweeps: AtomicU64,
+    pub settlement_failures: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    pub signatures: u64,
+    pub fallback_signatures: u64,
+    pub signer_failures: u64,
+    pub active_signer: u64,
+    pub deposit_top_ups: u64,
+    pub deposit_withdrawals: u64,
+    pub rebalance_failures: u64,
+    pub funding_balance_low: u64,
+    pub signing_queue_depth: u64,
+    pub signing_queue_full: u64,
+    pub signing_deadline_exceeded: u64,
+    pub clock_drift_secs: u64,
+    pub provider_syncing: u64,
+    pub blocks_behind: u64,
+    pub request_timeouts: u64,
+    pub token_sweeps: u64,
+    pub settlement_failures: u64,
+}
+
+impl Metrics {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            signatures: self.signatures.load(Ordering::Relaxed),
+            fallback_signatures: self.fallback_signatures.load(Ordering::Relaxed),
+            signer_failures: self.signer_failures.load(Ordering::Relaxed),
+            active_signer: self.active_signer.load(Ordering::Relaxed),
+            deposit_top_ups: self.deposit_top_ups.load(Ordering::Relaxed),
+            deposit_withdrawals: self.deposit_withdrawals.load(Ordering::Relaxed),
+            rebalance_failures: self.rebalance_failures.load(Ordering::Relaxed),
+            funding_balance_low: self.funding_balance_low.load(Ordering::Relaxed),
+            signing_queue_depth: self.signing_queue_depth.load(Ordering::Relaxed),
+            signing_queue_full: self.signing_queue_full.load(Ordering::Relaxed),
+            signing_deadline_exceeded: self.signing_deadline_exceeded.load(Ordering::Relaxed),
+            clock_drift_secs: self.clock_drift_secs.load(Ordering::Relaxed),
+            provider_syncing: self.provider_syncing.load(Ordering::Relaxed),
+            blocks_behind: self.blocks_behind.load(Ordering::Relaxed),
+            request_timeouts: self.request_timeouts.load(Ordering::Relaxed),
+            token_sweeps: self.token_sweeps.load(Ordering::Relaxed),
+            settlement_failures: self.settlement_failures.load(Ordering::Relaxed),
+        }
+    }
+}
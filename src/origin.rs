@@ -0,0 +1,123 @@
+// src/origin.rs
+use std::collections::HashMap;
+
+use ethers::abi::{self, Token};
+use ethers::types::{Signature, H256};
+use ethers::utils::{hash_message, keccak256};
+
+use crate::config::OriginConfig;
+use crate::error::PaymasterError;
+use crate::types::{SponsorshipContext, UserOperation};
+
+// Type string of the message a dapp's origin key signs, binding the origin to one operation
+pub const ORIGIN_TYPE: &str = "SponsorshipOrigin(uint256 chainId,address sender,uint256 nonce,string origin)";
+
+// Dapp domains that requests may claim to come from, and how each claim is proven
+pub struct OriginRegistry {
+    chain_id: u64,
+    origins: HashMap<String, OriginConfig>,
+}
+
+impl OriginRegistry {
+    pub fn new(chain_id: u64, origins: HashMap<String, OriginConfig>) -> Self {
+        Self { chain_id, origins }
+    }
+
+    // The origin the request comes from, once it is proven by an API key bound to the origin or by
+    // the origin key's signature over the operation. Requests that claim none are attributed to none.
+    pub fn verify(&self, user_op: &UserOperation, context: &SponsorshipContext) -> Result<Option<String>, PaymasterError> {
+        let Some(origin) = &context.origin else {
+            return Ok(None);
+        };
+        let config = self.origins
+            .get(origin)
+            .ok_or_else(|| PaymasterError::InvalidParameters(format!("Unknown origin {}", origin)))?;
+
+        let key_bound = context.api_key.as_ref().is_some_and(|api_key| config.api_keys.contains(api_key));
+        let signed = match (&context.origin_signature, config.signer) {
+            (Some(signature), Some(signer)) => Signature::try_from(signature.as_ref())
+                .ok()
+                .and_then(|signature| signature.recover(hash_message(digest(self.chain_id, user_op, origin))).ok())
+                .is_some_and(|recovered| recovered == signer),
+            _ => false,
+        };
+        match key_bound || signed {
+            true => Ok(Some(origin.clone())),
+            false => Err(PaymasterError::InvalidParameters(format!("Origin {} could not be verified", origin))),
+        }
+    }
+}
+
+// keccak256(abi.encode(keccak256(ORIGIN_TYPE), chainId, sender, nonce, keccak256(origin)))
+pub fn digest(chain_id: u64, user_op: &UserOperation, origin: &str) -> H256 {
+    H256::from(keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(ORIGIN_TYPE).to_vec()),
+        Token::Uint(chain_id.into()),
+        Token::Address(user_op.sender),
+        Token::Uint(user_op.nonce),
+        Token::FixedBytes(keccak256(origin).to_vec()),
+    ])))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::signers::{LocalWallet, Signer};
+    use ethers::types::U256;
+
+    use super::*;
+    use crate::self_test::synthetic_user_op;
+
+    fn user_op() -> UserOperation {
+        UserOperation {
+            sender: "0x1111111111111111111111111111111111111111".parse().unwrap(),
+            nonce: U256::from(7),
+            ..synthetic_user_op()
+        }
+    }
+
+    fn registry(signer: Option<&LocalWallet>) -> OriginRegistry {
+        let config = OriginConfig {
+            api_keys: vec!["dapp-key".to_string()],
+            signer: signer.map(Signer::address),
+        };
+        OriginRegistry::new(1, HashMap::from([("app.example".to_string(), config)]))
+    }
+
+    fn context(api_key: Option<&str>, origin_signature: Option<Signature>) -> SponsorshipContext {
+        SponsorshipContext {
+            api_key: api_key.map(str::to_string),
+            origin: Some("app.example".to_string()),
+            origin_signature: origin_signature.map(|signature| signature.to_vec().into()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn digest_binds_chain_sender_nonce_and_origin() {
+        let expected: H256 = "0xa7c39e16d3bb06b5ac801dcd0b7c5f3a5b9f082ef223999df4fb61d17cdc1578".parse().unwrap();
+        assert_eq!(digest(1, &user_op(), "app.example"), expected);
+        let expected: H256 = "0xf57afe972c3fced5e925a8f523bd156fe1ff441c89e3d5d4467801f832e4e676".parse().unwrap();
+        assert_eq!(digest(137, &user_op(), "app.example"), expected);
+    }
+
+    #[test]
+    fn verifies_origins_by_api_key_or_signature() {
+        let signer: LocalWallet = "0x0000000000000000000000000000000000000000000000000000000000000001".parse().unwrap();
+        let other: LocalWallet = "0x0000000000000000000000000000000000000000000000000000000000000002".parse().unwrap();
+        let sign = |wallet: &LocalWallet, user_op: &UserOperation| {
+            wallet.sign_hash(hash_message(digest(1, user_op, "app.example"))).unwrap()
+        };
+        let registry = registry(Some(&signer));
+
+        let verified = |context: SponsorshipContext| registry.verify(&user_op(), &context);
+        assert_eq!(verified(context(Some("dapp-key"), None)).unwrap().as_deref(), Some("app.example"));
+        assert_eq!(verified(context(None, Some(sign(&signer, &user_op())))).unwrap().as_deref(), Some("app.example"));
+        assert_eq!(verified(SponsorshipContext::default()).unwrap(), None);
+
+        assert!(verified(context(Some("other-key"), None)).is_err());
+        assert!(verified(context(None, Some(sign(&other, &user_op())))).is_err());
+        let replayed = UserOperation { nonce: U256::from(8), ..user_op() };
+        assert!(verified(context(None, Some(sign(&signer, &replayed)))).is_err());
+        assert!(verified(SponsorshipContext { origin: Some("unknown.example".to_string()), ..context(Some("dapp-key"), None) }).is_err());
+    }
+}
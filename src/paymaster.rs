@@ -1,253 +1,1912 @@
 // src/paymaster.rs
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::sync::Arc;
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
+use chrono::NaiveDate;
 use ethers::prelude::*;
-use ethers::signers::{LocalWallet, Signer};
-use ethers::utils::keccak256;
-use tracing::{debug, error, info};
+use ethers::utils::{hash_message, keccak256};
+use tracing::{debug, error, field, info, warn, Span};
 
+use crate::approval::{ApprovalQueue, Ticket, TicketStatus};
+use crate::audit::{AuditEntry, AuditLog, Decision};
+use crate::bundler::Bundler;
+use crate::cache::TtlCache;
+use crate::config::{
+    CacheConfig, DegradationConfig, FeesConfig, GasPriceBuffer, LimitsConfig, MaintenanceConfig, OriginConfig, PaymasterGasConfig, PolicyConfig, SessionConfig, SimulationConfig, TokenConfig, ValidityConfig,
+};
+use crate::contracts::{self, AccountFactory, EntryPoint, EntryPointVersion, TokenChargedFilter, TokenPaymaster, UserOperationEventFilter, VerifyingPaymaster};
+use crate::decoding;
+use crate::drift::SyncState;
 use crate::error::PaymasterError;
-use crate::types::{PaymasterAndData, PaymasterResponse, UserOperation, ValidationResult};
+use crate::maintenance::{Maintenance, MaintenanceStatus};
+use crate::origin::OriginRegistry;
+use crate::policy::PolicyRegistry;
+use crate::project::{self, Project, ProjectRegistry};
+use crate::provider::{self, EthProvider};
+use crate::replay::{ReplayCapture, ReplayRecord};
+use crate::report::{self, Period, ReportRow};
+use crate::revert::{self, Revert};
+use crate::rotation::{KeyRotation, RotationStatus};
+use crate::self_test;
+use crate::session::{SessionGrant, SessionGrantRequest, SessionRegistry};
+use crate::shard::{Shard, ShardSet};
+use crate::signer::PaymasterSigner;
+use crate::store::{MemoryStore, OperationState, OperationStatus, SponsorshipRecord, SponsorshipStore, TokenDebt, Transfer};
+use crate::token::{self, TokenRegistry};
+use crate::types::{
+    Capabilities, DashboardSummary, DepositStatus, EntryPointInfo, HealthAttestation, HealthResponse, OperatingTier, PaymasterAndData, PaymasterGasLimits, PaymasterMode,
+    PaymasterResponse, RejectionCount, SignerStatus, SponsorshipCheck, SponsorshipContext, SponsorshipQuota, SponsorshipReceipt, TokenQuote, UserOperation,
+};
+use crate::user_op_hash;
+
+// Decisions kept in memory for the dashboard
+const RECENT_DECISIONS: usize = 200;
+
+// Replay set of the sender and nonce of every included operation; the nonce cannot be used again
+const INCLUDED_NONCES: &str = "included_nonces";
+// How long an included nonce is remembered, in seconds
+const INCLUDED_NONCE_RETENTION: u64 = 86_400;
+// Sender and nonce of every signed operation, which may be signed again while the deposit is low
+const SIGNED_OPERATIONS: &str = "signed_operations";
+// How long past its validUntil a signed operation may still be renewed, in seconds
+const SIGNED_OPERATION_RETENTION: u64 = 86_400;
+// How long past its validUntil an operation is still looked for before it is reported failed, in
+// seconds; bundlers may take a moment to report an inclusion
+const RECEIPT_GRACE: u64 = 120;
+// Operations whose validUntil passed longer ago are no longer polled, in seconds
+const RECEIPT_POLL_WINDOW: u64 = 86_400;
+// Most blocks scanned for included operations at a time, while catching up after a restart
+const SCAN_RANGE: u64 = 2_000;
 
 pub struct Paymaster {
-    wallet: LocalWallet,
-    client: Arc<Provider<Http>>,
+    shards: ShardSet,
+    entry_point: EntryPoint<EthProvider>,
+    entry_point_version: EntryPointVersion,
     pub paymaster_address: Address,
     chain_id: u64,
     // Configuration parameters
     valid_duration: u64, // The validity time window in seconds
-    gas_price_buffer: u64, // Buffer percentage for gas price
+    limits: LimitsConfig,
+    paymaster_gas: PaymasterGasConfig,
+    fees: FeesConfig,
+    validity: ValidityConfig,
+    sync: Arc<SyncState>,
+    simulation: SimulationConfig,
+    degradation: DegradationConfig,
+    // Tier the deposits were at when last read
+    tier: Mutex<OperatingTier>,
+    maintenance: Maintenance,
+    policies: PolicyRegistry,
+    origins: OriginRegistry,
+    store: Arc<dyn SponsorshipStore>,
+    tokens: TokenRegistry,
+    cache_ttl: Duration,
+    deposit_cache: TtlCache<Address, U256>,
+    // Keyed by the number of blocks and the bits of the reward percentile
+    fee_history_cache: TtlCache<(u64, Option<u64>), FeeHistory>,
+    block_time_cache: TtlCache<(), u64>,
+    verifying_signer_cache: TtlCache<Address, Address>,
+    // Account implementation each factory deploys
+    account_implementation_cache: TtlCache<Address, Address>,
+    rotation: Option<KeyRotation>,
+    audit_log: Option<AuditLog>,
+    replay_capture: Option<ReplayCapture>,
+    approvals: Option<ApprovalQueue>,
+    projects: Option<ProjectRegistry>,
+    // Polled for the receipts of sponsored operations
+    bundler: Option<Bundler>,
+    sessions: SessionRegistry,
+    // Sign with a throwaway key and flag every response, for staging and load tests
+    dry_run: bool,
+    // Latest decisions, newest last
+    recent_decisions: Mutex<VecDeque<AuditEntry>>,
+    // Last attestation signed without a challenge, served again until the next block
+    attestation: Mutex<Option<HealthAttestation>>,
+}
+
+// What the checks ahead of signing found out about an operation
+struct Preflight {
+    max_cost: U256,
+    // Max cost at the gas price strategy's price, charged to the policy budget
+    budget_cost: U256,
+    gas_limits: Option<PaymasterGasLimits>,
+    // Value the operation moves, counted against the policy's daily limits once it is signed
+    transfers: Vec<Transfer>,
+    deposits: Vec<U256>,
+    token_quote: Option<TokenQuote>,
+    // Paymaster the deposit check picked, which token quotes are approved for
+    paymaster: Address,
+    now: u64,
+}
+
+// Result of a successful sponsorship, with the details needed for auditing
+struct Sponsorship {
+    response: PaymasterResponse,
+    signer: Address,
+    signature: Bytes,
+    max_cost: U256,
+    valid_after: u64,
+    valid_until: u64,
+    aggregator: Option<Address>,
 }
 
 impl Paymaster {
     pub async fn new(
-        private_key: String,
+        signer: Arc<dyn PaymasterSigner>,
         chain_id: u64,
-        eth_rpc_url: String,
+        provider: EthProvider,
+        entry_point: Address,
+        paymaster_address: Address,
     ) -> Result<Self> {
-        // Create the wallet from private key
-        let wallet = private_key.parse::<LocalWallet>()?.with_chain_id(chain_id);
-        
         // Create Ethereum client
-        let provider = Provider::<Http>::try_from(eth_rpc_url)?;
         let client = Arc::new(provider);
+        let entry_point = EntryPoint::new(entry_point, client.clone());
+        let cache_ttl = Duration::from_millis(CacheConfig::default().ttl_ms);
+        let store: Arc<dyn SponsorshipStore> = Arc::new(MemoryStore::default());
         
-        // Get the paymaster address from the wallet
-        let paymaster_address = wallet.address();
-        
-        info!("Initialized paymaster with address: {}", paymaster_address);
+        info!(
+            "Initialized paymaster {} with signer {} on EntryPoint {}",
+            paymaster_address, signer.address(), entry_point.address(),
+        );
         
         Ok(Self {
-            wallet,
-            client,
+            shards: ShardSet::new(Shard { paymaster_address, signer }),
+            entry_point,
+            entry_point_version: EntryPointVersion::default(),
             paymaster_address,
             chain_id,
             valid_duration: 3600, // Default 1 hour validity
-            gas_price_buffer: 10,  // Default 10% buffer
+            limits: LimitsConfig::default(),
+            paymaster_gas: PaymasterGasConfig::default(),
+            fees: FeesConfig::default(),
+            validity: ValidityConfig::default(),
+            sync: Arc::new(SyncState::default()),
+            simulation: SimulationConfig::default(),
+            degradation: DegradationConfig::default(),
+            tier: Mutex::new(OperatingTier::Normal),
+            maintenance: Maintenance::default(),
+            policies: PolicyRegistry::new(HashMap::new(), store.clone()),
+            origins: OriginRegistry::new(chain_id, HashMap::new()),
+            store,
+            tokens: TokenRegistry::new(HashMap::new(), client, cache_ttl),
+            cache_ttl,
+            deposit_cache: TtlCache::new(cache_ttl),
+            fee_history_cache: TtlCache::new(cache_ttl),
+            block_time_cache: TtlCache::new(cache_ttl),
+            verifying_signer_cache: TtlCache::new(cache_ttl),
+            account_implementation_cache: TtlCache::new(cache_ttl),
+            rotation: None,
+            audit_log: None,
+            replay_capture: None,
+            approvals: None,
+            projects: None,
+            bundler: None,
+            sessions: SessionRegistry::new(SessionConfig::default()),
+            dry_run: false,
+            recent_decisions: Mutex::new(VecDeque::with_capacity(RECENT_DECISIONS)),
+            attestation: Mutex::new(None),
         })
     }
     
-    // Sign a user operation to sponsor it
-    pub async fn sign_user_operation(&self, user_op: &UserOperation) -> Result<PaymasterResponse, PaymasterError> {
-        // 1. Validate the user operation
-        self.validate_user_operation(user_op).await?;
-        
-        // 2. Calculate the gas cost and check if we can afford it
-        let max_cost = self.calculate_max_cost(user_op)?;
-        
-        // 3. Check if the paymaster has enough funds
-        self.check_paymaster_balance(max_cost).await?;
+    // Release of the EntryPoint, which decides how user operations are hashed
+    pub fn with_entry_point_version(mut self, version: EntryPointVersion) -> Self {
+        self.entry_point_version = version;
+        self
+    }
+    
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+    
+    pub fn with_limits(mut self, limits: LimitsConfig) -> Self {
+        self.limits = limits;
+        self
+    }
+    
+    pub fn with_paymaster_gas(mut self, paymaster_gas: PaymasterGasConfig) -> Self {
+        self.paymaster_gas = paymaster_gas;
+        self
+    }
+    
+    pub fn with_fees(mut self, fees: FeesConfig) -> Self {
+        self.fees = fees;
+        self
+    }
+    
+    pub fn with_validity(mut self, validity: ValidityConfig) -> Self {
+        self.validity = validity;
+        self
+    }
+    
+    // Sync status of the provider as followed by the drift monitor
+    pub fn with_sync_state(mut self, sync: Arc<SyncState>) -> Self {
+        self.sync = sync;
+        self
+    }
+    
+    pub fn with_simulation(mut self, simulation: SimulationConfig) -> Self {
+        self.simulation = simulation;
+        self
+    }
+    
+    pub fn with_degradation(mut self, degradation: DegradationConfig) -> Self {
+        self.degradation = degradation;
+        self
+    }
+    
+    pub fn with_maintenance(mut self, maintenance: &MaintenanceConfig) -> Self {
+        self.maintenance = Maintenance::new(maintenance);
+        self
+    }
+    
+    pub fn with_policies(mut self, policies: HashMap<String, PolicyConfig>) -> Self {
+        self.policies = PolicyRegistry::new(policies, self.store.clone());
+        self
+    }
+    
+    pub fn with_origins(mut self, origins: HashMap<String, OriginConfig>) -> Self {
+        self.origins = OriginRegistry::new(self.chain_id, origins);
+        self
+    }
+    
+    // Keep budgets, reservations and the sponsorship history somewhere other than in memory
+    pub fn with_store(mut self, store: Arc<dyn SponsorshipStore>) -> Self {
+        self.policies.set_store(store.clone());
+        self.store = store;
+        self
+    }
+    
+    pub fn with_tokens(mut self, tokens: HashMap<String, TokenConfig>) -> Self {
+        self.tokens = TokenRegistry::new(tokens, self.entry_point.client(), self.cache_ttl);
+        self
+    }
+    
+    pub fn with_cache(mut self, cache: CacheConfig) -> Self {
+        self.cache_ttl = Duration::from_millis(cache.ttl_ms);
+        self.deposit_cache = TtlCache::new(self.cache_ttl);
+        self.fee_history_cache = TtlCache::new(self.cache_ttl);
+        self.block_time_cache = TtlCache::new(self.cache_ttl);
+        self.verifying_signer_cache = TtlCache::new(self.cache_ttl);
+        self.account_implementation_cache = TtlCache::new(self.cache_ttl);
+        self.tokens.set_price_ttl(self.cache_ttl);
+        self
+    }
+    
+    // Spread sponsorships across further funded paymaster contracts
+    pub fn with_shards(mut self, shards: Vec<Shard>) -> Self {
+        for shard in shards {
+            info!("Sharding sponsorships to paymaster {} with signer {}", shard.paymaster_address, shard.signer.address());
+            self.shards.push(shard);
+        }
+        self
+    }
+    
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
+    
+    pub fn with_replay_capture(mut self, replay_capture: ReplayCapture) -> Self {
+        self.replay_capture = Some(replay_capture);
+        self
+    }
+    
+    pub fn with_approval_queue(mut self, approvals: ApprovalQueue) -> Self {
+        self.approvals = Some(approvals);
+        self
+    }
+    
+    // Serve the policies of projects managed through the admin API and kept in the store
+    pub fn with_projects(mut self) -> Self {
+        self.projects = Some(ProjectRegistry::new(self.chain_id));
+        self
+    }
+    
+    // Track sponsored operations through the chain's bundler
+    pub fn with_bundler(mut self, bundler: Bundler) -> Self {
+        self.bundler = Some(bundler);
+        self
+    }
+    
+    // Standby key that a rotation moves the primary signer's paymasters to
+    pub fn with_next_signer(mut self, next: Arc<dyn PaymasterSigner>) -> Self {
+        let current = self.shards.iter().next().map(|shard| shard.signer.address()).unwrap_or_default();
+        self.rotation = Some(KeyRotation::new(current, next));
+        self
+    }
+    
+    pub fn with_sessions(mut self, sessions: SessionConfig) -> Self {
+        self.sessions = SessionRegistry::new(sessions);
+        self
+    }
+    
+    // Finish writing audit entries before the process exits
+    pub async fn close(&self) {
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.close().await;
+        }
+    }
+    
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+    
+    pub fn user_op_hash(&self, user_op: &UserOperation) -> H256 {
+        user_op_hash::hash(user_op, self.entry_point_version, self.entry_point.address(), self.chain_id)
+    }
+    
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            chain_ids: vec![self.chain_id],
+            entry_points: vec![EntryPointInfo {
+                address: self.entry_point.address(),
+                version: self.entry_point_version.to_string(),
+            }],
+            paymaster: self.paymaster_address,
+            modes: match self.tokens.is_empty() {
+                true => vec![PaymasterMode::Verifying],
+                false => vec![PaymasterMode::Verifying, PaymasterMode::Token],
+            },
+            tokens: self.tokens.addresses(),
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+    
+    // Report whether the paymaster can currently sign
+    pub fn health(&self) -> HealthResponse {
+        let mut signers: Vec<_> = self.shards.iter().flat_map(|shard| shard.signer.status()).collect();
+        let healthy = signers.iter().any(|signer| signer.healthy);
+        // The standby key is reported so it can be checked before rotating to it
+        if let Some(rotation) = &self.rotation {
+            signers.extend(rotation.next().status().into_iter().map(|status| SignerStatus { primary: false, ..status }));
+        }
+        HealthResponse {
+            healthy,
+            signers,
+            dry_run: self.dry_run,
+            tier: *self.tier.lock().unwrap(),
+            maintenance: self.maintenance.status().enabled,
+        }
+    }
+    
+    // Sign a synthetic operation with every shard, as a sponsorship would, and check that the
+    // paymasterAndData decodes back to what was signed and that its signature recovers to the
+    // signer over the hash rebuilt from the decoded fields. With `on_chain`, each paymaster
+    // contract must also accept it in validatePaymasterUserOp, called from the EntryPoint.
+    pub async fn self_test(&self, on_chain: bool) -> Result<(), PaymasterError> {
+        let user_op = self_test::synthetic_user_op();
+        let gas_limits = self.paymaster_gas_limits(None);
+        let now = unix_time()?;
         
-        // 4. Create time-range for paymaster validity
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map_err(|e| PaymasterError::InvalidParameters(e.to_string()))?
-            .as_secs();
+        for shard in self.shards.iter() {
+            // The contract only accepts the key it verifies with, which may be the next one mid-rotation
+            let (shard, valid_until) = match on_chain {
+                true => self.signing_key(shard, now + self.valid_duration, now).await?,
+                false => (Shard { paymaster_address: shard.paymaster_address, signer: shard.signer.clone() }, now + self.valid_duration),
+            };
+            let mut paymaster_data = PaymasterAndData {
+                paymaster: shard.paymaster_address,
+                gas_limits,
+                valid_until,
+                valid_after: now,
+                token_quote: None,
+                signature: Bytes::default(),
+            };
+            let signer;
+            (paymaster_data.signature, signer) = self.sign_paymaster_data(&shard, &user_op, &paymaster_data).await?;
+            let encoded = paymaster_data.encode();
             
-        let valid_until = now + self.valid_duration;
-        let valid_after = now;
+            let decoded = self_test::decode_paymaster_data(&encoded, gas_limits.is_some())?;
+            if decoded != paymaster_data {
+                return Err(PaymasterError::SelfTestFailed(format!(
+                    "paymasterAndData of {:?} decodes to {:?}, signed {:?}", shard.paymaster_address, decoded, paymaster_data
+                )));
+            }
+            // ERC-1271 signature blobs cannot be recovered; the contract checks those
+            let hash = user_op_hash::paymaster_hash(&user_op, self.entry_point_version, self.chain_id, &decoded);
+            let recovered = Signature::try_from(decoded.signature.as_ref())
+                .ok()
+                .map(|signature| signature.recover(hash_message(hash)));
+            if let Some(recovered) = recovered.filter(|recovered| recovered.as_ref().ok() != Some(&signer)) {
+                return Err(PaymasterError::SelfTestFailed(format!(
+                    "Signature of {:?} recovers to {:?}, signed by {:?}", shard.paymaster_address, recovered, signer
+                )));
+            }
+            
+            if on_chain {
+                // The EntryPoint passes the hash of the operation as submitted, with the signed
+                // paymasterAndData in place
+                let submitted = UserOperation { paymaster_and_data: encoded, ..user_op.clone() };
+                let user_op_hash = self.user_op_hash(&submitted);
+                let call = TransactionRequest::new()
+                    .from(self.entry_point.address())
+                    .to(shard.paymaster_address)
+                    .data(self_test::validate_paymaster_call(self.entry_point_version, &submitted, user_op_hash));
+                let output = self.entry_point.client()
+                    .call(&call.into(), None)
+                    .await
+                    .map_err(|e| match RpcError::as_error_response(&e).and_then(|response| response.as_revert_data()) {
+                        Some(data) => PaymasterError::SelfTestFailed(format!(
+                            "validatePaymasterUserOp of {:?} reverted: {}", shard.paymaster_address, revert::decode(&data)
+                        )),
+                        None => provider::call_error(self.entry_point.client_ref(), e),
+                    })?;
+                self_test::check_validation_data(&output, valid_until, now)?;
+            }
+            debug!("Self-test passed for paymaster {:?} signed by {:?}", shard.paymaster_address, signer);
+        }
         
-        // 5. Create the paymaster data
-        let paymaster_data = PaymasterAndData {
-            paymaster: self.paymaster_address,
-            valid_until,
-            valid_after,
-            signature: Bytes::default(), // Will be replaced with the actual signature
+        Ok(())
+    }
+    
+    // Sign the latest block, the primary paymaster's deposit and the software version with the
+    // primary signer, so a verifier can tell the paymaster's own key was live at `timestamp`
+    pub async fn attest(&self, challenge: Option<H256>) -> Result<HealthAttestation, PaymasterError> {
+        let client = self.entry_point.client();
+        let block = client.get_block(BlockNumber::Latest)
+            .await
+            .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))?
+            .ok_or_else(|| PaymasterError::EthereumProviderError("Latest block not found".to_string()))?;
+        let (Some(block_number), Some(block_hash)) = (block.number, block.hash) else {
+            return Err(PaymasterError::EthereumProviderError("Latest block has no number or hash".to_string()));
         };
+        // Without a challenge the answer only changes per block, so it is signed once per block
+        if challenge.is_none() {
+            let cached = self.attestation.lock().unwrap().clone();
+            if let Some(attestation) = cached.filter(|a| a.block_number == block_number.as_u64() && a.block_hash == block_hash) {
+                return Ok(attestation);
+            }
+        }
+        let shard = self.shards.iter().next()
+            .ok_or_else(|| PaymasterError::InvalidParameters("No paymaster configured".to_string()))?;
+        let deposit = self.paymaster_deposits().await?.first().copied().unwrap_or_default();
         
-        // 6. Hash and sign the paymaster data
-        let signature = self.sign_paymaster_data(user_op, valid_until, valid_after).await?;
+        let mut attestation = HealthAttestation {
+            chain_id: self.chain_id,
+            paymaster: shard.paymaster_address,
+            block_number: block_number.as_u64(),
+            block_hash,
+            deposit,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            timestamp: unix_time()?,
+            challenge: challenge.unwrap_or_default(),
+            signer: Address::zero(),
+            signature: Bytes::default(),
+        };
+        (attestation.signature, attestation.signer) = sign_hash(shard, attestation.digest()).await?;
+        if challenge.is_none() {
+            *self.attestation.lock().unwrap() = Some(attestation.clone());
+        }
+        Ok(attestation)
+    }
+    
+    pub fn maintenance_status(&self) -> MaintenanceStatus {
+        self.maintenance.status()
+    }
+    
+    // Turn maintenance mode on or off; the ETA is only kept while it is on
+    pub fn set_maintenance(&self, enabled: bool, eta: Option<u64>) -> MaintenanceStatus {
+        let status = self.maintenance.set(enabled, eta);
+        match enabled {
+            true => warn!("Maintenance mode on, serving only allowlisted API keys and senders"),
+            false => info!("Maintenance mode off"),
+        }
+        status
+    }
+    
+    // Deposits, signer health, budget consumption and recent rejections at a glance
+    pub async fn dashboard(&self) -> Result<DashboardSummary, PaymasterError> {
+        let deposits = self.paymaster_deposits().await?;
+        let tier = self.update_tier(&deposits);
+        let deposits = self.shards.iter()
+            .zip(deposits)
+            .zip(self.shards.in_flight())
+            .map(|((shard, deposit), in_flight)| DepositStatus {
+                paymaster: shard.paymaster_address,
+                deposit,
+                in_flight: self.shards.is_sharded().then_some(in_flight),
+            })
+            .collect();
         
-        // 7. Encode the paymaster data with the signature
-        let paymaster_and_data = self.encode_paymaster_data(valid_until, valid_after, signature)?;
+        // Errors read "<kind>: <details>"; the details rarely repeat, so only the kind is counted
+        let mut rejections: Vec<RejectionCount> = Vec::new();
+        for entry in self.recent_decisions.lock().unwrap().iter().filter(|entry| entry.decision == Decision::Rejected) {
+            let reason = entry.reason.as_deref().unwrap_or_default();
+            let kind = reason.split_once(": ").map_or(reason, |(kind, _)| kind);
+            match rejections.iter_mut().find(|rejection| rejection.reason == kind) {
+                Some(rejection) => rejection.count += 1,
+                None => rejections.push(RejectionCount { reason: kind.to_string(), count: 1 }),
+            }
+        }
+        rejections.sort_by_key(|rejection| Reverse(rejection.count));
         
-        Ok(PaymasterResponse {
-            paymaster_and_data,
+        Ok(DashboardSummary {
+            chain_id: self.chain_id,
+            tier,
+            deposits,
+            signers: self.health().signers,
+            policies: self.policies.usage(unix_time()?).await?,
+            rejections,
         })
     }
     
-    // Validate the user operation
-    async fn validate_user_operation(&self, user_op: &UserOperation) -> Result<(), PaymasterError> {
-        // Basic validation checks
-        if user_op.max_fee_per_gas.is_zero() || user_op.max_priority_fee_per_gas.is_zero() {
-            return Err(PaymasterError::InvalidUserOperation("Gas price cannot be zero".to_string()));
+    // Latest sponsorship decisions, newest first
+    pub fn recent_decisions(&self) -> Vec<AuditEntry> {
+        self.recent_decisions.lock().unwrap().iter().rev().cloned().collect()
+    }
+    
+    // Sign a user operation to sponsor it, recording the decision in the audit log
+    pub async fn sign_user_operation(
+        &self,
+        user_op: &UserOperation,
+        context: &SponsorshipContext,
+    ) -> Result<PaymasterResponse, PaymasterError> {
+        self.check_fields(user_op)?;
+        self.process_user_operation(user_op, context, true).await
+    }
+    
+    // Run the checks of a sponsorship without reserving budget or signing, so callers can tell
+    // whether the operation would be sponsored and on what terms
+    pub async fn check_user_operation(
+        &self,
+        user_op: &UserOperation,
+        context: &SponsorshipContext,
+    ) -> Result<SponsorshipCheck, PaymasterError> {
+        self.check_fields(user_op)?;
+        self.maintenance.check(Some(user_op.sender), context)?;
+        let origin = self.origins.verify(user_op, context)?;
+        let policy_id = self.policies.resolve(context)?;
+        if let Some(policy_id) = &policy_id {
+            self.policies.check_origin(policy_id, origin.as_deref())?;
+        }
+        let preflight = self.preflight(user_op, policy_id.as_deref(), context.token).await?;
+        Ok(SponsorshipCheck {
+            policy_id,
+            max_cost: preflight.max_cost,
+            token_quote: preflight.token_quote,
+        })
+    }
+    
+    // Open a session whose operations are charged to the policy selected by the context
+    pub fn create_session_grant(
+        &self,
+        request: SessionGrantRequest,
+        context: &SponsorshipContext,
+    ) -> Result<SessionGrant, PaymasterError> {
+        self.maintenance.check(Some(request.sender), context)?;
+        let policy_id = self.policies.resolve(context)?;
+        let grant = self.sessions.mint(request, policy_id, unix_time()?)?;
+        info!(
+            "Opened session for {} with {} operations until {}",
+            grant.sender, grant.max_ops, grant.expires_at,
+        );
+        Ok(grant)
+    }
+    
+    pub fn revoke_session_grant(&self, session_token: &str) -> bool {
+        self.sessions.revoke(session_token)
+    }
+    
+    // Start moving to the standby key. Signatures by the current key are capped at the end of the
+    // overlap window, which must outlast the validity window of those already handed out.
+    pub async fn start_key_rotation(&self, overlap_secs: Option<u64>) -> Result<RotationStatus, PaymasterError> {
+        let rotation = self.key_rotation()?;
+        let overlap_secs = overlap_secs.unwrap_or(2 * self.valid_duration);
+        if overlap_secs <= self.valid_duration {
+            return Err(PaymasterError::InvalidParameters(format!(
+                "The overlap must be longer than the {}s validity window", self.valid_duration
+            )));
         }
+        let overlap_until = self.chain_time().await? + overlap_secs;
+        rotation.start(overlap_until)?;
+        info!(
+            "Started key rotation to {:?}; the current key is accepted until {}",
+            rotation.next().address(), overlap_until,
+        );
+        self.key_rotation_status()
+    }
+    
+    pub fn cancel_key_rotation(&self) -> Result<RotationStatus, PaymasterError> {
+        self.key_rotation()?.cancel()?;
+        info!("Cancelled key rotation");
+        self.key_rotation_status()
+    }
+    
+    pub fn key_rotation_status(&self) -> Result<RotationStatus, PaymasterError> {
+        let rotation = self.key_rotation()?;
+        let paymasters: Vec<Address> = self.shards
+            .iter()
+            .filter(|shard| rotation.replaces(shard.signer.address()))
+            .map(|shard| shard.paymaster_address)
+            .collect();
+        let transaction_data = rotation.overlap_until().and_then(|overlap_until| {
+            VerifyingPaymaster::new(self.paymaster_address, self.entry_point.client())
+                .rotate_verifying_signer(rotation.next().address(), overlap_until)
+                .calldata()
+        });
+        Ok(rotation.status(&paymasters, transaction_data))
+    }
+    
+    // What the policy selected by the context can still sponsor today
+    pub async fn sponsorship_quota(&self, context: &SponsorshipContext) -> Result<SponsorshipQuota, PaymasterError> {
+        self.maintenance.check(None, context)?;
+        let policy_id = self.policies.resolve(context)?.ok_or_else(|| {
+            PaymasterError::InvalidParameters("No sponsorship policy is configured".to_string())
+        })?;
+        self.policies.quota(&policy_id, unix_time()?).await
+    }
+    
+    // Sponsored spend aggregated from the audit log
+    pub async fn spend_report(
+        &self,
+        period: Period,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> Result<Vec<ReportRow>, PaymasterError> {
+        let audit_log = self.audit_log.as_ref().ok_or(PaymasterError::UnsupportedOperation)?;
+        let entries = audit_log.entries(since.map(report::day_start).unwrap_or_default())
+            .await
+            .map_err(|e| PaymasterError::AuditLogError(e.to_string()))?;
         
-        // Add more validation as needed
-        // ...
+        Ok(report::aggregate(&entries, period, since, until))
+    }
+    
+    // Tickets waiting for an operator decision
+    pub fn pending_approvals(&self) -> Result<Vec<Ticket>, PaymasterError> {
+        Ok(self.approval_queue()?.pending(unix_time()?))
+    }
+    
+    // Current state of a ticket, polled by the original caller
+    pub fn sponsorship_ticket(&self, ticket_id: &str) -> Result<Ticket, PaymasterError> {
+        self.approval_queue()?
+            .get(ticket_id, unix_time()?)
+            .ok_or_else(|| PaymasterError::TicketNotFound(ticket_id.to_string()))
+    }
+    
+    // Sign a parked operation on behalf of an operator
+    pub async fn approve_ticket(&self, ticket_id: &str) -> Result<Ticket, PaymasterError> {
+        let approvals = self.approval_queue()?;
+        let ticket = approvals.begin_processing(ticket_id, unix_time()?)?;
         
-        Ok(())
+        let context = SponsorshipContext {
+            sponsorship_policy_id: ticket.policy_id.clone(),
+            token: ticket.token,
+            origin: ticket.origin.clone(),
+            ..Default::default()
+        };
+        
+        let status = match self.process_user_operation(&ticket.user_op, &context, false).await {
+            Ok(response) => {
+                info!("Operator approved ticket {}", ticket_id);
+                TicketStatus::Approved(Box::new(response))
+            }
+            Err(e) => {
+                warn!("Ticket {} approved by an operator was rejected: {}", ticket_id, e);
+                TicketStatus::Rejected {
+                    reason: e.to_string(),
+                }
+            }
+        };
+        
+        approvals.resolve(ticket_id, status)
+            .ok_or_else(|| PaymasterError::TicketNotFound(ticket_id.to_string()))
     }
     
-    // Calculate the maximum cost of the operation
-    fn calculate_max_cost(&self, user_op: &UserOperation) -> Result<U256, PaymasterError> {
-        // Calculate gas limit: callGasLimit + verificationGasLimit + preVerificationGas
-        let total_gas = user_op.call_gas_limit
-            .checked_add(user_op.verification_gas_limit)
-            .and_then(|sum| sum.checked_add(user_op.pre_verification_gas))
-            .ok_or_else(|| PaymasterError::InvalidUserOperation("Gas limit overflow".to_string()))?;
-            
-        // Apply buffer to gas price
-        let buffered_gas_price = user_op.max_fee_per_gas
-            .checked_mul(U256::from(100 + self.gas_price_buffer))
-            .and_then(|product| product.checked_div(U256::from(100)))
-            .ok_or_else(|| PaymasterError::InvalidUserOperation("Gas price calculation error".to_string()))?;
+    // Decline a parked operation on behalf of an operator
+    pub async fn reject_ticket(&self, ticket_id: &str, reason: Option<String>) -> Result<Ticket, PaymasterError> {
+        let approvals = self.approval_queue()?;
+        let ticket = approvals.begin_processing(ticket_id, unix_time()?)?;
+        let rejection = PaymasterError::RejectedByOperator(reason.unwrap_or_default());
+        record_span_fields(&ticket.user_op, ticket.user_op_hash, ticket.policy_id.as_deref());
+        
+        self.record_decision(
+            &ticket.user_op,
+            ticket.user_op_hash,
+            ticket.policy_id.clone(),
+            ticket.token,
+            ticket.origin.clone(),
+            &Err(rejection.clone()),
+        ).await?;
+        
+        info!("Operator rejected ticket {}", ticket_id);
+        approvals.resolve(ticket_id, TicketStatus::Rejected { reason: rejection.to_string() })
+            .ok_or_else(|| PaymasterError::TicketNotFound(ticket_id.to_string()))
+    }
+    
+    // Follow the EntryPoint's UserOperationEvent logs, turning policy reservations into the
+    // actual cost of included operations and releasing those whose signature expired
+    pub async fn run_inclusion_tracker(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.track_inclusions().await {
+                warn!("Failed to check for included operations: {}", e);
+            }
+        }
+    }
+    
+    async fn track_inclusions(&self) -> Result<(), PaymasterError> {
+        let client = self.entry_point.client();
+        let block = client.get_block(BlockNumber::Latest)
+            .await
+            .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))?
+            .ok_or_else(|| PaymasterError::EthereumProviderError("Latest block not found".to_string()))?;
+        let latest = block.number
+            .ok_or_else(|| PaymasterError::EthereumProviderError("Latest block has no number".to_string()))?;
+        
+        // Scanning picks up from the block the store last recorded, so operations included while
+        // the server was down are still settled. Missed blocks are caught up on a range at a time.
+        let from_block = self.store.scanned_block().await?.map(|block| U64::from(block) + 1).unwrap_or(latest);
+        let to_block = latest.min(from_block + SCAN_RANGE - 1);
+        let block = match to_block == latest {
+            true => block,
+            false => client.get_block(to_block)
+                .await
+                .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))?
+                .ok_or_else(|| PaymasterError::EthereumProviderError(format!("Block {} not found", to_block)))?,
+        };
+        
+        if from_block <= to_block {
+            let events = self.entry_point.event::<UserOperationEventFilter>()
+                .address(self.entry_point.address().into())
+                .topic3(self.shards.addresses().into_iter().map(H256::from).collect::<Vec<_>>())
+                .from_block(from_block)
+                .to_block(to_block)
+                .query()
+                .await
+                .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))?;
             
-        // Calculate max cost
-        let max_cost = total_gas
-            .checked_mul(buffered_gas_price)
-            .ok_or_else(|| PaymasterError::InvalidUserOperation("Max cost calculation overflow".to_string()))?;
+            let expires_at = block.timestamp.low_u64() + INCLUDED_NONCE_RETENTION;
+            for event in events {
+                self.shards.release(event.sender, event.nonce);
+                // Settling takes the reservation, so an operation another instance sharing the
+                // store settled already is not charged again. The nonce is only marked included
+                // once it is settled and its token debt recorded, so a failed scan is finished
+                // when the blocks are rescanned.
+                if let Some(policy_id) = self.policies.settle(event.sender, event.nonce, event.actual_gas_cost).await? {
+                    info!(
+                        "Operation {:?} from {:?} included, charged {} wei to policy {}",
+                        H256::from(event.user_op_hash), event.sender, event.actual_gas_cost, policy_id,
+                    );
+                }
+                if let Some(mut debt) = self.store.token_debt(event.sender, event.nonce).await? {
+                    debt.owed = Some(token::token_cost(event.actual_gas_cost, debt.exchange_rate, debt.max_token_cost));
+                    debt.user_op_hash = Some(H256::from(event.user_op_hash));
+                    self.store.record_token_debt(&debt).await?;
+                }
+                self.store.insert_replay_key(INCLUDED_NONCES, &operation_key(event.sender, event.nonce), expires_at).await?;
+            }
             
-        Ok(max_cost)
+            // Paymasters charge in postOp, so each charge is logged ahead of its operation's
+            // UserOperationEvent in the same transaction and both fall in the same scan
+            if !self.tokens.is_empty() {
+                self.reconcile_token_charges(from_block, to_block).await?;
+            }
+            self.store.record_scanned_block(to_block.as_u64()).await?;
+        }
+        
+        // The EntryPoint checks validUntil against the block timestamp, so once a block past
+        // it has been scanned the operation can no longer be included
+        let released = self.policies.release_expired(block.timestamp.low_u64()).await?;
+        if released > 0 {
+            info!("Released {} policy reservations for operations that were never included", released);
+        }
+        self.store.prune(block.timestamp.low_u64()).await?;
+        let released = self.shards.release_expired(block.timestamp.low_u64());
+        if released > 0 {
+            info!("Released {} deposit holds for operations that were never included", released);
+        }
+        
+        Ok(())
     }
     
-    // Check if the paymaster has enough balance
-    async fn check_paymaster_balance(&self, max_cost: U256) -> Result<(), PaymasterError> {
-        let balance = self.client.get_balance(self.paymaster_address, None)
+    // Match what the paymasters charged senders paying in tokens against what their operations owe
+    async fn reconcile_token_charges(&self, from_block: U64, to_block: U64) -> Result<(), PaymasterError> {
+        let charges = TokenPaymaster::new(self.paymaster_address, self.entry_point.client())
+            .event::<TokenChargedFilter>()
+            .address(ValueOrArray::Array(self.shards.addresses()))
+            .from_block(from_block)
+            .to_block(to_block)
+            .query()
             .await
-            .map_err(|e| PaymasterError::EthereumProviderError(e.to_string()))?;
+            .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))?;
+        
+        for charge in charges {
+            let Some(mut debt) = self.store.token_debt(charge.sender, charge.nonce).await? else {
+                warn!("Paymaster charged {} of token {:?} to {:?} for an operation it has no debt for", charge.amount, charge.token, charge.sender);
+                continue;
+            };
+            if charge.token != debt.token {
+                warn!("Operation from {:?} was quoted in token {:?} but charged in {:?}", debt.sender, debt.token, charge.token);
+                continue;
+            }
+            debt.collected = Some(charge.amount);
+            self.store.record_token_debt(&debt).await?;
+            if let Some(shortfall) = debt.shortfall() {
+                warn!(
+                    "Operation {:?} from {:?} owed {} of token {:?} but {} was collected, {} short",
+                    debt.user_op_hash.unwrap_or_default(), debt.sender, debt.owed.unwrap_or_default(), debt.token, charge.amount, shortfall,
+                );
+            }
+        }
+        Ok(())
+    }
+    
+    // Ask the bundler now and then what became of the sponsored operations still pending
+    pub async fn run_receipt_poller(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.poll_receipts().await {
+                warn!("Failed to poll the bundler for operation receipts: {}", e);
+            }
+        }
+    }
+    
+    async fn poll_receipts(&self) -> Result<(), PaymasterError> {
+        let Some(bundler) = &self.bundler else {
+            return Ok(());
+        };
+        let now = unix_time()?;
+        let pending = self.store.pending_operations(now.saturating_sub(RECEIPT_POLL_WINDOW)).await?;
+        let total = pending.len();
+        // One operation the bundler fails on does not hold up the others
+        let mut failed = 0;
+        for mut status in pending {
+            let receipt = match bundler.user_operation_receipt(status.user_op_hash).await {
+                Ok(receipt) => receipt,
+                Err(e) => {
+                    debug!("Failed to fetch the receipt of operation {:?}: {}", status.user_op_hash, e);
+                    failed += 1;
+                    continue;
+                }
+            };
+            match receipt {
+                Some(receipt) => {
+                    status.status = match receipt.success {
+                        true => OperationState::Included,
+                        false => OperationState::Failed,
+                    };
+                    status.transaction_hash = Some(receipt.receipt.transaction_hash);
+                    status.block_number = Some(receipt.receipt.block_number.as_u64());
+                    status.actual_gas_cost = Some(receipt.actual_gas_cost);
+                    status.actual_gas_used = Some(receipt.actual_gas_used);
+                    status.reason = receipt.reason.filter(|_| !receipt.success);
+                }
+                None if status.valid_until + RECEIPT_GRACE < now => expire(&mut status),
+                None => continue,
+            }
+            debug!("Operation {:?} from {:?} is {}", status.user_op_hash, status.sender, status.status.as_str());
+            self.store.record_operation_status(&status).await?;
+        }
+        if failed > 0 {
+            warn!("Failed to fetch the receipts of {} of {} pending operations from the bundler", failed, total);
+        }
+        Ok(())
+    }
+    
+    // What became of an operation this server sponsored, by the userOpHash it was submitted under
+    pub async fn sponsored_operation_status(&self, user_op_hash: H256) -> Result<OperationStatus, PaymasterError> {
+        if self.bundler.is_none() {
+            return Err(PaymasterError::UnsupportedOperation);
+        }
+        let mut status = self.store.operation_status(user_op_hash)
+            .await?
+            .ok_or_else(|| PaymasterError::OperationNotFound(format!("{:?}", user_op_hash)))?;
+        // Reported before the poller gets to it
+        if status.status == OperationState::Pending && status.valid_until + RECEIPT_GRACE < unix_time()? {
+            expire(&mut status);
+        }
+        Ok(status)
+    }
+    
+    // Token debts recorded since `since`; with `outstanding`, only those of included operations
+    // whose charge fell short or has not been seen
+    pub async fn token_debts(&self, since: u64, outstanding: bool) -> Result<Vec<TokenDebt>, PaymasterError> {
+        let mut debts = self.store.token_debts(since).await?;
+        if outstanding {
+            debts.retain(|debt| debt.shortfall().is_some());
+        }
+        Ok(debts)
+    }
+    
+    pub fn tracks_inclusions(&self) -> bool {
+        !self.policies.is_empty() || self.shards.is_sharded() || !self.tokens.is_empty() || self.projects.is_some()
+    }
+    
+    // Reload projects from the store now and then, picking up changes made through other
+    // instances sharing it
+    pub async fn run_project_sync(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.load_projects().await {
+                warn!("Failed to reload projects: {}", e);
+            }
+        }
+    }
+    
+    // Serve the policies of every project in the store, and stop serving those of projects
+    // removed from it. Returns how many projects there are.
+    pub async fn load_projects(&self) -> Result<usize, PaymasterError> {
+        let projects = self.project_registry()?;
+        let stored = self.store.projects().await?;
+        
+        for project in projects.list() {
+            if !stored.iter().any(|stored| stored.id == project.id) {
+                projects.remove(&project.id);
+                self.policies.replace(&project::policy_prefix(&project.id), HashMap::new());
+            }
+        }
+        for project in &stored {
+            if projects.get(&project.id).ok().as_ref() == Some(project) {
+                continue;
+            }
+            match projects.insert(project.clone()) {
+                Ok(policies) => self.policies.replace(&project::policy_prefix(&project.id), policies),
+                Err(e) => warn!("Not serving project {}: {}", project.id, e),
+            }
+        }
+        Ok(stored.len())
+    }
+    
+    pub fn project(&self, project_id: &str) -> Result<Project, PaymasterError> {
+        self.project_registry()?.get(project_id)
+    }
+    
+    pub fn projects(&self) -> Result<Vec<Project>, PaymasterError> {
+        Ok(self.project_registry()?.list())
+    }
+    
+    // Create a project, or with `replace` update an existing one, and serve its policies
+    pub async fn save_project(&self, mut project: Project, replace: bool) -> Result<Project, PaymasterError> {
+        let projects = self.project_registry()?;
+        let existing = projects.get(&project.id).ok();
+        match (&existing, replace) {
+            (Some(_), false) => {
+                return Err(PaymasterError::InvalidParameters(format!("Project {} already exists", project.id)));
+            }
+            (None, true) => return Err(PaymasterError::ProjectNotFound(project.id)),
+            _ => {}
+        }
+        projects.check(&project, &self.policies)?;
+        
+        let now = unix_time()?;
+        project.created_at = existing.map(|existing| existing.created_at).unwrap_or(now);
+        project.updated_at = now;
+        self.store.save_project(&project).await?;
+        let policies = projects.insert(project.clone())?;
+        self.policies.replace(&project::policy_prefix(&project.id), policies);
+        
+        info!("Saved project {} with {} policies", project.id, project.policies.len());
+        Ok(project)
+    }
+    
+    // Remove a project and stop serving its policies. What they already reserved is settled as usual.
+    pub async fn delete_project(&self, project_id: &str) -> Result<Project, PaymasterError> {
+        let projects = self.project_registry()?;
+        let project = projects.get(project_id)?;
+        self.store.delete_project(project_id).await?;
+        projects.remove(project_id);
+        self.policies.replace(&project::policy_prefix(project_id), HashMap::new());
+        
+        info!("Deleted project {}", project_id);
+        Ok(project)
+    }
+    
+    // Malformed operations are turned away before they are hashed, so they are not audited
+    fn check_fields(&self, user_op: &UserOperation) -> Result<(), PaymasterError> {
+        match self.limits.strict_decoding {
+            true => decoding::check_fields(&self.limits, user_op),
+            false => Ok(()),
+        }
+    }
+    
+    fn approval_queue(&self) -> Result<&ApprovalQueue, PaymasterError> {
+        self.approvals.as_ref().ok_or(PaymasterError::UnsupportedOperation)
+    }
+    
+    fn project_registry(&self) -> Result<&ProjectRegistry, PaymasterError> {
+        self.projects.as_ref().ok_or(PaymasterError::UnsupportedOperation)
+    }
+    
+    fn key_rotation(&self) -> Result<&KeyRotation, PaymasterError> {
+        self.rotation.as_ref().ok_or(PaymasterError::UnsupportedOperation)
+    }
+    
+    async fn process_user_operation(
+        &self,
+        user_op: &UserOperation,
+        context: &SponsorshipContext,
+        require_approval: bool,
+    ) -> Result<PaymasterResponse, PaymasterError> {
+        let user_op_hash = self.user_op_hash(user_op);
+        // Operators approving a ticket are served in maintenance mode, and vouch for the origin
+        // it was parked with. A session token stands in for the policy ID or API key.
+        let origin = match require_approval {
+            true => self.maintenance
+                .check(Some(user_op.sender), context)
+                .and_then(|_| self.origins.verify(user_op, context)),
+            false => Ok(context.origin.clone()),
+        };
+        let policy_id = origin.as_ref().map_err(Clone::clone).and_then(|origin| {
+            let policy_id = match &context.session_token {
+                Some(session_token) => self.sessions.authorize(session_token, user_op, unix_time()?)?,
+                None => self.policies.resolve(context)?,
+            };
+            if let Some(policy_id) = &policy_id {
+                self.policies.check_origin(policy_id, origin.as_deref())?;
+            }
+            Ok(policy_id)
+        });
+        let origin = origin.ok().flatten();
+        
+        record_span_fields(user_op, user_op_hash, policy_id.as_ref().ok().and_then(Option::as_deref));
+        let (result, held) = match &policy_id {
+            Ok(policy_id) => {
+                let resolved = SponsorshipContext {
+                    sponsorship_policy_id: policy_id.clone(),
+                    token: context.token,
+                    origin: origin.clone(),
+                    ..Default::default()
+                };
+                match self.sponsor_user_operation(user_op, user_op_hash, &resolved, require_approval).await {
+                    Ok((sponsorship, held)) => (Ok(sponsorship), Some(held)),
+                    Err(e) => (Err(e), None),
+                }
+            }
+            Err(e) => (Err(e.clone()), None),
+        };
+        
+        // A decision that cannot be recorded is not handed out, so its reservations are given
+        // back and the session does not count it
+        let authorized = policy_id.is_ok();
+        let policy_id = policy_id.ok().flatten();
+        let recorded = self.record_decision(user_op, user_op_hash, policy_id.clone(), context.token, origin.clone(), &result).await;
+        if let (Some(session_token), true) = (&context.session_token, authorized) {
+            if result.is_err() || recorded.is_err() {
+                self.sessions.refund(session_token);
+            }
+        }
+        recorded?;
+        if let Some(held) = held {
+            held.commit();
+        }
+        
+        // Operator approvals cannot be replayed, so only requests from dapps are captured
+        if let Some(replay_capture) = self.replay_capture.as_ref().filter(|_| require_approval) {
+            let (decision, reason) = match &result {
+                Ok(_) => (Decision::Sponsored, None),
+                Err(e @ PaymasterError::ApprovalRequired(_)) => (Decision::Pending, Some(e.to_string())),
+                Err(e) => (Decision::Rejected, Some(e.to_string())),
+            };
+            let record = ReplayRecord {
+                timestamp: unix_time()?,
+                user_op: user_op.clone(),
+                context: SponsorshipContext {
+                    sponsorship_policy_id: policy_id.or_else(|| context.sponsorship_policy_id.clone()),
+                    token: context.token,
+                    origin: context.origin.clone(),
+                    origin_signature: context.origin_signature.clone(),
+                    ..Default::default()
+                },
+                user_op_hash,
+                decision,
+                reason,
+            };
+            if let Err(e) = replay_capture.append(&record).await {
+                warn!("Failed to capture the request for replay: {}", e);
+            }
+        }
+        
+        result.map(|sponsorship| sponsorship.response)
+    }
+    
+    async fn record_decision(
+        &self,
+        user_op: &UserOperation,
+        user_op_hash: H256,
+        policy_id: Option<String>,
+        token: Option<Address>,
+        origin: Option<String>,
+        result: &Result<Sponsorship, PaymasterError>,
+    ) -> Result<(), PaymasterError> {
+        let (decision, reason, signer, signature_hash) = match result {
+            Ok(sponsorship) => (
+                Decision::Sponsored,
+                None,
+                Some(sponsorship.signer),
+                Some(H256::from(keccak256(&sponsorship.signature))),
+            ),
+            Err(e @ PaymasterError::ApprovalRequired(_)) => (Decision::Pending, Some(e.to_string()), None, None),
+            Err(e) => (Decision::Rejected, Some(e.to_string()), None, None),
+        };
+        
+        let entry = AuditEntry {
+            timestamp: unix_time()?,
+            chain_id: self.chain_id,
+            user_op_hash,
+            user_op: user_op.clone(),
+            policy_id,
+            token,
+            origin,
+            decision,
+            reason,
+            signer,
+            signature_hash,
+            max_cost: result.as_ref().ok().map(|sponsorship| sponsorship.max_cost),
+            receipt: result.as_ref().ok().and_then(|sponsorship| sponsorship.response.receipt.clone()),
+            hmac: None,
+        };
+        
+        if let Some(audit_log) = &self.audit_log {
+            audit_log.append(entry.clone()).await?;
+        }
+        if let Some(projects) = &self.projects {
+            projects.notify(&entry);
+        }
+        
+        if let Ok(sponsorship) = result {
+            self.store.record_sponsorship(&SponsorshipRecord {
+                user_op_hash,
+                chain_id: self.chain_id,
+                sender: user_op.sender,
+                nonce: user_op.nonce,
+                policy_id: entry.policy_id.clone(),
+                max_cost: sponsorship.max_cost,
+                valid_after: sponsorship.valid_after,
+                valid_until: sponsorship.valid_until,
+                paymaster_and_data: sponsorship.response.paymaster_and_data.clone(),
+                created_at: entry.timestamp,
+                aggregator: sponsorship.aggregator,
+            }).await?;
+            
+            // Bundlers know the operation by its hash with the paymaster's data in place
+            if self.bundler.is_some() {
+                let submitted = UserOperation {
+                    paymaster_and_data: sponsorship.response.paymaster_and_data.clone(),
+                    ..user_op.clone()
+                };
+                self.store.record_operation_status(&OperationStatus {
+                    user_op_hash: self.user_op_hash(&submitted),
+                    sender: user_op.sender,
+                    nonce: user_op.nonce,
+                    status: OperationState::Pending,
+                    valid_until: sponsorship.valid_until,
+                    transaction_hash: None,
+                    block_number: None,
+                    actual_gas_cost: None,
+                    actual_gas_used: None,
+                    reason: None,
+                    created_at: entry.timestamp,
+                }).await?;
+            }
             
-        if balance <= max_cost {
-            return Err(PaymasterError::InsufficientFunds);
+            // What the sender will owe is only known once the operation is included
+            if let Some(quote) = &sponsorship.response.token_quote {
+                self.store.record_token_debt(&TokenDebt {
+                    sender: user_op.sender,
+                    nonce: user_op.nonce,
+                    token: quote.token,
+                    exchange_rate: quote.exchange_rate,
+                    max_token_cost: quote.max_token_cost,
+                    owed: None,
+                    collected: None,
+                    user_op_hash: None,
+                    created_at: entry.timestamp,
+                }).await?;
+            }
         }
         
+        let mut recent_decisions = self.recent_decisions.lock().unwrap();
+        if recent_decisions.len() == RECENT_DECISIONS {
+            recent_decisions.pop_front();
+        }
+        recent_decisions.push_back(entry);
         Ok(())
     }
     
-    // Hash and sign the paymaster data
-    async fn sign_paymaster_data(
+    async fn sponsor_user_operation(
         &self,
         user_op: &UserOperation,
-        valid_until: u64,
-        valid_after: u64,
-    ) -> Result<Bytes, PaymasterError> {
-        // Calculate user operation hash according to ERC-4337 spec
-        let user_op_hash = self.hash_user_operation(user_op);
-        
-        // Prepare the message to sign: paymaster + validUntil + validAfter + userOpHash
-        let mut message = vec![];
-        message.extend_from_slice(&self.paymaster_address.as_bytes());
-        message.extend_from_slice(&valid_until.to_be_bytes());
-        message.extend_from_slice(&valid_after.to_be_bytes());
-        message.extend_from_slice(&user_op_hash.as_bytes());
-        
-        // Hash the message
-        let message_hash = keccak256(&message);
-        
-        // Sign the hash
-        let signature = self.wallet.sign_message(message_hash)
-            .await
-            .map_err(|e| PaymasterError::SignatureVerificationFailed)?;
-            
-        // Convert to bytes
-        let signature_bytes = Bytes::from(signature.to_vec());
+        user_op_hash: H256,
+        resolved: &SponsorshipContext,
+        require_approval: bool,
+    ) -> Result<(Sponsorship, HeldReservations<'_>), PaymasterError> {
+        let policy_id = resolved.sponsorship_policy_id.as_deref();
+        let token = resolved.token;
+        let Preflight { max_cost, budget_cost, gas_limits, transfers, deposits, token_quote, paymaster, now } = self.preflight(user_op, policy_id, token).await?;
+        
+        // 5. Park operations that need an operator's sign-off
+        if let Some(approvals) = self.approvals.as_ref().filter(|_| require_approval) {
+            if let Some(reason) = approvals.requires_approval(user_op, max_cost) {
+                let ticket_id = approvals.park(user_op, user_op_hash, max_cost, resolved, reason, now);
+                info!("Operation from {} parked for approval as ticket {}", user_op.sender, ticket_id);
+                return Err(PaymasterError::ApprovalRequired(ticket_id));
+            }
+        }
+        
+        // 6. Create time-range for paymaster validity
+        let (valid_until, valid_after) = self.validity_window(now).await?;
+        
+        // 7. Reserve the max cost against the policy budget and the deposit with the most room,
+        // and sign the paymaster data for that deposit's paymaster. Both are given back if any
+        // later step fails, the decision cannot be recorded, or the request is abandoned.
+        if let Some(policy_id) = policy_id {
+            self.policies.reserve(policy_id, user_op.sender, user_op.nonce, budget_cost, valid_until, now).await?;
+        }
+        let held = HeldReservations {
+            shards: &self.shards,
+            store: self.store.clone(),
+            sender: user_op.sender,
+            nonce: user_op.nonce,
+            policy: policy_id.is_some(),
+            committed: false,
+        };
+        let shard = self.shards.reserve(&deposits, user_op.sender, user_op.nonce, max_cost, valid_until)?;
+        if let Some(quote) = token_quote.as_ref().filter(|_| shard.paymaster_address != paymaster) {
+            self.tokens.check_allowance(quote, user_op.sender, shard.paymaster_address).await?;
+        }
+        let (shard, valid_until) = self.signing_key(shard, valid_until, now).await?;
         
-        Ok(signature_bytes)
+        // 8. Create the paymaster data
+        let mut paymaster_data = PaymasterAndData {
+            paymaster: shard.paymaster_address,
+            gas_limits,
+            valid_until,
+            valid_after,
+            token_quote: token_quote.clone(),
+            signature: Bytes::default(),
+        };
+        let (signature, signer) = self.sign_paymaster_data(&shard, user_op, &paymaster_data).await?;
+        paymaster_data.signature = signature.clone();
+        
+        // 9. Encode the paymaster data with the signature
+        let paymaster_and_data = paymaster_data.encode();
+        
+        // 10. Check the EntryPoint accepts the operation with the paymaster data in place, and
+        // that the policy allows the signature aggregator the account turns out to use
+        let mut aggregator = None;
+        if self.simulation.enabled {
+            let simulated = self.simulate_validation(user_op, &paymaster_and_data).await?;
+            aggregator = self.check_aggregator(policy_id, simulated)?;
+        }
+        
+        // 11. Sign a receipt of the terms for the dapp to keep
+        let receipt = self.sign_receipt(&shard, user_op_hash, valid_until, valid_after, policy_id).await?;
+        
+        // 12. Count the value the operation moves against the policy's daily limits
+        if let Some(policy_id) = policy_id {
+            self.policies.record_transfers(user_op.sender, user_op.nonce, policy_id, &transfers, now).await?;
+        }
+        
+        // 13. Remember the operation, so it can still be renewed once only renewals are signed
+        if self.degradation.renewal_only_below.is_some() {
+            let key = operation_key(user_op.sender, user_op.nonce);
+            self.store.insert_replay_key(SIGNED_OPERATIONS, &key, valid_until + SIGNED_OPERATION_RETENTION).await?;
+        }
+        
+        let sponsorship = Sponsorship {
+            response: PaymasterResponse {
+                paymaster_and_data,
+                receipt: Some(receipt),
+                token_quote,
+                paymaster_verification_gas_limit: gas_limits.map(|gas_limits| gas_limits.verification_gas_limit.into()),
+                paymaster_post_op_gas_limit: gas_limits.map(|gas_limits| gas_limits.post_op_gas_limit.into()),
+                dry_run: self.dry_run,
+            },
+            signer,
+            signature,
+            max_cost,
+            valid_after,
+            valid_until,
+            aggregator,
+        };
+        Ok((sponsorship, held))
     }
     
-    // Encode paymaster data according to ERC-4337 spec
-    fn encode_paymaster_data(
+    // Steps of a sponsorship that only check the operation, without holding or signing anything
+    async fn preflight(
         &self,
-        valid_until: u64,
-        valid_after: u64,
-        signature: Bytes,
-    ) -> Result<Bytes, PaymasterError> {
-        // Encode: paymaster address (20 bytes) + validUntil (32 bytes) + validAfter (32 bytes) + signature
-        let mut data = vec![];
+        user_op: &UserOperation,
+        policy_id: Option<&str>,
+        token: Option<Address>,
+    ) -> Result<Preflight, PaymasterError> {
+        let now = unix_time()?;
         
-        // Add paymaster address
-        data.extend_from_slice(self.paymaster_address.as_bytes());
+        // 1. Calculate the gas cost, including what the paymaster itself is granted
+        let gas_limits = self.paymaster_gas_limits(token);
+        let (max_cost, budget_cost) = self.calculate_max_cost(user_op, gas_limits.as_ref()).await?;
         
-        // Add valid until (32 bytes)
-        let mut valid_until_bytes = [0u8; 32];
-        let valid_until_be = valid_until.to_be_bytes();
-        valid_until_bytes[32 - valid_until_be.len()..].copy_from_slice(&valid_until_be);
-        data.extend_from_slice(&valid_until_bytes);
+        // 2. Validate the user operation
+        let new_sender = self.validate_user_operation(user_op, max_cost).await?;
+        if !user_op.init_code.is_empty() {
+            self.check_deployment(user_op, policy_id).await?;
+        }
+        let transfers = match policy_id {
+            Some(policy_id) => {
+                self.policies.check_verification_gas(policy_id, user_op.verification_gas_limit, new_sender)?;
+                self.policies.check_calls(policy_id, user_op, now).await?
+            }
+            None => Vec::new(),
+        };
+        if self.store.contains_replay_key(INCLUDED_NONCES, &operation_key(user_op.sender, user_op.nonce)).await? {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "nonce {} of {:?} has already been used", user_op.nonce, user_op.sender
+            )));
+        }
+        self.check_inclusion_fees(user_op).await?;
         
-        // Add valid after (32 bytes)
-        let mut valid_after_bytes = [0u8; 32];
-        let valid_after_be = valid_after.to_be_bytes();
-        valid_after_bytes[32 - valid_after_be.len()..].copy_from_slice(&valid_after_be);
-        data.extend_from_slice(&valid_after_bytes);
+        // 3. Check the deposits still sponsor this operation at the tier they are at, and some
+        // paymaster deposit has enough funds left
+        let deposits = self.paymaster_deposits().await?;
+        self.check_tier(self.update_tier(&deposits), user_op, policy_id).await?;
+        let paymaster = self.shards.check(&deposits, user_op.sender, user_op.nonce, max_cost)?;
         
-        // Add signature
-        data.extend_from_slice(&signature);
+        // 4. Price the operation for a sender paying in tokens, and check the sponsorship
+        // policy still has room for it
+        let token_quote = match token {
+            Some(token) => Some(self.tokens.quote(token, user_op.sender, paymaster, max_cost, now).await?),
+            None => None,
+        };
+        if let Some(policy_id) = policy_id {
+            self.policies.check(policy_id, budget_cost, now).await?;
+        }
         
-        Ok(Bytes::from(data))
+        Ok(Preflight { max_cost, budget_cost, gas_limits, transfers, deposits, token_quote, paymaster, now })
     }
     
-    // Calculate the hash of a user operation according to ERC-4337 spec
-    fn hash_user_operation(&self, user_op: &UserOperation) -> H256 {
-        // Pack the user operation
-        let mut data = vec![];
+    // Work out the tier from the total deposit, logging when it changes
+    fn update_tier(&self, deposits: &[U256]) -> OperatingTier {
+        let total = deposits.iter().fold(U256::zero(), |total, deposit| total.saturating_add(*deposit));
+        let below = |level: Option<U256>| level.is_some_and(|level| total < level);
+        let tier = if below(self.degradation.pause_below) {
+            OperatingTier::Paused
+        } else if below(self.degradation.renewal_only_below) {
+            OperatingTier::RenewalOnly
+        } else if below(self.degradation.priority_only_below) {
+            OperatingTier::PriorityOnly
+        } else {
+            OperatingTier::Normal
+        };
         
-        // Pack sender
-        data.extend_from_slice(user_op.sender.as_bytes());
+        let previous = std::mem::replace(&mut *self.tier.lock().unwrap(), tier);
+        if tier > previous {
+            warn!("Paymaster deposit is down to {} wei, moving to tier {:?}", total, tier);
+        } else if tier < previous {
+            info!("Paymaster deposit is back up to {} wei, moving to tier {:?}", total, tier);
+        }
+        tier
+    }
+    
+    async fn check_tier(&self, tier: OperatingTier, user_op: &UserOperation, policy_id: Option<&str>) -> Result<(), PaymasterError> {
+        let reason = match tier {
+            OperatingTier::Normal => return Ok(()),
+            OperatingTier::PriorityOnly if policy_id.is_some_and(|policy_id| self.policies.is_priority(policy_id)) => return Ok(()),
+            OperatingTier::PriorityOnly => "only priority policies are sponsored",
+            OperatingTier::RenewalOnly => {
+                let key = operation_key(user_op.sender, user_op.nonce);
+                if self.store.contains_replay_key(SIGNED_OPERATIONS, &key).await? {
+                    return Ok(());
+                }
+                "only operations that were already signed are signed again"
+            }
+            OperatingTier::Paused => "sponsorship is paused",
+        };
+        Err(PaymasterError::DepositLow(reason.to_string()))
+    }
+    
+    // Validate the user operation, returning whether its sender is new: the EntryPoint has not yet
+    // run one of its operations under the nonce's key
+    async fn validate_user_operation(&self, user_op: &UserOperation, max_cost: U256) -> Result<bool, PaymasterError> {
+        // Basic validation checks
+        if user_op.max_fee_per_gas.is_zero() || user_op.max_priority_fee_per_gas.is_zero() {
+            return Err(PaymasterError::InvalidUserOperation("Gas price cannot be zero".to_string()));
+        }
         
-        // Pack nonce (32 bytes)
-        let nonce_bytes = ethers::utils::rlp::encode(&user_op.nonce);
-        data.extend_from_slice(&nonce_bytes);
+        // Reject absurd values before they can drain the deposit in a single operation
+        let gas_caps = [
+            ("callGasLimit", user_op.call_gas_limit, self.limits.max_call_gas_limit),
+            ("verificationGasLimit", user_op.verification_gas_limit, self.limits.max_verification_gas_limit),
+            ("preVerificationGas", user_op.pre_verification_gas, self.limits.max_pre_verification_gas),
+        ];
+        for (field, value, cap) in gas_caps {
+            if value > U256::from(cap) {
+                return Err(PaymasterError::InvalidUserOperation(format!("{} {} exceeds limit {}", field, value, cap)));
+            }
+        }
         
-        // Pack initCode hash
-        data.extend_from_slice(&keccak256(&user_op.init_code));
+        let size_caps = [
+            ("callData", user_op.call_data.len(), self.limits.max_call_data_size),
+            ("initCode", user_op.init_code.len(), self.limits.max_init_code_size),
+        ];
+        for (field, size, cap) in size_caps {
+            if size > cap {
+                return Err(PaymasterError::InvalidUserOperation(format!(
+                    "{} is {} bytes, limit is {} bytes", field, size, cap
+                )));
+            }
+        }
         
-        // Pack callData hash
-        data.extend_from_slice(&keccak256(&user_op.call_data));
+        if let Some(cap) = self.limits.max_cost {
+            if max_cost > cap {
+                return Err(PaymasterError::InvalidUserOperation(format!(
+                    "Max cost {} wei exceeds limit {} wei", max_cost, cap
+                )));
+            }
+        }
         
-        // Pack callGasLimit
-        let call_gas_limit_bytes = ethers::utils::rlp::encode(&user_op.call_gas_limit);
-        data.extend_from_slice(&call_gas_limit_bytes);
+        // The nonce must not already have been used on-chain for its key
+        let nonce_key = user_op.nonce >> 64;
+        let current_nonce = self.entry_point.get_nonce(user_op.sender, nonce_key)
+            .call()
+            .await
+            .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))?;
+            
+        if user_op.nonce < current_nonce {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "Nonce {} already used, EntryPoint expects {}", user_op.nonce, current_nonce
+            )));
+        }
         
-        // Pack verificationGasLimit
-        let verification_gas_limit_bytes = ethers::utils::rlp::encode(&user_op.verification_gas_limit);
-        data.extend_from_slice(&verification_gas_limit_bytes);
+        Ok(current_nonce == nonce_key << 64)
+    }
+    
+    // An operation deploying its account must come from the address its initCode deploys to, or
+    // the EntryPoint rejects it once the paymaster has paid for validation. Under a policy that
+    // lists allowed accounts, the factory and the implementation it deploys must be listed too.
+    async fn check_deployment(&self, user_op: &UserOperation, policy_id: Option<&str>) -> Result<(), PaymasterError> {
+        let factory = user_op.init_code
+            .get(..20)
+            .map(Address::from_slice)
+            .ok_or_else(|| PaymasterError::InvalidUserOperation("initCode is shorter than a factory address".to_string()))?;
         
-        // Pack preVerificationGas
-        let pre_verification_gas_bytes = ethers::utils::rlp::encode(&user_op.pre_verification_gas);
-        data.extend_from_slice(&pre_verification_gas_bytes);
+        // getSenderAddress runs the factory and always reverts, reporting the CREATE2 address
+        // the account would be deployed at
+        let deployed_at = match self.entry_point.get_sender_address(user_op.init_code.clone()).call().await {
+            Ok(()) => None,
+            Err(e) => match e.as_revert() {
+                Some(data) => match revert::sender_address(data) {
+                    Some(address) => Some(address),
+                    None => return Err(PaymasterError::InvalidUserOperation(format!(
+                        "initCode does not deploy an account: {}", revert::decode(data)
+                    ))),
+                },
+                None => return Err(provider::call_error(self.entry_point.client_ref(), e)),
+            },
+        };
+        match deployed_at.filter(|address| !address.is_zero()) {
+            Some(address) if address == user_op.sender => {}
+            Some(address) => return Err(PaymasterError::InvalidUserOperation(format!(
+                "initCode deploys {:?}, not the sender {:?}", address, user_op.sender
+            ))),
+            None => return Err(PaymasterError::InvalidUserOperation(format!(
+                "Factory {:?} did not deploy an account", factory
+            ))),
+        }
         
-        // Pack maxFeePerGas
-        let max_fee_per_gas_bytes = ethers::utils::rlp::encode(&user_op.max_fee_per_gas);
-        data.extend_from_slice(&max_fee_per_gas_bytes);
+        let Some(policy_id) = policy_id else {
+            return Ok(());
+        };
+        let rules = self.policies.account_rules(policy_id);
+        if rules.is_empty() {
+            return Ok(());
+        }
+        let rules: Vec<_> = rules.iter().filter(|rule| rule.factory == factory).collect();
+        if rules.is_empty() {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "Policy {} does not sponsor accounts deployed by factory {:?}", policy_id, factory
+            )));
+        }
+        if rules.iter().any(|rule| rule.implementation.is_none()) {
+            return Ok(());
+        }
         
-        // Pack maxPriorityFeePerGas
-        let max_priority_fee_per_gas_bytes = ethers::utils::rlp::encode(&user_op.max_priority_fee_per_gas);
-        data.extend_from_slice(&max_priority_fee_per_gas_bytes);
+        let implementation = self.account_implementation_cache.get_or_fetch(factory, || async {
+            AccountFactory::new(factory, self.entry_point.client())
+                .account_implementation()
+                .call()
+                .await
+                .map_err(|e| match e.as_revert() {
+                    Some(_) => PaymasterError::InvalidUserOperation(format!(
+                        "Factory {:?} does not report its account implementation", factory
+                    )),
+                    None => provider::call_error(self.entry_point.client_ref(), e),
+                })
+        }).await?;
+        if !rules.iter().any(|rule| rule.implementation == Some(implementation)) {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "Policy {} does not sponsor accounts of implementation {:?}", policy_id, implementation
+            )));
+        }
+        Ok(())
+    }
+    
+    // Reject operations paying too little to be included while their sponsorship is valid
+    async fn check_inclusion_fees(&self, user_op: &UserOperation) -> Result<(), PaymasterError> {
+        let mut min_priority_fee = self.fees.min_priority_fee.unwrap_or_default();
         
-        // First hash
-        let hash = keccak256(&data);
+        if let Some(percentile) = self.fees.priority_fee_percentile {
+            let history = self.fee_history(self.fees.fee_history_blocks, Some(percentile)).await?;
+            
+            // The last base fee is the one charged by the next block
+            if let Some(next_base_fee) = history.base_fee_per_gas.last() {
+                if user_op.max_fee_per_gas < *next_base_fee {
+                    return Err(PaymasterError::InvalidUserOperation(format!(
+                        "maxFeePerGas {} is below the next block's base fee {}", user_op.max_fee_per_gas, next_base_fee
+                    )));
+                }
+            }
+            
+            // Median over the sampled blocks of each block's priority fee at the percentile
+            let mut rewards: Vec<U256> = history.reward.iter().filter_map(|block| block.first().copied()).collect();
+            rewards.sort();
+            if let Some(median) = rewards.get(rewards.len() / 2) {
+                min_priority_fee = min_priority_fee.max(*median);
+            }
+        }
         
-        // Include chain ID and entrypoint address in the hash
-        let mut chain_hash_data = vec![];
-        chain_hash_data.extend_from_slice(&hash);
-        chain_hash_data.extend_from_slice(&ethers::utils::rlp::encode(&U256::from(self.chain_id)));
-        chain_hash_data.extend_from_slice(self.paymaster_address.as_bytes());
+        if user_op.max_priority_fee_per_gas < min_priority_fee {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "maxPriorityFeePerGas {} is below the minimum {}", user_op.max_priority_fee_per_gas, min_priority_fee
+            )));
+        }
+        
+        Ok(())
+    }
+    
+    // simulateValidation reverts even when validation passes, so only a revert other than its
+    // ValidationResult is a failure. Returns the signature aggregator the account uses, if any.
+    async fn simulate_validation(&self, user_op: &UserOperation, paymaster_and_data: &Bytes) -> Result<Option<Address>, PaymasterError> {
+        let user_op = contracts::UserOperation {
+            sender: user_op.sender,
+            nonce: user_op.nonce,
+            init_code: user_op.init_code.clone(),
+            call_data: user_op.call_data.clone(),
+            call_gas_limit: user_op.call_gas_limit,
+            verification_gas_limit: user_op.verification_gas_limit,
+            pre_verification_gas: user_op.pre_verification_gas,
+            max_fee_per_gas: user_op.max_fee_per_gas,
+            max_priority_fee_per_gas: user_op.max_priority_fee_per_gas,
+            paymaster_and_data: paymaster_and_data.clone(),
+            signature: user_op.signature.clone(),
+        };
+        
+        match self.entry_point.simulate_validation(user_op).call().await {
+            Ok(()) => Ok(None),
+            Err(e) => match e.as_revert() {
+                Some(data) if revert::is_validation_result(data) => {
+                    self.check_return_info(data).await?;
+                    Ok(revert::aggregator(data))
+                }
+                Some(data) => {
+                    let revert = revert::decode(data);
+                    info!("Simulation rejected the operation: {}", revert);
+                    Err(PaymasterError::SimulationFailed(revert))
+                }
+                None => Err(provider::call_error(self.entry_point.client_ref(), e)),
+            },
+        }
+    }
+    
+    // A passing simulateValidation still reports failed signatures and windows rather than
+    // reverting on them, and a bundler would drop such an operation
+    async fn check_return_info(&self, data: &Bytes) -> Result<(), PaymasterError> {
+        let rejected = |reason: String| {
+            info!("Simulation rejected the operation: {}", reason);
+            Err(PaymasterError::SimulationFailed(Revert {
+                error: "ValidationResult".to_string(),
+                code: None,
+                reason,
+                revert_data: data.clone(),
+            }))
+        };
+        let Some(info) = revert::return_info(data) else {
+            return rejected(format!("Undecodable validation result {}", data));
+        };
+
+        // A dry run signs with a throwaway key, which the paymaster contract reports as a failed
+        // signature; without an aggregator that cannot be told from the account's own
+        if info.sig_failed && !self.dry_run && !self.simulation.allow_failed_signatures {
+            return rejected("Signature validation failed".to_string());
+        }
+        let now = self.chain_time().await?;
+        if info.valid_until != 0 && info.valid_until <= now {
+            return rejected(format!("Operation expired at {}", info.valid_until));
+        }
+        if info.valid_after > now {
+            return rejected(format!("Operation is not valid until {}", info.valid_after));
+        }
+        Ok(())
+    }
+    
+    // Accounts that use a signature aggregator need a bundler that supports it, so they are only
+    // sponsored under a policy that lists the aggregator
+    fn check_aggregator(&self, policy_id: Option<&str>, aggregator: Option<Address>) -> Result<Option<Address>, PaymasterError> {
+        let Some(aggregator) = aggregator else {
+            return Ok(None);
+        };
+        if !policy_id.is_some_and(|policy_id| self.policies.allows_aggregator(policy_id, aggregator)) {
+            info!("Refused an account that uses signature aggregator {:?}", aggregator);
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "signature aggregator {:?} is not supported", aggregator
+            )));
+        }
+        Ok(Some(aggregator))
+    }
+    
+    // The EntryPoint checks the window against block timestamps, so it is anchored to the latest
+    // block rather than the host clock; a host clock far from chain time means one of them is wrong,
+    // and the latest block of a provider still syncing is not the chain's
+    async fn validity_window(&self, now: u64) -> Result<(u64, u64), PaymasterError> {
+        self.sync.check()?;
+        let chain_time = self.chain_time().await?;
+        
+        let drift = now.abs_diff(chain_time);
+        if drift > self.validity.max_clock_drift_secs {
+            warn!("Host clock is {}s away from the latest block timestamp {}", drift, chain_time);
+            return Err(PaymasterError::ClockDrift(drift));
+        }
+        
+        Ok((chain_time + self.valid_duration, chain_time.saturating_sub(self.validity.skew_secs)))
+    }
+    
+    // Timestamp of the latest block
+    async fn chain_time(&self) -> Result<u64, PaymasterError> {
+        self.block_time_cache.get_or_fetch((), || async {
+            let block = self.entry_point.client()
+                .get_block(BlockNumber::Latest)
+                .await
+                .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))?
+                .ok_or_else(|| PaymasterError::EthereumProviderError("Latest block not found".to_string()))?;
+            Ok(block.timestamp.low_u64())
+        }).await
+    }
+    
+    // The key to sign for `shard` with, and the validUntil its signature may carry. While a
+    // rotation is pending the current key's signatures must expire within the overlap; the next
+    // key takes over once the contract verifies with it.
+    async fn signing_key(&self, shard: &Shard, valid_until: u64, now: u64) -> Result<(Shard, u64), PaymasterError> {
+        let paymaster_address = shard.paymaster_address;
+        let current = Shard { paymaster_address, signer: shard.signer.clone() };
+        let Some(rotation) = self.rotation.as_ref().filter(|rotation| rotation.replaces(shard.signer.address())) else {
+            return Ok((current, valid_until));
+        };
+        let next = Shard { paymaster_address, signer: rotation.next().clone() };
+        if rotation.is_rotated(paymaster_address) {
+            return Ok((next, valid_until));
+        }
+        
+        let verifying_signer = self.verifying_signer_cache.get_or_fetch(paymaster_address, || async {
+            VerifyingPaymaster::new(paymaster_address, self.entry_point.client())
+                .verifying_signer()
+                .call()
+                .await
+                .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))
+        }).await?;
+        if verifying_signer == next.signer.address() {
+            info!("Paymaster {:?} now verifies with {:?}, signing with the next key", paymaster_address, verifying_signer);
+            rotation.mark_rotated(paymaster_address);
+            return Ok((next, valid_until));
+        }
+        
+        // Once the overlap is over the owner can no longer switch the contract, so the rotation lapsed
+        match rotation.overlap_until().filter(|overlap_until| *overlap_until > now) {
+            Some(overlap_until) => Ok((current, valid_until.min(overlap_until))),
+            None => Ok((current, valid_until)),
+        }
+    }
+    
+    // Gas granted to the paymaster in the operation's mode; v0.6 covers the paymaster with the
+    // operation's own verificationGasLimit
+    fn paymaster_gas_limits(&self, token: Option<Address>) -> Option<PaymasterGasLimits> {
+        let gas = match token {
+            Some(_) => self.paymaster_gas.token,
+            None => self.paymaster_gas.verifying,
+        };
+        match self.entry_point_version {
+            EntryPointVersion::V06 => None,
+            EntryPointVersion::V07 | EntryPointVersion::V08 => Some(PaymasterGasLimits {
+                verification_gas_limit: gas.verification_gas_limit,
+                post_op_gas_limit: gas.post_op_gas_limit,
+            }),
+        }
+    }
+    
+    // Calculate the maximum cost of the operation at its maxFeePerGas, which deposits must cover,
+    // and at the gas price strategy's price, which policy budgets are charged
+    async fn calculate_max_cost(
+        &self,
+        user_op: &UserOperation,
+        gas_limits: Option<&PaymasterGasLimits>,
+    ) -> Result<(U256, U256), PaymasterError> {
+        // Calculate gas limit: callGasLimit + verificationGasLimit + preVerificationGas, plus the
+        // paymaster's verification and postOp gas on v0.7 and later
+        let paymaster_gas = gas_limits
+            .map(|gas_limits| U256::from(gas_limits.verification_gas_limit) + U256::from(gas_limits.post_op_gas_limit))
+            .unwrap_or_default();
+        let total_gas = user_op.call_gas_limit
+            .checked_add(user_op.verification_gas_limit)
+            .and_then(|sum| sum.checked_add(user_op.pre_verification_gas))
+            .and_then(|sum| sum.checked_add(paymaster_gas))
+            .ok_or_else(|| PaymasterError::InvalidUserOperation("Gas limit overflow".to_string()))?;
+            
+        // Apply buffer to gas price
+        let buffered_gas_price = self.buffered_gas_price(user_op).await?;
+            
+        // Calculate max cost
+        let cost = |gas_price: U256| total_gas
+            .checked_mul(gas_price)
+            .ok_or_else(|| PaymasterError::InvalidUserOperation("Max cost calculation overflow".to_string()));
+            
+        Ok((cost(user_op.max_fee_per_gas)?, cost(buffered_gas_price)?))
+    }
+    
+    async fn buffered_gas_price(&self, user_op: &UserOperation) -> Result<U256, PaymasterError> {
+        let max_fee = user_op.max_fee_per_gas;
+        let gas_price = match self.fees.gas_price_buffer {
+            GasPriceBuffer::Percent { percent } => {
+                return 100u64
+                    .checked_add(percent)
+                    .and_then(|percent| max_fee.checked_mul(U256::from(percent)))
+                    .and_then(|product| product.checked_div(U256::from(100)))
+                    .ok_or_else(|| PaymasterError::InvalidUserOperation("Gas price calculation error".to_string()));
+            }
+            GasPriceBuffer::BaseFeeMultiplier { multiplier } => {
+                // The last base fee is the one charged by the next block
+                let history = self.fee_history(1, None).await?;
+                let next_base_fee = history.base_fee_per_gas.last().copied().unwrap_or_default();
+                let scaled = next_base_fee.saturating_mul(U256::from((multiplier * 1000.0) as u64)) / 1000;
+                scaled.saturating_add(user_op.max_priority_fee_per_gas)
+            }
+            GasPriceBuffer::Percentile { percentile, blocks } => {
+                let history = self.fee_history(blocks, Some(percentile)).await?;
+                history.base_fee_per_gas
+                    .iter()
+                    .zip(&history.reward)
+                    .map(|(base_fee, reward)| base_fee.saturating_add(reward.first().copied().unwrap_or_default()))
+                    .max()
+                    .unwrap_or(max_fee)
+            }
+        };
+        Ok(gas_price.min(max_fee))
+    }
+    
+    // Base fees of the last `blocks` blocks and the next, with each block's priority fee at `percentile`
+    async fn fee_history(&self, blocks: u64, percentile: Option<f64>) -> Result<FeeHistory, PaymasterError> {
+        self.fee_history_cache.get_or_fetch((blocks, percentile.map(f64::to_bits)), || async {
+            self.entry_point.client()
+                .fee_history(blocks, BlockNumber::Latest, percentile.as_slice())
+                .await
+                .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))
+        }).await
+    }
+    
+    // EntryPoint deposit of each shard's paymaster, in shard order
+    async fn paymaster_deposits(&self) -> Result<Vec<U256>, PaymasterError> {
+        let mut deposits = vec![];
+        for shard in self.shards.iter() {
+            let deposit = self.deposit_cache.get_or_fetch(shard.paymaster_address, || async {
+                self.entry_point.balance_of(shard.paymaster_address)
+                    .call()
+                    .await
+                    .map_err(|e| provider::call_error(self.entry_point.client_ref(), e))
+            }).await?;
+            deposits.push(deposit);
+        }
         
-        // Final hash
-        let final_hash = keccak256(&chain_hash_data);
-        H256::from_slice(&final_hash)
+        Ok(deposits)
+    }
+    
+    // Sign the terms of `paymaster_data` for `user_op`; its signature is not part of the hash
+    async fn sign_paymaster_data(&self, shard: &Shard, user_op: &UserOperation, paymaster_data: &PaymasterAndData) -> Result<(Bytes, Address), PaymasterError> {
+        sign_hash(shard, user_op_hash::paymaster_hash(user_op, self.entry_point_version, self.chain_id, paymaster_data)).await
+    }
+    
+    async fn sign_receipt(
+        &self,
+        shard: &Shard,
+        user_op_hash: H256,
+        valid_until: u64,
+        valid_after: u64,
+        policy_id: Option<&str>,
+    ) -> Result<SponsorshipReceipt, PaymasterError> {
+        let mut receipt = SponsorshipReceipt {
+            chain_id: self.chain_id,
+            paymaster: shard.paymaster_address,
+            user_op_hash,
+            valid_until,
+            valid_after,
+            policy_id: policy_id.map(str::to_string),
+            signer: Address::zero(),
+            signature: Bytes::default(),
+        };
+        (receipt.signature, receipt.signer) = sign_hash(shard, receipt.digest()).await?;
+        Ok(receipt)
     }
     
-}
\ No newline at end of file
+}
+
+// An operation's policy reservation and deposit hold, kept once the sponsorship is recorded and
+// handed out, and given back when dropped before that: on an error, or when the request was
+// abandoned mid-way
+struct HeldReservations<'a> {
+    shards: &'a ShardSet,
+    store: Arc<dyn SponsorshipStore>,
+    sender: Address,
+    nonce: U256,
+    policy: bool,
+    committed: bool,
+}
+
+impl HeldReservations<'_> {
+    fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for HeldReservations<'_> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        self.shards.release(self.sender, self.nonce);
+        if self.policy {
+            let (store, sender, nonce) = (self.store.clone(), self.sender, self.nonce);
+            tokio::spawn(async move {
+                if let Err(e) = store.release(sender, nonce).await {
+                    warn!("Failed to release the policy reservation of {:?}: {}", sender, e);
+                }
+            });
+        }
+    }
+}
+
+async fn sign_hash(shard: &Shard, hash: H256) -> Result<(Bytes, Address), PaymasterError> {
+    let signature = shard.signer.sign_hash(hash).await.map_err(|e| {
+        error!("Failed to sign paymaster data: {}", e);
+        PaymasterError::SignatureVerificationFailed
+    })?;
+
+    // Recover the key that actually signed, which may be a fallback signer.
+    // ERC-1271 signature blobs are attributed to the contract signer.
+    let signer = Signature::try_from(signature.as_ref())
+        .ok()
+        .and_then(|signature| signature.recover(hash_message(hash)).ok())
+        .unwrap_or_else(|| shard.signer.address());
+
+    Ok((signature, signer))
+}
+
+// Current host time in seconds since the Unix epoch
+fn unix_time() -> Result<u64, PaymasterError> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .map_err(|e| PaymasterError::InvalidParameters(e.to_string()))
+}
+
+// Key of an operation in the replay sets
+fn operation_key(sender: Address, nonce: U256) -> String {
+    format!("{:?}:{}", sender, nonce)
+}
+
+// Mark an operation that can no longer be included as failed
+fn expire(status: &mut OperationStatus) {
+    status.status = OperationState::Failed;
+    status.reason = Some("Not included before validUntil".to_string());
+}
+
+// Tag the request's span so every log line of the decision carries the operation
+fn record_span_fields(user_op: &UserOperation, user_op_hash: H256, policy_id: Option<&str>) {
+    let span = Span::current();
+    span.record("sender", field::debug(user_op.sender));
+    span.record("user_op_hash", field::debug(user_op_hash));
+    if let Some(policy_id) = policy_id {
+        span.record("policy_id", policy_id);
+    }
+}
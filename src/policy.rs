@@ -0,0 +1,425 @@
+// src/policy.rs
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use ethers::types::{Address, U256};
+
+use crate::calldata;
+use crate::config::{AccountRule, PolicyConfig};
+use crate::error::PaymasterError;
+use crate::project;
+use crate::store::{DailyUsage, MemoryStore, Reservation, SponsorshipStore, Transfer};
+use crate::types::{PolicyUsage, SponsorshipContext, SponsorshipQuota, UserOperation};
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+// Configured sponsorship policies and those of projects, with what each has sponsored kept in
+// the sponsorship store
+pub struct PolicyRegistry {
+    policies: RwLock<HashMap<String, Arc<PolicyConfig>>>,
+    store: Arc<dyn SponsorshipStore>,
+}
+
+impl Default for PolicyRegistry {
+    fn default() -> Self {
+        Self::new(HashMap::new(), Arc::new(MemoryStore::default()))
+    }
+}
+
+impl PolicyRegistry {
+    pub fn new(policies: HashMap<String, PolicyConfig>, store: Arc<dyn SponsorshipStore>) -> Self {
+        Self {
+            policies: RwLock::new(policies.into_iter().map(|(id, policy)| (id, Arc::new(policy))).collect()),
+            store,
+        }
+    }
+
+    pub fn set_store(&mut self, store: Arc<dyn SponsorshipStore>) {
+        self.store = store;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.policies.read().unwrap().is_empty()
+    }
+
+    // Replace every policy whose ID starts with `prefix` with `policies`
+    pub fn replace(&self, prefix: &str, policies: HashMap<String, PolicyConfig>) {
+        let mut current = self.policies.write().unwrap();
+        current.retain(|id, _| !id.starts_with(prefix));
+        current.extend(policies.into_iter().map(|(id, policy)| (id, Arc::new(policy))));
+    }
+
+    pub fn is_priority(&self, policy_id: &str) -> bool {
+        self.get(policy_id).is_some_and(|policy| policy.priority)
+    }
+
+    pub fn allows_aggregator(&self, policy_id: &str, aggregator: Address) -> bool {
+        self.get(policy_id).is_some_and(|policy| policy.allowed_aggregators.contains(&aggregator))
+    }
+
+    // Check the request comes from an origin the policy sponsors, when it lists any
+    pub fn check_origin(&self, policy_id: &str, origin: Option<&str>) -> Result<(), PaymasterError> {
+        let policy = self.policy(policy_id)?;
+        let allowed = policy.allowed_origins.is_empty()
+            || origin.is_some_and(|origin| policy.allowed_origins.iter().any(|allowed| allowed == origin));
+        match allowed {
+            true => Ok(()),
+            false => Err(PaymasterError::InvalidParameters(format!(
+                "Sponsorship policy {} does not sponsor requests from {}", policy_id, origin.unwrap_or("unknown origins")
+            ))),
+        }
+    }
+
+    // IDs of the policies that list `api_key`
+    pub fn with_api_key(&self, api_key: &str) -> Vec<String> {
+        let policies = self.policies.read().unwrap();
+        policies.iter().filter(|(_, policy)| policy.api_keys.iter().any(|key| key == api_key)).map(|(id, _)| id.clone()).collect()
+    }
+
+    // Factories whose deployments the policy sponsors; any when empty
+    pub fn account_rules(&self, policy_id: &str) -> Vec<AccountRule> {
+        self.get(policy_id).map(|policy| policy.allowed_accounts.clone()).unwrap_or_default()
+    }
+
+    // Select the policy named by the request context. Once any policy is configured, every
+    // request has to name one, either by ID or through an API key, requests paying in a token
+    // included. A key listed by several policies, as a project's key is by each of its
+    // policies, must be sent with the ID of one of them. A policy that lists keys can only be
+    // selected with one of them, and so can every project policy.
+    pub fn resolve(&self, context: &SponsorshipContext) -> Result<Option<String>, PaymasterError> {
+        let policies = self.policies.read().unwrap();
+        let key_policies: Vec<&String> = match &context.api_key {
+            Some(api_key) => policies.iter().filter(|(_, policy)| policy.api_keys.contains(api_key)).map(|(id, _)| id).collect(),
+            None => Vec::new(),
+        };
+
+        match (&context.sponsorship_policy_id, &context.api_key) {
+            (Some(id), _) if !policies.contains_key(id) => {
+                Err(PaymasterError::InvalidParameters(format!("Unknown sponsorship policy {}", id)))
+            }
+            (Some(id), _) if key_policies.contains(&id) => Ok(Some(id.clone())),
+            // The API key may authenticate the caller or prove an origin without being the policy's
+            (Some(id), _) if policies[id].api_keys.is_empty() && !project::is_project_policy(id) => Ok(Some(id.clone())),
+            (Some(id), Some(_)) => Err(PaymasterError::InvalidParameters(format!(
+                "API key is not valid for sponsorship policy {}",
+                id
+            ))),
+            (Some(id), None) => Err(PaymasterError::InvalidParameters(format!(
+                "Sponsorship policy {} requires one of its API keys",
+                id
+            ))),
+            (None, Some(_)) if key_policies.len() == 1 => Ok(Some(key_policies[0].clone())),
+            (None, Some(_)) if key_policies.len() > 1 => Err(PaymasterError::InvalidParameters(
+                "API key is valid for several sponsorship policies, a sponsorshipPolicyId is required".to_string(),
+            )),
+            (None, _) if policies.is_empty() => Ok(None),
+            (None, _) => Err(PaymasterError::InvalidParameters(
+                "A sponsorshipPolicyId or an apiKey listed by a policy is required".to_string(),
+            )),
+        }
+    }
+
+    pub async fn quota(&self, policy_id: &str, now: u64) -> Result<SponsorshipQuota, PaymasterError> {
+        let policy = self.policy(policy_id)?;
+        let usage = self.usage_today(policy_id, now).await?;
+
+        let remaining_budget = policy.daily_budget.map(|budget| budget.saturating_sub(usage.committed()));
+        let remaining_ops_today = policy.max_ops_per_day.map(|max| max.saturating_sub(usage.ops));
+        let expired = policy.expires_at.is_some_and(|expires_at| now >= expires_at);
+
+        Ok(SponsorshipQuota {
+            policy_id: policy_id.to_string(),
+            available: !expired && remaining_budget != Some(U256::zero()) && remaining_ops_today != Some(0),
+            remaining_budget,
+            remaining_ops_today,
+            expires_at: policy.expires_at,
+            resets_at: (now / SECONDS_PER_DAY + 1) * SECONDS_PER_DAY,
+        })
+    }
+
+    // Today's consumption of every policy, ordered by ID
+    pub async fn usage(&self, now: u64) -> Result<Vec<PolicyUsage>, PaymasterError> {
+        let policies: Vec<_> = self.policies.read().unwrap().iter().map(|(id, policy)| (id.clone(), policy.clone())).collect();
+        let mut usage = Vec::with_capacity(policies.len());
+        for (policy_id, policy) in &policies {
+            let today = self.usage_today(policy_id, now).await?;
+            usage.push(PolicyUsage {
+                policy_id: policy_id.clone(),
+                spent_today: today.committed(),
+                ops_today: today.ops,
+                daily_budget: policy.daily_budget,
+                max_ops_per_day: policy.max_ops_per_day,
+            });
+        }
+        usage.sort_by(|a, b| a.policy_id.cmp(&b.policy_id));
+        Ok(usage)
+    }
+
+    // Check that the policy can sponsor an operation costing at most `max_cost`
+    pub async fn check(&self, policy_id: &str, max_cost: U256, now: u64) -> Result<(), PaymasterError> {
+        let usage = self.usage_today(policy_id, now).await?;
+        self.check_usage(policy_id, &usage, max_cost, now)
+    }
+
+    // Check the operation's verificationGasLimit against the policy's caps, including the one for
+    // senders without on-chain history when `new_sender` is set
+    pub fn check_verification_gas(
+        &self,
+        policy_id: &str,
+        verification_gas_limit: U256,
+        new_sender: bool,
+    ) -> Result<(), PaymasterError> {
+        let policy = self.policy(policy_id)?;
+        let caps = [
+            (policy.max_verification_gas_limit, ""),
+            (policy.new_sender_max_verification_gas_limit.filter(|_| new_sender), " for new senders"),
+        ];
+        for (cap, scope) in caps {
+            if let Some(cap) = cap.filter(|cap| verification_gas_limit > U256::from(*cap)) {
+                return Err(PaymasterError::InvalidUserOperation(format!(
+                    "verificationGasLimit {} exceeds the {} policy limit {}{}",
+                    verification_gas_limit, policy_id, cap, scope,
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Check every call the operation makes, each call of a batch on its own, against the
+    // policy's call rules and value caps, so that a call the policy does not sponsor cannot be
+    // slipped in next to one it does. Returns the transfers to count against daily limits once
+    // the operation is signed.
+    pub async fn check_calls(&self, policy_id: &str, user_op: &UserOperation, now: u64) -> Result<Vec<Transfer>, PaymasterError> {
+        let policy = self.policy(policy_id)?;
+        if policy.allowed_calls.is_empty()
+            && policy.max_batch_value.is_none()
+            && policy.max_daily_value.is_none()
+            && policy.token_limits.is_empty()
+        {
+            return Ok(Vec::new());
+        }
+
+        let calls = calldata::decode_calls(&user_op.call_data).ok_or_else(|| {
+            PaymasterError::InvalidUserOperation(format!(
+                "Operations under policy {} must use a known execute function", policy_id
+            ))
+        })?;
+
+        let mut total_value = U256::zero();
+        let mut token_amounts: HashMap<Address, U256> = HashMap::new();
+        for (index, call) in calls.iter().enumerate() {
+            if !policy.allowed_calls.is_empty() {
+                let selector = call.data.get(..4).and_then(|selector| <[u8; 4]>::try_from(selector).ok());
+                let rule = policy.allowed_calls.iter().find(|rule| {
+                    rule.target == call.target
+                        && (rule.selectors.is_empty() || selector.is_some_and(|selector| rule.selectors.contains(&selector)))
+                });
+                let Some(rule) = rule else {
+                    return Err(PaymasterError::InvalidUserOperation(format!(
+                        "Call {} to {:?} is not sponsored by policy {}", index, call.target, policy_id
+                    )));
+                };
+                if let Some(max_value) = rule.max_value.filter(|max_value| call.value > *max_value) {
+                    return Err(PaymasterError::PolicyLimitExceeded(format!(
+                        "Call {} sends {} wei to {:?}, policy {} allows {} wei",
+                        index, call.value, call.target, policy_id, max_value,
+                    )));
+                }
+            }
+            total_value = total_value.saturating_add(call.value);
+
+            if policy.token_limits.iter().any(|limit| limit.token == call.target) {
+                if let Some(amount) = calldata::token_amount(call) {
+                    let total = token_amounts.entry(call.target).or_default();
+                    *total = total.saturating_add(amount);
+                }
+            }
+        }
+
+        if let Some(max_batch_value) = policy.max_batch_value.filter(|max_batch_value| total_value > *max_batch_value) {
+            return Err(PaymasterError::PolicyLimitExceeded(format!(
+                "Calls send {} wei in total, policy {} allows {} wei", total_value, policy_id, max_batch_value
+            )));
+        }
+        for limit in &policy.token_limits {
+            let amount = token_amounts.get(&limit.token).copied().unwrap_or_default();
+            if let Some(max_per_op) = limit.max_per_op.filter(|max_per_op| amount > *max_per_op) {
+                return Err(PaymasterError::PolicyLimitExceeded(format!(
+                    "Calls move {} of {:?}, policy {} allows {}", amount, limit.token, policy_id, max_per_op
+                )));
+            }
+        }
+
+        // The zero address stands for the native currency
+        let native = policy.max_daily_value.map(|daily_limit| Transfer {
+            asset: Address::zero(),
+            amount: total_value,
+            daily_limit,
+        });
+        let tokens = policy.token_limits.iter().filter_map(|limit| {
+            limit.max_per_day.map(|daily_limit| Transfer {
+                asset: limit.token,
+                amount: token_amounts.get(&limit.token).copied().unwrap_or_default(),
+                daily_limit,
+            })
+        });
+        let transfers: Vec<Transfer> = native.into_iter().chain(tokens).filter(|transfer| !transfer.amount.is_zero()).collect();
+
+        let day = now / SECONDS_PER_DAY;
+        for transfer in &transfers {
+            transfer.check(policy_id, self.store.transferred(policy_id, day, transfer.asset).await?)?;
+        }
+        Ok(transfers)
+    }
+
+    // Count the transfers of a signed operation against the policy's daily limits, replacing
+    // the count of an earlier signature of the same sender and nonce
+    pub async fn record_transfers(
+        &self,
+        sender: Address,
+        nonce: U256,
+        policy_id: &str,
+        transfers: &[Transfer],
+        now: u64,
+    ) -> Result<(), PaymasterError> {
+        if transfers.is_empty() {
+            return Ok(());
+        }
+        self.store.record_transfers(sender, nonce, policy_id, now / SECONDS_PER_DAY, transfers).await
+    }
+
+    // Check the policy and hold `max_cost` of its budget for an operation about to be signed.
+    // Signing the same sender and nonce again replaces the earlier reservation, since only
+    // one of the two can be included.
+    pub async fn reserve(
+        &self,
+        policy_id: &str,
+        sender: Address,
+        nonce: U256,
+        max_cost: U256,
+        valid_until: u64,
+        now: u64,
+    ) -> Result<(), PaymasterError> {
+        let reservation = Reservation {
+            policy_id: policy_id.to_string(),
+            day: now / SECONDS_PER_DAY,
+            amount: max_cost,
+            valid_until,
+        };
+        self.store
+            .reserve(sender, nonce, reservation, &|usage| self.check_usage(policy_id, usage, max_cost, now))
+            .await
+    }
+
+    // Drop the reservation of an operation that was not signed after all
+    pub async fn release(&self, sender: Address, nonce: U256) -> Result<(), PaymasterError> {
+        self.store.release(sender, nonce).await
+    }
+
+    // Replace the reservation of an included operation with what it actually cost.
+    // Returns the policy it was charged to, or None if the operation had no reservation.
+    pub async fn settle(&self, sender: Address, nonce: U256, actual_cost: U256) -> Result<Option<String>, PaymasterError> {
+        self.store.settle(sender, nonce, actual_cost).await
+    }
+
+    // Release reservations whose signature expired before `block_timestamp` without the
+    // operation being included. Returns how many were released.
+    pub async fn release_expired(&self, block_timestamp: u64) -> Result<usize, PaymasterError> {
+        self.store.release_expired(block_timestamp).await
+    }
+
+    fn check_usage(&self, policy_id: &str, usage: &DailyUsage, max_cost: U256, now: u64) -> Result<(), PaymasterError> {
+        let policy = self.policy(policy_id)?;
+
+        if policy.expires_at.is_some_and(|expires_at| now >= expires_at) {
+            return Err(PaymasterError::PolicyLimitExceeded(format!("policy {} has expired", policy_id)));
+        }
+
+        if policy.max_ops_per_day.is_some_and(|max| usage.ops >= max) {
+            return Err(PaymasterError::PolicyLimitExceeded(format!(
+                "policy {} has used its daily operation allowance", policy_id
+            )));
+        }
+
+        if policy.daily_budget.is_some_and(|budget| usage.committed().saturating_add(max_cost) > budget) {
+            return Err(PaymasterError::PolicyLimitExceeded(format!(
+                "max cost {} wei exceeds the remaining daily budget of policy {}", max_cost, policy_id
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, policy_id: &str) -> Option<Arc<PolicyConfig>> {
+        self.policies.read().unwrap().get(policy_id).cloned()
+    }
+
+    fn policy(&self, policy_id: &str) -> Result<Arc<PolicyConfig>, PaymasterError> {
+        self.get(policy_id)
+            .ok_or_else(|| PaymasterError::InvalidParameters(format!("Unknown sponsorship policy {}", policy_id)))
+    }
+
+    async fn usage_today(&self, policy_id: &str, now: u64) -> Result<DailyUsage, PaymasterError> {
+        self.store.usage(policy_id, now / SECONDS_PER_DAY).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> PolicyRegistry {
+        let keyed = |api_keys: &[&str]| PolicyConfig {
+            api_keys: api_keys.iter().map(|api_key| api_key.to_string()).collect(),
+            ..Default::default()
+        };
+        PolicyRegistry::new(
+            HashMap::from([
+                ("open".to_string(), PolicyConfig::default()),
+                ("launch".to_string(), keyed(&["launch-key"])),
+                ("acme:onboarding".to_string(), keyed(&["acme-key"])),
+                ("acme:trading".to_string(), keyed(&["acme-key", "trading-key"])),
+                ("acme:keyless".to_string(), keyed(&[])),
+            ]),
+            Arc::new(MemoryStore::default()),
+        )
+    }
+
+    fn resolve(policy_id: Option<&str>, api_key: Option<&str>) -> Result<Option<String>, PaymasterError> {
+        registry().resolve(&SponsorshipContext {
+            sponsorship_policy_id: policy_id.map(str::to_string),
+            api_key: api_key.map(str::to_string),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn resolves_policies_by_id_or_key() {
+        assert_eq!(resolve(Some("open"), None).unwrap().as_deref(), Some("open"));
+        assert_eq!(resolve(None, Some("launch-key")).unwrap().as_deref(), Some("launch"));
+        assert_eq!(resolve(Some("launch"), Some("launch-key")).unwrap().as_deref(), Some("launch"));
+        assert_eq!(resolve(None, Some("trading-key")).unwrap().as_deref(), Some("acme:trading"));
+        assert_eq!(resolve(Some("open"), Some("access-key")).unwrap().as_deref(), Some("open"));
+
+        assert!(resolve(None, None).is_err());
+        assert!(resolve(Some("unknown"), None).is_err());
+        assert!(resolve(None, Some("unknown-key")).is_err());
+    }
+
+    #[test]
+    fn requires_a_key_for_keyed_and_project_policies() {
+        assert!(resolve(Some("launch"), None).is_err());
+        assert!(resolve(Some("acme:onboarding"), None).is_err());
+        assert!(resolve(Some("acme:keyless"), None).is_err());
+        assert!(resolve(Some("launch"), Some("acme-key")).is_err());
+        assert!(resolve(Some("acme:keyless"), Some("access-key")).is_err());
+    }
+
+    #[test]
+    fn needs_the_policy_id_for_a_key_of_several_policies() {
+        assert!(resolve(None, Some("acme-key")).is_err());
+        assert_eq!(resolve(Some("acme:onboarding"), Some("acme-key")).unwrap().as_deref(), Some("acme:onboarding"));
+        assert_eq!(resolve(Some("acme:trading"), Some("acme-key")).unwrap().as_deref(), Some("acme:trading"));
+        assert!(resolve(Some("acme:onboarding"), Some("trading-key")).is_err());
+    }
+}
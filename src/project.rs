@@ -0,0 +1,276 @@
+// src/project.rs
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::audit::AuditEntry;
+use crate::config::PolicyConfig;
+use crate::error::PaymasterError;
+use crate::policy::PolicyRegistry;
+
+// Separates the project from the policy in the IDs its policies are served under
+pub const POLICY_SEPARATOR: char = ':';
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// A tenant of the service, with its own API keys, policies and webhooks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Keys that select the project's policies as `apiKey` in the sponsorship context
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    // Chain IDs the project is served on; every chain when empty
+    #[serde(default)]
+    pub chains: Vec<u64>,
+    // Policies keyed by ID, in the format of the config file's `[policies]`. They are served as
+    // `<project>:<policy>`, so budgets are counted apart from every other project's.
+    #[serde(default)]
+    pub policies: BTreeMap<String, Value>,
+    // Sent every sponsorship decision made under the project's policies
+    #[serde(default)]
+    pub webhook_urls: Vec<String>,
+    // Keeps the project but stops serving its policies
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+impl Project {
+    // Policy IDs as served, with what the config of each says
+    pub fn policies(&self) -> Result<HashMap<String, PolicyConfig>, PaymasterError> {
+        self.policies
+            .iter()
+            .map(|(policy_id, policy)| {
+                let mut policy: PolicyConfig = serde_json::from_value(policy.clone()).map_err(|e| {
+                    PaymasterError::InvalidParameters(format!("Invalid policy {} of project {}: {}", policy_id, self.id, e))
+                })?;
+                policy.api_keys.extend(self.api_keys.iter().cloned());
+                Ok((format!("{}{}", policy_prefix(&self.id), policy_id), policy))
+            })
+            .collect()
+    }
+
+    pub fn serves(&self, chain_id: u64) -> bool {
+        !self.disabled && (self.chains.is_empty() || self.chains.contains(&chain_id))
+    }
+
+    fn validate(&self) -> Result<(), PaymasterError> {
+        let valid_id = |id: &str| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_id(&self.id) {
+            return Err(PaymasterError::InvalidParameters(
+                "Project IDs may only use letters, digits, '-' and '_'".to_string(),
+            ));
+        }
+        if let Some(policy_id) = self.policies.keys().find(|policy_id| !valid_id(policy_id)) {
+            return Err(PaymasterError::InvalidParameters(format!(
+                "Policy ID {} may only use letters, digits, '-' and '_'", policy_id
+            )));
+        }
+        if self.api_keys.iter().any(String::is_empty) {
+            return Err(PaymasterError::InvalidParameters("API keys must not be empty".to_string()));
+        }
+        for webhook_url in &self.webhook_urls {
+            let url = Url::parse(webhook_url)
+                .map_err(|e| PaymasterError::InvalidParameters(format!("Invalid webhook URL {}: {}", webhook_url, e)))?;
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(PaymasterError::InvalidParameters(format!("Webhook URL {} must use http or https", webhook_url)));
+            }
+        }
+        self.policies().map(|_| ())
+    }
+}
+
+// Start of the IDs a project's policies are served under
+pub fn policy_prefix(project_id: &str) -> String {
+    format!("{}{}", project_id, POLICY_SEPARATOR)
+}
+
+// Whether a policy is served for a project rather than from the config file
+pub fn is_project_policy(policy_id: &str) -> bool {
+    policy_id.contains(POLICY_SEPARATOR)
+}
+
+// Projects known to this server, kept in step with the sponsorship store
+pub struct ProjectRegistry {
+    chain_id: u64,
+    projects: Mutex<HashMap<String, Project>>,
+    client: reqwest::Client,
+}
+
+impl ProjectRegistry {
+    pub fn new(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            projects: Mutex::new(HashMap::new()),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn get(&self, project_id: &str) -> Result<Project, PaymasterError> {
+        self.projects
+            .lock()
+            .unwrap()
+            .get(project_id)
+            .cloned()
+            .ok_or_else(|| PaymasterError::ProjectNotFound(project_id.to_string()))
+    }
+
+    // Every project, ordered by ID
+    pub fn list(&self) -> Vec<Project> {
+        let mut projects: Vec<Project> = self.projects.lock().unwrap().values().cloned().collect();
+        projects.sort_by(|a, b| a.id.cmp(&b.id));
+        projects
+    }
+
+    // Check a project can be saved: its fields are valid, and none of its API keys belong to
+    // another project or to a policy outside the project
+    pub fn check(&self, project: &Project, policies: &PolicyRegistry) -> Result<(), PaymasterError> {
+        project.validate()?;
+        let prefix = policy_prefix(&project.id);
+        let owner = project.api_keys.iter().find_map(|api_key| {
+            policies.with_api_key(api_key).into_iter().find(|policy_id| !policy_id.starts_with(&prefix))
+        });
+        if let Some(policy_id) = owner {
+            return Err(PaymasterError::InvalidParameters(format!("An API key is already used by policy {}", policy_id)));
+        }
+        let projects = self.projects.lock().unwrap();
+        let taken = projects
+            .values()
+            .filter(|other| other.id != project.id)
+            .find(|other| other.api_keys.iter().any(|api_key| project.api_keys.contains(api_key)));
+        if let Some(other) = taken {
+            return Err(PaymasterError::InvalidParameters(format!("An API key is already used by project {}", other.id)));
+        }
+        Ok(())
+    }
+
+    // Add or replace a project, returning the policies to serve for it on this chain
+    pub fn insert(&self, project: Project) -> Result<HashMap<String, PolicyConfig>, PaymasterError> {
+        let policies = match project.serves(self.chain_id) {
+            true => project.policies()?,
+            false => HashMap::new(),
+        };
+        self.projects.lock().unwrap().insert(project.id.clone(), project);
+        Ok(policies)
+    }
+
+    pub fn remove(&self, project_id: &str) -> Option<Project> {
+        self.projects.lock().unwrap().remove(project_id)
+    }
+
+    // Post a decision to the webhooks of the project owning its policy. Deliveries are not
+    // retried; failures are only logged.
+    pub fn notify(&self, entry: &AuditEntry) {
+        let Some((project_id, _)) = entry.policy_id.as_deref().and_then(|policy_id| policy_id.split_once(POLICY_SEPARATOR)) else {
+            return;
+        };
+        let webhook_urls = match self.projects.lock().unwrap().get(project_id) {
+            Some(project) => project.webhook_urls.clone(),
+            None => return,
+        };
+
+        let body = match serde_json::to_string(entry) {
+            Ok(body) => body,
+            Err(e) => return warn!("Failed to encode a sponsorship decision for webhooks: {}", e),
+        };
+        for webhook_url in webhook_urls {
+            let request = self.client
+                .post(&webhook_url)
+                .timeout(WEBHOOK_TIMEOUT)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            tokio::spawn(async move {
+                if let Err(e) = request.send().await.and_then(|response| response.error_for_status()) {
+                    warn!("Failed to deliver a sponsorship decision to webhook {}: {}", webhook_url, e);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn project() -> Project {
+        serde_json::from_value(json!({
+            "id": "acme",
+            "apiKeys": ["acme-key"],
+            "policies": {
+                "onboarding": { "max_ops_per_day": 100, "api_keys": ["onboarding-key"] },
+                "trading": {}
+            },
+            "webhookUrls": ["https://hooks.acme.example/arka"]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn validates_ids_keys_and_webhooks() {
+        assert!(project().validate().is_ok());
+
+        let invalid = [
+            Project { id: "acme:eu".to_string(), ..project() },
+            Project { id: String::new(), ..project() },
+            Project { api_keys: vec![String::new()], ..project() },
+            Project { webhook_urls: vec!["ftp://hooks.acme.example".to_string()], ..project() },
+            Project { webhook_urls: vec!["not a url".to_string()], ..project() },
+            Project { policies: BTreeMap::from([("on:boarding".to_string(), json!({}))]), ..project() },
+            Project { policies: BTreeMap::from([("onboarding".to_string(), json!({ "max_ops_per_day": "many" }))]), ..project() },
+        ];
+        for project in invalid {
+            assert!(project.validate().is_err(), "{:?} passed validation", project.id);
+        }
+    }
+
+    #[test]
+    fn serves_policies_under_the_project_with_its_keys() {
+        let policies = project().policies().unwrap();
+
+        let mut ids: Vec<&String> = policies.keys().collect();
+        ids.sort();
+        assert_eq!(ids, ["acme:onboarding", "acme:trading"]);
+        assert!(ids.iter().all(|id| is_project_policy(id)));
+        assert_eq!(policies["acme:onboarding"].api_keys, ["onboarding-key", "acme-key"]);
+        assert_eq!(policies["acme:onboarding"].max_ops_per_day, Some(100));
+        assert_eq!(policies["acme:trading"].api_keys, ["acme-key"]);
+    }
+
+    #[test]
+    fn refuses_keys_used_elsewhere() {
+        let registry = ProjectRegistry::new(1);
+        let config_policies = HashMap::from([(
+            "launch".to_string(),
+            PolicyConfig { api_keys: vec!["launch-key".to_string()], ..Default::default() },
+        )]);
+        let policies = PolicyRegistry::new(config_policies, Arc::new(MemoryStore::default()));
+
+        let served = registry.insert(project()).unwrap();
+        policies.replace(&policy_prefix("acme"), served);
+        // A project may keep its own keys when it is saved again
+        assert!(registry.check(&project(), &policies).is_ok());
+
+        let other = Project { id: "globex".to_string(), policies: BTreeMap::new(), webhook_urls: vec![], ..project() };
+        assert!(registry.check(&other, &policies).is_err());
+        let other = Project { api_keys: vec!["launch-key".to_string()], ..other };
+        assert!(registry.check(&other, &policies).is_err());
+        let other = Project { api_keys: vec!["globex-key".to_string()], ..other };
+        assert!(registry.check(&other, &policies).is_ok());
+    }
+}
@@ -0,0 +1,271 @@
+// src/provider.rs
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ethers::prelude::*;
+use ethers::providers::{HttpClientError, JsonRpcClient, JsonRpcError, RpcError};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::config::{ProviderConfig, ProxyConfig};
+use crate::error::PaymasterError;
+
+pub type EthProvider = Provider<GuardedHttp>;
+
+// Create the Ethereum provider, routing requests through the chain's proxy when one is configured
+pub fn connect(eth_rpc_url: &str, proxy: Option<&ProxyConfig>, config: &ProviderConfig) -> Result<EthProvider> {
+    let url = Url::parse(eth_rpc_url).with_context(|| format!("Invalid Ethereum RPC URL {}", eth_rpc_url))?;
+
+    let Some(proxy) = proxy else {
+        return Ok(Provider::new(GuardedHttp::new(Http::new(url), config)));
+    };
+
+    let mut proxy_url = Url::parse(&proxy.url).with_context(|| format!("Invalid proxy URL {}", proxy.url))?;
+
+    // Credentials travel in the proxy URL: basic auth for HTTP proxies, username/password for SOCKS5
+    if let Some(username) = &proxy.username {
+        proxy_url.set_username(username)
+            .map_err(|_| anyhow::anyhow!("Proxy URL {} cannot carry credentials", proxy.url))?;
+        proxy_url.set_password(proxy.password()?.as_deref())
+            .map_err(|_| anyhow::anyhow!("Proxy URL {} cannot carry credentials", proxy.url))?;
+    }
+
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(proxy_url)?)
+        .build()?;
+
+    info!("Routing Ethereum provider requests through proxy {}", proxy.url);
+    Ok(Provider::new(GuardedHttp::new(Http::new_with_client(url, client), config)))
+}
+
+// Map a failed provider call, reporting the endpoint as unavailable while its circuit is open
+pub fn call_error(client: &EthProvider, e: impl fmt::Display) -> PaymasterError {
+    unavailable(client).unwrap_or_else(|| PaymasterError::EthereumProviderError(e.to_string()))
+}
+
+pub fn unavailable(client: &EthProvider) -> Option<PaymasterError> {
+    client.as_ref().retry_after().map(|retry_after| PaymasterError::ProviderUnavailable(retry_after.as_secs()))
+}
+
+#[derive(Debug, Error)]
+pub enum GuardedHttpError {
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+
+    #[error("{0} timed out after {1:?}")]
+    Timeout(String, Duration),
+
+    #[error("Circuit open, retry after {}s", .0.as_secs())]
+    CircuitOpen(Duration),
+}
+
+impl RpcError for GuardedHttpError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            GuardedHttpError::Http(e) => e.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            GuardedHttpError::Http(e) => e.as_serde_error(),
+            _ => None,
+        }
+    }
+}
+
+impl From<GuardedHttpError> for ProviderError {
+    fn from(e: GuardedHttpError) -> Self {
+        match e {
+            GuardedHttpError::Http(e) => e.into(),
+            e => ProviderError::JsonRpcClientError(Box::new(e)),
+        }
+    }
+}
+
+// HTTP transport that bounds every call by a timeout and stops calling an endpoint that keeps failing.
+// Clones share one circuit breaker.
+#[derive(Debug, Clone)]
+pub struct GuardedHttp {
+    inner: Http,
+    timeout: Duration,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl GuardedHttp {
+    pub fn new(inner: Http, config: &ProviderConfig) -> Self {
+        Self {
+            inner,
+            timeout: Duration::from_millis(config.timeout_ms),
+            breaker: Arc::new(CircuitBreaker::new(config.failure_threshold, Duration::from_secs(config.cooldown_secs))),
+        }
+    }
+
+    // Time until the endpoint is tried again, if the circuit is open
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.breaker.retry_after()
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for GuardedHttp {
+    type Error = GuardedHttpError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let permit = self.breaker.acquire().map_err(GuardedHttpError::CircuitOpen)?;
+
+        let result = match tokio::time::timeout(self.timeout, JsonRpcClient::request(&self.inner, method, params)).await {
+            Ok(result) => result.map_err(GuardedHttpError::from),
+            Err(_) => Err(GuardedHttpError::Timeout(method.to_string(), self.timeout)),
+        };
+
+        // A JSON-RPC error response (a revert, a bad argument) still means the endpoint is up
+        match &result {
+            Err(e) if !e.is_error_response() => permit.record_failure(method, e),
+            _ => permit.record_success(),
+        }
+        result
+    }
+}
+
+// Trips after `threshold` consecutive failures; once the cooldown elapses a single trial call
+// is let through, closing the circuit on success and reopening it on failure
+#[derive(Debug)]
+struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    fn acquire(&self) -> Result<BreakerPermit<'_>, Duration> {
+        let mut state = self.state.lock().unwrap();
+        match state.open_until {
+            None => Ok(BreakerPermit { breaker: self, trial: false }),
+            Some(until) if Instant::now() < until => Err(round_up(until - Instant::now())),
+            Some(_) if state.trial_in_flight => Err(Duration::from_secs(1)),
+            Some(_) => {
+                state.trial_in_flight = true;
+                Ok(BreakerPermit { breaker: self, trial: true })
+            }
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        match state.open_until {
+            Some(until) if Instant::now() < until => Some(round_up(until - Instant::now())),
+            Some(_) if state.trial_in_flight => Some(Duration::from_secs(1)),
+            _ => None,
+        }
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() {
+            info!("Ethereum provider recovered, closing circuit");
+        }
+        *state = BreakerState::default();
+    }
+
+    fn record_failure(&self, method: &str, e: &GuardedHttpError) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        state.trial_in_flight = false;
+        if state.open_until.is_some() || state.consecutive_failures >= self.threshold {
+            warn!(
+                "Ethereum provider failed {} consecutive calls (last {}: {}), opening circuit for {:?}",
+                state.consecutive_failures, method, e, self.cooldown,
+            );
+            state.open_until = Some(Instant::now() + self.cooldown);
+        }
+    }
+}
+
+// Lets one call through the breaker. A trial call that is dropped before its outcome is
+// recorded, as when the request it serves is cancelled, lets the next call be the trial.
+struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    trial: bool,
+}
+
+impl BreakerPermit<'_> {
+    fn record_success(mut self) {
+        self.trial = false;
+        self.breaker.record_success();
+    }
+
+    fn record_failure(mut self, method: &str, e: &GuardedHttpError) {
+        self.trial = false;
+        self.breaker.record_failure(method, e);
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.trial {
+            self.breaker.state.lock().unwrap().trial_in_flight = false;
+        }
+    }
+}
+
+// Whole seconds, so a retry hint never undershoots
+fn round_up(duration: Duration) -> Duration {
+    Duration::from_secs(duration.as_secs() + u64::from(duration.subsec_nanos() > 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tripped() -> CircuitBreaker {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.acquire().unwrap().record_failure("eth_call", &GuardedHttpError::Timeout("eth_call".to_string(), Duration::ZERO));
+        breaker
+    }
+
+    #[test]
+    fn lets_a_single_trial_through() {
+        let breaker = tripped();
+        let trial = breaker.acquire().unwrap();
+        assert!(breaker.acquire().is_err());
+        trial.record_success();
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn frees_the_trial_of_a_dropped_call() {
+        let breaker = tripped();
+        drop(breaker.acquire().unwrap());
+        let trial = breaker.acquire().unwrap();
+        assert!(trial.trial);
+        assert!(breaker.acquire().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+// src/rebalance.rs
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use ethers::types::{Address, U256};
+use ethers::utils::format_ether;
+use tracing::{error, info, warn};
+
+use crate::config::RebalanceConfig;
+use crate::deposit::DepositManager;
+use crate::error::PaymasterError;
+use crate::metrics::Metrics;
+
+// Keeps paymaster deposits within the configured band
+pub struct Rebalancer {
+    managers: Vec<DepositManager>,
+    min_deposit: U256,
+    target_deposit: U256,
+    // Upper bound and where the excess goes
+    drain: Option<(U256, Address)>,
+    min_funding_balance: Option<U256>,
+    metrics: Arc<Metrics>,
+}
+
+impl Rebalancer {
+    pub fn new(config: &RebalanceConfig, managers: Vec<DepositManager>, metrics: Arc<Metrics>) -> Result<Self> {
+        let (Some(min_deposit), Some(target_deposit)) = (config.min_deposit, config.target_deposit) else {
+            bail!("Rebalancing needs min_deposit and target_deposit");
+        };
+        if target_deposit < min_deposit {
+            bail!("target_deposit must be at least min_deposit");
+        }
+        let drain = match (config.max_deposit, config.treasury) {
+            (Some(max_deposit), _) if max_deposit <= target_deposit => bail!("max_deposit must be above target_deposit"),
+            (Some(max_deposit), Some(treasury)) => Some((max_deposit, treasury)),
+            (Some(_), None) => bail!("max_deposit needs a treasury to withdraw to"),
+            (None, _) => None,
+        };
+
+        Ok(Self {
+            managers,
+            min_deposit,
+            target_deposit,
+            drain,
+            min_funding_balance: config.min_funding_balance,
+            metrics,
+        })
+    }
+
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let mut funding_low = match self.funding_balance_low().await {
+                Ok(low) => low,
+                Err(e) => {
+                    error!("Failed to read the funding wallet balance: {}", e);
+                    Metrics::increment(&self.metrics.rebalance_failures);
+                    false
+                }
+            };
+            for manager in &self.managers {
+                if let Err(e) = self.rebalance(manager, &mut funding_low).await {
+                    error!("Failed to rebalance the deposit of paymaster {:?}: {}", manager.paymaster_address(), e);
+                    Metrics::increment(&self.metrics.rebalance_failures);
+                }
+            }
+            self.metrics.funding_balance_low.store(funding_low as u64, Ordering::Relaxed);
+        }
+    }
+
+    async fn rebalance(&self, manager: &DepositManager, funding_low: &mut bool) -> Result<()> {
+        let deposit = manager.deposit_balance().await?;
+
+        if deposit < self.min_deposit {
+            let amount = self.target_deposit - deposit;
+            let available = manager.wallet_balance().await?;
+            // The wallet also pays gas for the deposit transaction
+            if available <= amount {
+                *funding_low = true;
+                bail!(
+                    "funding wallet holds {} ETH, not enough for a {} ETH top-up",
+                    format_ether(available), format_ether(amount),
+                );
+            }
+
+            let tx_hash = manager.deposit(amount).await?;
+            info!(
+                "Topped up paymaster {:?} from {} to {} ETH in transaction {:?}",
+                manager.paymaster_address(), format_ether(deposit), format_ether(self.target_deposit), tx_hash,
+            );
+            Metrics::increment(&self.metrics.deposit_top_ups);
+        } else if let Some((max_deposit, treasury)) = self.drain.filter(|(max_deposit, _)| deposit > *max_deposit) {
+            let amount = deposit - self.target_deposit;
+            let tx_hash = manager.withdraw_to(treasury, amount).await?;
+            info!(
+                "Withdrew {} ETH above the {} ETH ceiling of paymaster {:?} to {:?} in transaction {:?}",
+                format_ether(amount), format_ether(max_deposit), manager.paymaster_address(), treasury, tx_hash,
+            );
+            Metrics::increment(&self.metrics.deposit_withdrawals);
+        }
+
+        Ok(())
+    }
+
+    async fn funding_balance_low(&self) -> Result<bool, PaymasterError> {
+        let (Some(min_funding_balance), Some(manager)) = (self.min_funding_balance, self.managers.first()) else {
+            return Ok(false);
+        };
+
+        let balance = manager.wallet_balance().await?;
+        let low = balance < min_funding_balance;
+        if low {
+            warn!(
+                "Funding wallet holds {} ETH, below the {} ETH minimum",
+                format_ether(balance), format_ether(min_funding_balance),
+            );
+        }
+        Ok(low)
+    }
+}
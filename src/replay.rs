@@ -0,0 +1,127 @@
+// src/replay.rs
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::audit::Decision;
+use crate::error::PaymasterError;
+use crate::paymaster::Paymaster;
+use crate::types::{SponsorshipContext, UserOperation};
+
+// A sponsorship request and the decision it got, as captured for replay. The context only keeps
+// the policy the request resolved to and the token; API keys and session tokens are left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayRecord {
+    pub timestamp: u64,
+    pub user_op: UserOperation,
+    pub context: SponsorshipContext,
+    pub user_op_hash: H256,
+    pub decision: Decision,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+// Appends every sponsorship request to a JSON lines file
+pub struct ReplayCapture {
+    path: PathBuf,
+    // Serializes writers so lines are not interleaved
+    lock: Mutex<()>,
+}
+
+impl ReplayCapture {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, lock: Mutex::new(()) }
+    }
+
+    pub async fn append(&self, record: &ReplayRecord) -> Result<(), PaymasterError> {
+        let line = serde_json::to_string(record).map_err(|e| PaymasterError::StorageError(e.to_string()))?;
+
+        let _guard = self.lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| PaymasterError::StorageError(e.to_string()))?;
+        file.write_all(format!("{}\n", line).as_bytes())
+            .await
+            .and(file.flush().await)
+            .map_err(|e| PaymasterError::StorageError(e.to_string()))
+    }
+}
+
+// A captured request whose replay came out differently
+#[derive(Debug, Clone)]
+pub struct ReplayDiff {
+    // Line of the request in the capture file
+    pub line: usize,
+    pub recorded: ReplayRecord,
+    pub user_op_hash: H256,
+    pub decision: Decision,
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for ReplayDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {} {:?}: ", self.line, self.recorded.user_op_hash)?;
+        if self.user_op_hash != self.recorded.user_op_hash {
+            write!(f, "userOpHash is now {:?}; ", self.user_op_hash)?;
+        }
+        write!(
+            f,
+            "{} -> {}",
+            describe(self.recorded.decision, self.recorded.reason.as_deref()),
+            describe(self.decision, self.reason.as_deref()),
+        )
+    }
+}
+
+fn describe(decision: Decision, reason: Option<&str>) -> String {
+    match (decision, reason) {
+        (Decision::Rejected, Some(reason)) => format!("rejected ({})", reason),
+        (decision, _) => decision.as_str().to_string(),
+    }
+}
+
+pub async fn read_records(path: &Path) -> Result<Vec<ReplayRecord>> {
+    let contents = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read replay file {}", path.display()))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| format!("Malformed replay record on line {}", index + 1))
+        })
+        .collect()
+}
+
+// Run the captured requests through the paymaster in order, returning those whose decision, or
+// rejection reason, or userOpHash changed. Ticket IDs differ between runs, so parked operations
+// only compare by decision.
+pub async fn replay(paymaster: &Paymaster, records: Vec<ReplayRecord>) -> Vec<ReplayDiff> {
+    let mut diffs = Vec::new();
+    for (index, recorded) in records.into_iter().enumerate() {
+        let user_op_hash = paymaster.user_op_hash(&recorded.user_op);
+        let (decision, reason) = match paymaster.sign_user_operation(&recorded.user_op, &recorded.context).await {
+            Ok(_) => (Decision::Sponsored, None),
+            Err(e @ PaymasterError::ApprovalRequired(_)) => (Decision::Pending, Some(e.to_string())),
+            Err(e) => (Decision::Rejected, Some(e.to_string())),
+        };
+
+        let changed = user_op_hash != recorded.user_op_hash
+            || decision != recorded.decision
+            || (decision == Decision::Rejected && reason != recorded.reason);
+        if changed {
+            diffs.push(ReplayDiff { line: index + 1, recorded, user_op_hash, decision, reason });
+        }
+    }
+    diffs
+}
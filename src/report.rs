@@ -0,0 +1,131 @@
+// src/report.rs
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime};
+use clap::ValueEnum;
+use ethers::types::{Address, U256};
+use ethers::utils::format_ether;
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::audit::{AuditEntry, Decision};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Daily,
+    // Weeks start on Monday
+    Weekly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+// Sponsored operations and spend for one period, chain, policy, dapp origin and sender
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportRow {
+    pub period_start: NaiveDate,
+    pub chain_id: u64,
+    pub policy_id: Option<String>,
+    pub origin: Option<String>,
+    pub sender: Address,
+    pub operations: u64,
+    // Sum of the max cost charged for each operation, in wei
+    #[serde(serialize_with = "serialize_decimal")]
+    pub sponsored_wei: U256,
+}
+
+// Period start, chain ID, policy ID, origin and sender
+type GroupKey = (NaiveDate, u64, Option<String>, Option<String>, Address);
+
+// Group sponsored operations recorded in the audit log; `since` and `until` are inclusive UTC dates
+pub fn aggregate(
+    entries: &[AuditEntry],
+    period: Period,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+) -> Vec<ReportRow> {
+    let mut groups: BTreeMap<GroupKey, (u64, U256)> = BTreeMap::new();
+
+    for entry in entries.iter().filter(|entry| entry.decision == Decision::Sponsored) {
+        let Some(date) = DateTime::from_timestamp(entry.timestamp as i64, 0).map(|time| time.date_naive()) else {
+            continue;
+        };
+        if since.is_some_and(|since| date < since) || until.is_some_and(|until| date > until) {
+            continue;
+        }
+
+        let key = (period_start(date, period), entry.chain_id, entry.policy_id.clone(), entry.origin.clone(), entry.user_op.sender);
+        let (operations, sponsored) = groups.entry(key).or_default();
+        *operations += 1;
+        *sponsored = sponsored.saturating_add(entry.max_cost.unwrap_or_default());
+    }
+
+    groups
+        .into_iter()
+        .map(|((period_start, chain_id, policy_id, origin, sender), (operations, sponsored_wei))| ReportRow {
+            period_start,
+            chain_id,
+            policy_id,
+            origin,
+            sender,
+            operations,
+            sponsored_wei,
+        })
+        .collect()
+}
+
+pub fn render(rows: &[ReportRow], format: ReportFormat) -> serde_json::Result<String> {
+    match format {
+        ReportFormat::Json => serde_json::to_string_pretty(rows).map(|json| json + "\n"),
+        ReportFormat::Csv => Ok(to_csv(rows)),
+    }
+}
+
+fn to_csv(rows: &[ReportRow]) -> String {
+    let mut csv = String::from("period_start,chain_id,policy_id,origin,sender,operations,sponsored_wei,sponsored_eth\n");
+
+    for row in rows {
+        csv.push_str(&format!(
+            "{},{},{},{},{:?},{},{},{}\n",
+            row.period_start,
+            row.chain_id,
+            csv_field(row.policy_id.as_deref().unwrap_or_default()),
+            csv_field(row.origin.as_deref().unwrap_or_default()),
+            row.sender,
+            row.operations,
+            row.sponsored_wei,
+            format_ether(row.sponsored_wei),
+        ));
+    }
+
+    csv
+}
+
+// Quote fields that would otherwise break the row
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Unix timestamp of midnight UTC at the start of `date`
+pub fn day_start(date: NaiveDate) -> u64 {
+    date.and_time(NaiveTime::MIN).and_utc().timestamp().max(0) as u64
+}
+
+fn period_start(date: NaiveDate, period: Period) -> NaiveDate {
+    match period {
+        Period::Daily => date,
+        Period::Weekly => date - Days::new(date.weekday().num_days_from_monday() as u64),
+    }
+}
+
+fn serialize_decimal<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
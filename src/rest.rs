@@ -0,0 +1,162 @@
+// src/rest.rs
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
+use serde_json::{json, Map, Value};
+use tower::{Layer, Service};
+
+// REST routes onto the JSON-RPC methods that answer them, so API keys and access lists guard
+// them like the methods themselves
+pub const ROUTES: [(Method, &str, &str); 3] = [
+    (Method::POST, "/v1/sponsor", "pm_sponsorUserOperation"),
+    (Method::POST, "/v1/validate", "pm_validateUserOperation"),
+    (Method::GET, "/v1/quota", "pm_getSponsorshipQuota"),
+];
+
+// Translates the REST facade into JSON-RPC calls to the server behind it, and their responses
+// back into plain JSON with an HTTP status. Every other request is passed through.
+#[derive(Debug, Clone, Copy)]
+pub struct RestLayer {
+    enabled: bool,
+}
+
+impl RestLayer {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl<S> Layer<S> for RestLayer {
+    type Service = Rest<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Rest { inner, enabled: self.enabled }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rest<S> {
+    inner: S,
+    enabled: bool,
+}
+
+impl<S> Service<Request<Body>> for Rest<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let route = ROUTES
+            .iter()
+            .find(|(method, path, _)| request.method() == method && request.uri().path() == *path);
+        let Some((_, _, method)) = route.filter(|_| self.enabled) else {
+            let response = self.inner.call(request);
+            return Box::pin(async move { response.await.map_err(Into::into) });
+        };
+
+        // The clone that was polled ready serves this request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            let params = match *method {
+                "pm_getSponsorshipQuota" => quota_params(parts.uri.query()),
+                _ => operation_params(&hyper::body::to_bytes(body).await?),
+            };
+            let params = match params {
+                Ok(params) => params,
+                Err(message) => return respond(StatusCode::BAD_REQUEST, json!({ "error": { "code": -32602, "message": message } })),
+            };
+
+            let call = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+            parts.method = Method::POST;
+            parts.uri = Uri::from_static("/");
+            parts.headers.insert(CONTENT_TYPE, "application/json".parse()?);
+            parts.headers.remove(CONTENT_LENGTH);
+
+            let response = inner.call(Request::from_parts(parts, Body::from(call.to_string()))).await.map_err(Into::into)?;
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            // Refused before reaching the server, such as for a body over the size limit
+            let Ok(mut response) = serde_json::from_slice::<Value>(&body) else {
+                return Response::builder().status(status).body(Body::from(body)).map_err(Into::into);
+            };
+
+            match response.get_mut("result") {
+                Some(result) => respond(StatusCode::OK, result.take()),
+                None => {
+                    let error = response.get_mut("error").map(Value::take).unwrap_or(Value::Null);
+                    // API key refusals keep their own status
+                    let status = match status {
+                        StatusCode::OK => error_status(&error),
+                        status => status,
+                    };
+                    let mut response = respond(status, json!({ "error": error }))?;
+                    if let Some(retry_after) = error.pointer("/data/retryAfter").and_then(Value::as_u64) {
+                        response.headers_mut().insert(RETRY_AFTER, retry_after.into());
+                    }
+                    Ok(response)
+                }
+            }
+        })
+    }
+}
+
+// `{"userOp": {...}, "context": {...}}` as the positional parameters of the sponsor and validate methods
+fn operation_params(body: &[u8]) -> Result<Value, String> {
+    let Ok(Value::Object(mut body)) = serde_json::from_slice::<Value>(body) else {
+        return Err("Request body must be a JSON object".to_string());
+    };
+
+    let mut params = vec![body.remove("userOp").unwrap_or(Value::Null)];
+    if let Some(context) = body.remove("context") {
+        params.push(context);
+    }
+    Ok(Value::Array(params))
+}
+
+// `?sponsorshipPolicyId=...` or `?apiKey=...` as the sponsorship context of the quota method
+fn quota_params(query: Option<&str>) -> Result<Value, String> {
+    let context: HashMap<String, String> = serde_urlencoded::from_str(query.unwrap_or_default())
+        .map_err(|e| format!("Invalid query string: {}", e))?;
+    Ok(json!([context.into_iter().map(|(key, value)| (key, Value::String(value))).collect::<Map<_, _>>()]))
+}
+
+fn error_status(error: &Value) -> StatusCode {
+    match error.get("code").and_then(Value::as_i64) {
+        // Parse errors, invalid requests and invalid parameters
+        Some(-32700 | -32600 | -32602) => StatusCode::BAD_REQUEST,
+        // Methods hidden by the access lists
+        Some(-32601) => StatusCode::NOT_FOUND,
+        // Provider unavailable, or paused for maintenance
+        Some(-32002 | -32004) => StatusCode::SERVICE_UNAVAILABLE,
+        // Abandoned at the client's deadline
+        Some(-32005) => StatusCode::GATEWAY_TIMEOUT,
+        Some(-32603) | None => StatusCode::INTERNAL_SERVER_ERROR,
+        // The paymaster declined the operation
+        Some(_) => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+fn respond(status: StatusCode, body: Value) -> Result<Response<Body>, Box<dyn Error + Send + Sync>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(Into::into)
+}
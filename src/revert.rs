@@ -0,0 +1,269 @@
+// src/revert.rs
+use std::fmt;
+
+use ethers::abi::{self, ParamType, Token};
+use ethers::types::{Address, Bytes, U256};
+use ethers::utils::id;
+use serde::Serialize;
+
+// Errors the EntryPoint reverts with, plus Solidity's built-in Error(string) and Panic(uint256)
+const ERROR: &str = "Error(string)";
+const PANIC: &str = "Panic(uint256)";
+const FAILED_OP: &str = "FailedOp(uint256,string)";
+const FAILED_OP_WITH_REVERT: &str = "FailedOpWithRevert(uint256,string,bytes)";
+const SIGNATURE_VALIDATION_FAILED: &str = "SignatureValidationFailed(address)";
+const SENDER_ADDRESS_RESULT: &str = "SenderAddressResult(address)";
+// simulateValidation always reverts; these two mean validation passed
+const VALIDATION_RESULT: &str =
+    "ValidationResult((uint256,uint256,bool,uint48,uint48,bytes),(uint256,uint256),(uint256,uint256),(uint256,uint256))";
+const VALIDATION_RESULT_WITH_AGGREGATION: &str =
+    "ValidationResultWithAggregation((uint256,uint256,bool,uint48,uint48,bytes),(uint256,uint256),(uint256,uint256),(uint256,uint256),(address,(uint256,uint256)))";
+
+// Revert data decoded into something an integrator can read and match on
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revert {
+    // Name of the Solidity error, or "unknown"
+    pub error: String,
+    // EntryPoint reason code such as "AA21", or the panic code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    pub reason: String,
+    pub revert_data: Bytes,
+}
+
+impl fmt::Display for Revert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl Revert {
+    // The EntryPoint's AA3x codes come from the paymaster's own validation
+    pub fn is_paymaster_failure(&self) -> bool {
+        self.code.as_deref().is_some_and(|code| code.starts_with("AA3"))
+    }
+}
+
+// The returnInfo of a passing simulateValidation. The EntryPoint only reports the signature
+// failures and windows of the account and paymaster here, leaving them to the caller to enforce.
+// `sig_failed` is the account's signature, or the paymaster's when the account has no aggregator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReturnInfo {
+    pub sig_failed: bool,
+    pub valid_after: u64,
+    // 0 for no expiry
+    pub valid_until: u64,
+}
+
+// Whether `data` is simulateValidation reporting success
+pub fn is_validation_result(data: &[u8]) -> bool {
+    data.len() >= 4 && (data[..4] == id(VALIDATION_RESULT) || data[..4] == id(VALIDATION_RESULT_WITH_AGGREGATION))
+}
+
+// Signature failure and validity window simulateValidation reported for a passing operation
+pub fn return_info(data: &[u8]) -> Option<ReturnInfo> {
+    let fields = validation_result(data)?.into_iter().next()?.into_tuple()?;
+    let [_, _, Token::Bool(sig_failed), Token::Uint(valid_after), Token::Uint(valid_until), _] = fields.as_slice() else {
+        return None;
+    };
+    Some(ReturnInfo {
+        sig_failed: *sig_failed,
+        valid_after: valid_after.low_u64(),
+        valid_until: valid_until.low_u64(),
+    })
+}
+
+// Signature aggregator of the account, when simulateValidation reported success with aggregation
+pub fn aggregator(data: &[u8]) -> Option<Address> {
+    validation_result(data)?.into_iter().nth(4)?.into_tuple()?.into_iter().next()?.into_address()
+}
+
+fn validation_result(data: &[u8]) -> Option<Vec<Token>> {
+    let (selector, args) = data.split_first_chunk::<4>()?;
+    let stake_info = ParamType::Tuple(vec![ParamType::Uint(256), ParamType::Uint(256)]);
+    let return_info = ParamType::Tuple(vec![
+        ParamType::Uint(256),
+        ParamType::Uint(256),
+        ParamType::Bool,
+        ParamType::Uint(48),
+        ParamType::Uint(48),
+        ParamType::Bytes,
+    ]);
+    let mut types = vec![return_info, stake_info.clone(), stake_info.clone(), stake_info.clone()];
+    match *selector {
+        s if s == id(VALIDATION_RESULT) => {}
+        s if s == id(VALIDATION_RESULT_WITH_AGGREGATION) => types.push(ParamType::Tuple(vec![ParamType::Address, stake_info])),
+        _ => return None,
+    }
+    abi::decode(&types, args).ok()
+}
+
+// Address getSenderAddress reported the account in initCode would be deployed at
+pub fn sender_address(data: &[u8]) -> Option<Address> {
+    let (selector, args) = data.split_first_chunk::<4>()?;
+    if *selector != id(SENDER_ADDRESS_RESULT) {
+        return None;
+    }
+    abi::decode(&[ParamType::Address], args).ok()?.into_iter().next()?.into_address()
+}
+
+pub fn decode(data: &Bytes) -> Revert {
+    let revert = |error: &str, code: Option<String>, reason: String| Revert {
+        error: error.to_string(),
+        code,
+        reason,
+        revert_data: data.clone(),
+    };
+
+    if data.is_empty() {
+        return revert("unknown", None, "Reverted without a reason".to_string());
+    }
+    let Some((selector, args)) = data.split_first_chunk::<4>() else {
+        return revert("unknown", None, format!("Malformed revert data {}", data));
+    };
+
+    let decoded = match *selector {
+        s if s == id(ERROR) => abi::decode(&[ParamType::String], args).ok().map(|tokens| {
+            let reason = string(&tokens[0]);
+            revert("Error", None, reason)
+        }),
+        s if s == id(PANIC) => abi::decode(&[ParamType::Uint(256)], args).ok().map(|tokens| {
+            let code = uint(&tokens[0]);
+            revert("Panic", Some(format!("{:#04x}", code.low_u64())), format!("Panic: {}", panic_reason(code)))
+        }),
+        s if s == id(FAILED_OP) => {
+            abi::decode(&[ParamType::Uint(256), ParamType::String], args).ok().map(|tokens| {
+                let reason = string(&tokens[1]);
+                revert("FailedOp", aa_code(&reason), reason)
+            })
+        }
+        s if s == id(FAILED_OP_WITH_REVERT) => {
+            abi::decode(&[ParamType::Uint(256), ParamType::String, ParamType::Bytes], args).ok().map(|tokens| {
+                let reason = string(&tokens[1]);
+                let inner = decode(&Bytes::from(tokens[2].clone().into_bytes().unwrap_or_default()));
+                revert("FailedOpWithRevert", aa_code(&reason), format!("{}: {}", reason, inner.reason))
+            })
+        }
+        s if s == id(SIGNATURE_VALIDATION_FAILED) => abi::decode(&[ParamType::Address], args).ok().map(|tokens| {
+            let aggregator = tokens[0].clone().into_address().unwrap_or_default();
+            revert("SignatureValidationFailed", None, format!("Signature rejected by aggregator {:?}", aggregator))
+        }),
+        s if s == id(SENDER_ADDRESS_RESULT) => abi::decode(&[ParamType::Address], args).ok().map(|tokens| {
+            let sender = tokens[0].clone().into_address().unwrap_or_default();
+            revert("SenderAddressResult", None, format!("Counterfactual sender is {:?}", sender))
+        }),
+        _ => None,
+    };
+
+    decoded.unwrap_or_else(|| revert("unknown", None, format!("Unrecognized revert {}", data)))
+}
+
+// EntryPoint reasons start with a code such as "AA21 didn't pay prefund"
+fn aa_code(reason: &str) -> Option<String> {
+    let code = reason.split_whitespace().next()?;
+    let valid = code.len() == 4 && code.starts_with("AA") && code[2..].chars().all(|c| c.is_ascii_digit());
+    valid.then(|| code.to_string())
+}
+
+fn panic_reason(code: U256) -> &'static str {
+    match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to uninitialized function",
+        _ => "unknown panic code",
+    }
+}
+
+fn string(token: &Token) -> String {
+    token.clone().into_string().unwrap_or_default()
+}
+
+fn uint(token: &Token) -> U256 {
+    token.clone().into_uint().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_selector(selector: &str, args: &[Token]) -> Bytes {
+        let mut data = hex::decode(selector).unwrap();
+        data.extend(abi::encode(args));
+        Bytes::from(data)
+    }
+
+    fn stake_info() -> Token {
+        Token::Tuple(vec![Token::Uint(U256::zero()), Token::Uint(U256::zero())])
+    }
+
+    fn return_info_token(sig_failed: bool, valid_after: u64, valid_until: u64) -> Token {
+        Token::Tuple(vec![
+            Token::Uint(U256::from(60_000)),
+            Token::Uint(U256::exp10(15)),
+            Token::Bool(sig_failed),
+            Token::Uint(valid_after.into()),
+            Token::Uint(valid_until.into()),
+            Token::Bytes(Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn reads_validation_results() {
+        let account_aggregator = Address::repeat_byte(0xa9);
+        let plain = with_selector("e0cff05f", &[return_info_token(false, 1_700_000_000, 1_700_003_600), stake_info(), stake_info(), stake_info()]);
+        let aggregated = with_selector("faecb4e4", &[
+            return_info_token(true, 0, 0),
+            stake_info(),
+            stake_info(),
+            stake_info(),
+            Token::Tuple(vec![Token::Address(account_aggregator), stake_info()]),
+        ]);
+
+        assert!(is_validation_result(&plain));
+        assert!(is_validation_result(&aggregated));
+        assert_eq!(
+            return_info(&plain),
+            Some(ReturnInfo { sig_failed: false, valid_after: 1_700_000_000, valid_until: 1_700_003_600 })
+        );
+        assert_eq!(return_info(&aggregated), Some(ReturnInfo { sig_failed: true, valid_after: 0, valid_until: 0 }));
+        assert_eq!(aggregator(&plain), None);
+        assert_eq!(aggregator(&aggregated), Some(account_aggregator));
+
+        let failed = with_selector("220266b6", &[Token::Uint(U256::zero()), Token::String("AA21 didn't pay prefund".to_string())]);
+        assert!(!is_validation_result(&failed));
+        assert!(!is_validation_result(&plain[..3]));
+        assert_eq!(return_info(&plain[..100]), None);
+    }
+
+    #[test]
+    fn decodes_entry_point_and_solidity_errors() {
+        let failed = decode(&with_selector("220266b6", &[Token::Uint(U256::zero()), Token::String("AA34 signature error".to_string())]));
+        assert_eq!((failed.error.as_str(), failed.code.as_deref()), ("FailedOp", Some("AA34")));
+        assert_eq!(failed.reason, "AA34 signature error");
+        assert!(failed.is_paymaster_failure());
+
+        let inner = with_selector("08c379a0", &[Token::String("not allowed".to_string())]);
+        let nested = decode(&with_selector("65c8fd4d", &[
+            Token::Uint(U256::zero()),
+            Token::String("AA23 reverted".to_string()),
+            Token::Bytes(inner.to_vec()),
+        ]));
+        assert_eq!((nested.error.as_str(), nested.code.as_deref()), ("FailedOpWithRevert", Some("AA23")));
+        assert_eq!(nested.reason, "AA23 reverted: not allowed");
+        assert!(!nested.is_paymaster_failure());
+
+        let panic = decode(&with_selector("4e487b71", &[Token::Uint(U256::from(0x11))]));
+        assert_eq!((panic.code.as_deref(), panic.reason.as_str()), (Some("0x11"), "Panic: arithmetic overflow or underflow"));
+
+        assert_eq!(decode(&Bytes::default()).reason, "Reverted without a reason");
+        assert_eq!(decode(&Bytes::from(vec![0xde, 0xad])).error, "unknown");
+        assert_eq!(decode(&with_selector("deadbeef", &[])).error, "unknown");
+    }
+}
@@ -0,0 +1,109 @@
+// src/rotation.rs
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use ethers::types::{Address, Bytes};
+use serde::Serialize;
+
+use crate::error::PaymasterError;
+use crate::signer::PaymasterSigner;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationTarget {
+    pub paymaster: Address,
+    // Whether the contract already verifies with the next key
+    pub rotated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationStatus {
+    pub current_signer: Address,
+    pub next_signer: Address,
+    // Signatures by the current key stay valid on-chain up to this validUntil after the switch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap_until: Option<u64>,
+    // `rotateVerifyingSigner(next, overlapUntil)` calldata for the owner to send to each paymaster
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction_data: Option<Bytes>,
+    pub paymasters: Vec<RotationTarget>,
+}
+
+// Moves fresh signatures from the configured signers to a standby key. Once a rotation is started,
+// signatures by the current key are capped at the end of the overlap window, which the contract
+// keeps accepting them for after its owner switches it to the next key. The next key is used for
+// a paymaster as soon as its contract is seen verifying with it.
+pub struct KeyRotation {
+    current: Address,
+    next: Arc<dyn PaymasterSigner>,
+    overlap_until: Mutex<Option<u64>>,
+    rotated: Mutex<HashSet<Address>>,
+}
+
+impl KeyRotation {
+    pub fn new(current: Address, next: Arc<dyn PaymasterSigner>) -> Self {
+        Self {
+            current,
+            next,
+            overlap_until: Mutex::new(None),
+            rotated: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn next(&self) -> &Arc<dyn PaymasterSigner> {
+        &self.next
+    }
+
+    // Whether a paymaster signed for by `signer` takes part in the rotation
+    pub fn replaces(&self, signer: Address) -> bool {
+        signer == self.current
+    }
+
+    pub fn start(&self, overlap_until: u64) -> Result<(), PaymasterError> {
+        let mut current = self.overlap_until.lock().unwrap();
+        if let Some(overlap_until) = *current {
+            return Err(PaymasterError::InvalidParameters(format!(
+                "A key rotation with overlap until {} is already in progress", overlap_until
+            )));
+        }
+        *current = Some(overlap_until);
+        Ok(())
+    }
+
+    // Only possible before any contract switched, and only safe if the owner will not send the transactions
+    pub fn cancel(&self) -> Result<(), PaymasterError> {
+        if !self.rotated.lock().unwrap().is_empty() {
+            return Err(PaymasterError::InvalidParameters(
+                "The next key is already in use and the rotation cannot be cancelled".to_string(),
+            ));
+        }
+        *self.overlap_until.lock().unwrap() = None;
+        Ok(())
+    }
+
+    pub fn overlap_until(&self) -> Option<u64> {
+        *self.overlap_until.lock().unwrap()
+    }
+
+    pub fn is_rotated(&self, paymaster: Address) -> bool {
+        self.rotated.lock().unwrap().contains(&paymaster)
+    }
+
+    pub fn mark_rotated(&self, paymaster: Address) {
+        self.rotated.lock().unwrap().insert(paymaster);
+    }
+
+    pub fn status(&self, paymasters: &[Address], transaction_data: Option<Bytes>) -> RotationStatus {
+        RotationStatus {
+            current_signer: self.current,
+            next_signer: self.next.address(),
+            overlap_until: self.overlap_until(),
+            transaction_data,
+            paymasters: paymasters
+                .iter()
+                .map(|&paymaster| RotationTarget { paymaster, rotated: self.is_rotated(paymaster) })
+                .collect(),
+        }
+    }
+}
@@ -1,61 +1,544 @@
 // src/rpc.rs
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
+use chrono::NaiveDate;
 use jsonrpsee::core::{async_trait, RpcResult};
 use jsonrpsee::proc_macros::rpc;
+use jsonrpsee::types::error::{ErrorObjectOwned, INVALID_PARAMS_CODE};
+use ethers::types::{Address, H256};
 use jsonrpsee::RpcModule;
-use serde_json::json;
-use tracing::{debug, error, info};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tracing::{debug, error, field, info, info_span, Instrument};
 
+use crate::approval::Ticket;
+use crate::audit::AuditEntry;
+use crate::deadline::{self, DEADLINE_EXCEEDED_CODE};
 use crate::error::PaymasterError;
+use crate::maintenance::MaintenanceStatus;
+use crate::metrics::{Metrics, MetricsSnapshot};
 use crate::paymaster::Paymaster;
-use crate::types::{PaymasterResponse, UserOperation, ValidationResult};
+use crate::project::Project;
+use crate::report::{Period, ReportRow};
+use crate::rotation::RotationStatus;
+use crate::session::{SessionGrant, SessionGrantRequest};
+use crate::store::{OperationStatus, TokenDebt};
+use crate::types::{Capabilities, DashboardSummary, HealthAttestation, HealthResponse, PaymasterResponse, SponsorshipCheck, SponsorshipContext, SponsorshipQuota, UserOperation};
 
 // Define the RPC interface
 #[rpc(server, namespace = "pm")]
 pub trait PaymasterRpc {
     /// Requests the paymaster to sponsor a user operation
     #[method(name = "sponsorUserOperation")]
-    async fn sponsor(&self, user_op: UserOperation) -> RpcResult<PaymasterResponse>;
+    async fn sponsor(&self, user_op: UserOperation, sponsorship_context: Option<SponsorshipContext>) -> RpcResult<PaymasterResponse>;
+    
+    /// Checks whether a user operation would be sponsored, without signing it
+    #[method(name = "validateUserOperation")]
+    async fn validate(&self, user_op: UserOperation, sponsorship_context: Option<SponsorshipContext>) -> RpcResult<SponsorshipCheck>;
+    
+    /// Returns what the caller's sponsorship policy can still sponsor today
+    #[method(name = "getSponsorshipQuota")]
+    async fn sponsorship_quota(&self, sponsorship_context: SponsorshipContext) -> RpcResult<SponsorshipQuota>;
+    
+    /// Returns the state of a sponsorship held for operator approval
+    #[method(name = "getSponsorshipTicket")]
+    fn sponsorship_ticket(&self, ticket_id: String) -> RpcResult<Ticket>;
+    
+    /// Reports whether a sponsored operation is pending, included or failed, by the userOpHash
+    /// it was submitted under
+    #[method(name = "getSponsoredOperationStatus")]
+    async fn sponsored_operation_status(&self, user_op_hash: H256) -> RpcResult<OperationStatus>;
+    
+    /// Opens a short-lived session whose operations are sponsored on presenting its token
+    #[method(name = "createSessionGrant")]
+    fn create_session_grant(&self, request: SessionGrantRequest, sponsorship_context: SponsorshipContext) -> RpcResult<SessionGrant>;
+    
+    /// Ends a session before it expires
+    #[method(name = "revokeSessionGrant")]
+    fn revoke_session_grant(&self, session_token: String) -> RpcResult<bool>;
+    
+    /// Returns the EntryPoint addresses the paymaster works with
+    #[method(name = "supportedEntryPoints")]
+    fn supported_entry_points(&self) -> RpcResult<Vec<Address>>;
+    
+    /// Describes the chains, EntryPoints, paymaster modes and tokens this deployment supports
+    #[method(name = "getCapabilities")]
+    fn capabilities(&self) -> RpcResult<Capabilities>;
+}
+
+// Operator interface; must not be reachable by untrusted clients
+#[rpc(server, namespace = "admin")]
+pub trait AdminRpc {
+    /// Lists sponsorships waiting for approval
+    #[method(name = "listPendingSponsorships")]
+    fn pending_sponsorships(&self) -> RpcResult<Vec<Ticket>>;
+    
+    /// Approves and signs a pending sponsorship
+    #[method(name = "approveSponsorship")]
+    async fn approve_sponsorship(&self, ticket_id: String) -> RpcResult<Ticket>;
+    
+    /// Rejects a pending sponsorship
+    #[method(name = "rejectSponsorship")]
+    async fn reject_sponsorship(&self, ticket_id: String, reason: Option<String>) -> RpcResult<Ticket>;
+    
+    /// Aggregates sponsored spend per period, chain, policy and sender
+    #[method(name = "getSpendReport")]
+    async fn spend_report(
+        &self,
+        period: Period,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> RpcResult<Vec<ReportRow>>;
+    
+    /// Summarizes deposits, signer health, budget consumption and recent rejections
+    #[method(name = "getDashboard")]
+    async fn dashboard(&self) -> RpcResult<DashboardSummary>;
+    
+    /// Lists the latest sponsorship decisions, newest first
+    #[method(name = "getRecentSponsorships")]
+    fn recent_sponsorships(&self) -> RpcResult<Vec<AuditEntry>>;
+    
+    /// Starts moving fresh signatures to the standby key, returning the owner's contract call
+    #[method(name = "startKeyRotation")]
+    async fn start_key_rotation(&self, overlap_secs: Option<u64>) -> RpcResult<RotationStatus>;
+    
+    /// Reports which paymasters already verify with the standby key
+    #[method(name = "getKeyRotation")]
+    fn key_rotation(&self) -> RpcResult<RotationStatus>;
+    
+    /// Abandons a rotation whose contract call will not be sent
+    #[method(name = "cancelKeyRotation")]
+    fn cancel_key_rotation(&self) -> RpcResult<RotationStatus>;
+    
+    /// Serves only allowlisted API keys and senders while enabled, telling everyone else when to come back
+    #[method(name = "setMaintenance")]
+    fn set_maintenance(&self, enabled: bool, eta: Option<u64>) -> RpcResult<MaintenanceStatus>;
+    
+    /// Reports whether maintenance mode is on
+    #[method(name = "getMaintenance")]
+    fn maintenance(&self) -> RpcResult<MaintenanceStatus>;
+    
+    /// Lists what operations paid for in tokens owe and what was collected for them
+    #[method(name = "getTokenDebts")]
+    async fn token_debts(&self, since: Option<u64>, outstanding: Option<bool>) -> RpcResult<Vec<TokenDebt>>;
+    
+    /// Adds a project and starts serving its policies
+    #[method(name = "createProject")]
+    async fn create_project(&self, project: Project) -> RpcResult<Project>;
+    
+    /// Replaces a project's keys, chains, policies and webhooks
+    #[method(name = "updateProject")]
+    async fn update_project(&self, project: Project) -> RpcResult<Project>;
+    
+    /// Removes a project and stops serving its policies
+    #[method(name = "deleteProject")]
+    async fn delete_project(&self, project_id: String) -> RpcResult<Project>;
+    
+    #[method(name = "getProject")]
+    fn project(&self, project_id: String) -> RpcResult<Project>;
+    
+    /// Lists every project, ordered by ID
+    #[method(name = "listProjects")]
+    fn projects(&self) -> RpcResult<Vec<Project>>;
+}
+
+// Operational endpoints, also served as plain GET requests on /health, /attestation and /metrics
+#[rpc(server, namespace = "system")]
+pub trait SystemRpc {
+    /// Reports signer availability
+    #[method(name = "health")]
+    fn health(&self) -> RpcResult<HealthResponse>;
+    
+    /// Signs the latest block, deposit and version with the paymaster's key as proof of liveness
+    #[method(name = "attestation")]
+    async fn attestation(&self, challenge: Option<H256>) -> RpcResult<HealthAttestation>;
+    
+    /// Returns the current metric values
+    #[method(name = "metrics")]
+    fn metrics(&self) -> RpcResult<MetricsSnapshot>;
 }
 
 pub struct PaymasterRpcImpl {
     paymaster: Arc<Paymaster>,
+    metrics: Arc<Metrics>,
+    // Cap on the deadline a sponsorship context may set
+    max_timeout: Option<Duration>,
 }
 
 impl PaymasterRpcImpl {
-    pub fn new(paymaster: Arc<Paymaster>) -> Self {
-        Self { paymaster }
+    pub fn new(paymaster: Arc<Paymaster>, metrics: Arc<Metrics>) -> Self {
+        Self { paymaster, metrics, max_timeout: None }
+    }
+    
+    pub fn with_max_timeout(mut self, max_timeout: Option<Duration>) -> Self {
+        self.max_timeout = max_timeout;
+        self
+    }
+    
+    // Run a call within the deadline its context asks for. Dropping the call at the deadline
+    // cancels the provider calls, simulation and signing it is waiting on.
+    async fn within_deadline<T>(
+        &self,
+        timeout_ms: Option<u64>,
+        call: impl Future<Output = Result<T, PaymasterError>>,
+    ) -> Result<T, PaymasterError> {
+        let Some(timeout) = deadline::timeout(timeout_ms, self.max_timeout) else {
+            return call.await;
+        };
+        match tokio::time::timeout(timeout, call).await {
+            Ok(result) => result,
+            Err(_) => {
+                Metrics::increment(&self.metrics.request_timeouts);
+                Err(PaymasterError::DeadlineExceeded(timeout.as_millis() as u64))
+            }
+        }
     }
 }
 
 #[async_trait]
 impl PaymasterRpcServer for PaymasterRpcImpl {
-    async fn sponsor(&self, user_op: UserOperation) -> RpcResult<PaymasterResponse> {
-        debug!("Received sponsor request for sender: {}", user_op.sender);
+    async fn sponsor(&self, user_op: UserOperation, sponsorship_context: Option<SponsorshipContext>) -> RpcResult<PaymasterResponse> {
+        // The paymaster fills in the operation's fields once it has computed its hash
+        let span = info_span!(
+            "sponsor",
+            chain_id = self.paymaster.chain_id(),
+            sender = field::Empty,
+            user_op_hash = field::Empty,
+            policy_id = field::Empty,
+        );
         
-        match self.paymaster.sign_user_operation(&user_op).await {
-            Ok(response) => {
-                info!("Successfully sponsored operation for {}", user_op.sender);
-                Ok(response)
-            }
-            Err(e) => {
-                error!("Failed to sponsor operation: {}", e);
-                Err(jsonrpsee::types::error::ErrorObject::owned(
-                    -32000,
-                    format!("Paymaster error: {}", e),
-                    None::<()>,
-                ))
+        async move {
+            debug!("Received sponsor request for sender: {}", user_op.sender);
+            
+            let sponsorship_context = sponsorship_context.unwrap_or_default();
+            let sponsored = self.paymaster.sign_user_operation(&user_op, &sponsorship_context);
+            match self.within_deadline(sponsorship_context.timeout_ms, sponsored).await {
+                Ok(response) => {
+                    info!("Successfully sponsored operation for {}", user_op.sender);
+                    Ok(response)
+                }
+                Err(e @ PaymasterError::ApprovalRequired(_)) => {
+                    info!("Operation for {} is awaiting approval", user_op.sender);
+                    Err(to_rpc_error(e))
+                }
+                Err(e) => {
+                    error!("Failed to sponsor operation: {}", e);
+                    Err(to_rpc_error(e))
+                }
             }
         }
+        .instrument(span)
+        .await
+    }
+    
+    async fn validate(&self, user_op: UserOperation, sponsorship_context: Option<SponsorshipContext>) -> RpcResult<SponsorshipCheck> {
+        let sponsorship_context = sponsorship_context.unwrap_or_default();
+        let checked = self.paymaster.check_user_operation(&user_op, &sponsorship_context);
+        self.within_deadline(sponsorship_context.timeout_ms, checked).await.map_err(to_rpc_error)
+    }
+    
+    async fn sponsorship_quota(&self, sponsorship_context: SponsorshipContext) -> RpcResult<SponsorshipQuota> {
+        let quota = self.paymaster.sponsorship_quota(&sponsorship_context);
+        self.within_deadline(sponsorship_context.timeout_ms, quota).await.map_err(to_rpc_error)
+    }
+    
+    fn sponsorship_ticket(&self, ticket_id: String) -> RpcResult<Ticket> {
+        self.paymaster.sponsorship_ticket(&ticket_id).map_err(to_rpc_error)
+    }
+    
+    async fn sponsored_operation_status(&self, user_op_hash: H256) -> RpcResult<OperationStatus> {
+        self.paymaster.sponsored_operation_status(user_op_hash).await.map_err(to_rpc_error)
+    }
+    
+    fn create_session_grant(&self, request: SessionGrantRequest, sponsorship_context: SponsorshipContext) -> RpcResult<SessionGrant> {
+        self.paymaster.create_session_grant(request, &sponsorship_context).map_err(to_rpc_error)
+    }
+    
+    fn revoke_session_grant(&self, session_token: String) -> RpcResult<bool> {
+        Ok(self.paymaster.revoke_session_grant(&session_token))
+    }
+    
+    fn supported_entry_points(&self) -> RpcResult<Vec<Address>> {
+        Ok(self.paymaster.capabilities().entry_points.into_iter().map(|entry_point| entry_point.address).collect())
+    }
+    
+    fn capabilities(&self) -> RpcResult<Capabilities> {
+        Ok(self.paymaster.capabilities())
     }
 }
 
+#[async_trait]
+impl AdminRpcServer for PaymasterRpcImpl {
+    fn pending_sponsorships(&self) -> RpcResult<Vec<Ticket>> {
+        self.paymaster.pending_approvals().map_err(to_rpc_error)
+    }
+    
+    async fn approve_sponsorship(&self, ticket_id: String) -> RpcResult<Ticket> {
+        let span = ticket_span("approve", self.paymaster.chain_id(), &ticket_id);
+        self.paymaster.approve_ticket(&ticket_id).instrument(span).await.map_err(to_rpc_error)
+    }
+    
+    async fn reject_sponsorship(&self, ticket_id: String, reason: Option<String>) -> RpcResult<Ticket> {
+        let span = ticket_span("reject", self.paymaster.chain_id(), &ticket_id);
+        self.paymaster.reject_ticket(&ticket_id, reason).instrument(span).await.map_err(to_rpc_error)
+    }
+    
+    async fn spend_report(
+        &self,
+        period: Period,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+    ) -> RpcResult<Vec<ReportRow>> {
+        self.paymaster.spend_report(period, since, until).await.map_err(to_rpc_error)
+    }
+    
+    async fn dashboard(&self) -> RpcResult<DashboardSummary> {
+        self.paymaster.dashboard().await.map_err(to_rpc_error)
+    }
+    
+    fn recent_sponsorships(&self) -> RpcResult<Vec<AuditEntry>> {
+        Ok(self.paymaster.recent_decisions())
+    }
+    
+    async fn start_key_rotation(&self, overlap_secs: Option<u64>) -> RpcResult<RotationStatus> {
+        self.paymaster.start_key_rotation(overlap_secs).await.map_err(to_rpc_error)
+    }
+    
+    fn key_rotation(&self) -> RpcResult<RotationStatus> {
+        self.paymaster.key_rotation_status().map_err(to_rpc_error)
+    }
+    
+    fn cancel_key_rotation(&self) -> RpcResult<RotationStatus> {
+        self.paymaster.cancel_key_rotation().map_err(to_rpc_error)
+    }
+    
+    fn set_maintenance(&self, enabled: bool, eta: Option<u64>) -> RpcResult<MaintenanceStatus> {
+        Ok(self.paymaster.set_maintenance(enabled, eta))
+    }
+    
+    fn maintenance(&self) -> RpcResult<MaintenanceStatus> {
+        Ok(self.paymaster.maintenance_status())
+    }
+    
+    async fn token_debts(&self, since: Option<u64>, outstanding: Option<bool>) -> RpcResult<Vec<TokenDebt>> {
+        self.paymaster.token_debts(since.unwrap_or_default(), outstanding.unwrap_or_default()).await.map_err(to_rpc_error)
+    }
+    
+    async fn create_project(&self, project: Project) -> RpcResult<Project> {
+        self.paymaster.save_project(project, false).await.map_err(to_rpc_error)
+    }
+    
+    async fn update_project(&self, project: Project) -> RpcResult<Project> {
+        self.paymaster.save_project(project, true).await.map_err(to_rpc_error)
+    }
+    
+    async fn delete_project(&self, project_id: String) -> RpcResult<Project> {
+        self.paymaster.delete_project(&project_id).await.map_err(to_rpc_error)
+    }
+    
+    fn project(&self, project_id: String) -> RpcResult<Project> {
+        self.paymaster.project(&project_id).map_err(to_rpc_error)
+    }
+    
+    fn projects(&self) -> RpcResult<Vec<Project>> {
+        self.paymaster.projects().map_err(to_rpc_error)
+    }
+}
+
+#[async_trait]
+impl SystemRpcServer for PaymasterRpcImpl {
+    fn health(&self) -> RpcResult<HealthResponse> {
+        Ok(self.paymaster.health())
+    }
+    
+    async fn attestation(&self, challenge: Option<H256>) -> RpcResult<HealthAttestation> {
+        self.paymaster.attest(challenge).await.map_err(to_rpc_error)
+    }
+    
+    fn metrics(&self) -> RpcResult<MetricsSnapshot> {
+        Ok(self.metrics.snapshot())
+    }
+}
+
+// Span for an operator decision on a ticket; the operation's fields are recorded once the ticket is loaded
+fn ticket_span(action: &str, chain_id: u64, ticket_id: &str) -> tracing::Span {
+    info_span!(
+        "ticket",
+        action,
+        chain_id,
+        ticket_id,
+        sender = field::Empty,
+        user_op_hash = field::Empty,
+        policy_id = field::Empty,
+    )
+}
+
+// Map paymaster errors to JSON-RPC errors, attaching machine-readable data where callers need it
+fn to_rpc_error(e: PaymasterError) -> ErrorObjectOwned {
+    match &e {
+        PaymasterError::ApprovalRequired(ticket_id) => ErrorObjectOwned::owned(
+            -32001,
+            format!("Paymaster error: {}", e),
+            Some(json!({ "ticketId": ticket_id })),
+        ),
+        // ERC-4337 bundler codes: -32501 when the paymaster's validation failed, -32500 otherwise
+        PaymasterError::SimulationFailed(revert) => ErrorObjectOwned::owned(
+            if revert.is_paymaster_failure() { -32501 } else { -32500 },
+            format!("Paymaster error: {}", e),
+            Some(revert),
+        ),
+        PaymasterError::ProviderUnavailable(retry_after) => ErrorObjectOwned::owned(
+            -32002,
+            format!("Paymaster error: {}", e),
+            Some(json!({ "retryAfter": retry_after })),
+        ),
+        PaymasterError::Paused(eta) => ErrorObjectOwned::owned(
+            -32004,
+            format!("Paymaster error: {}", e),
+            Some(json!({ "eta": eta })),
+        ),
+        PaymasterError::MalformedField(field, _) => ErrorObjectOwned::owned(
+            INVALID_PARAMS_CODE,
+            format!("Paymaster error: {}", e),
+            Some(json!({ "field": format!("userOp.{}", field) })),
+        ),
+        PaymasterError::DeadlineExceeded(_) => ErrorObjectOwned::owned(DEADLINE_EXCEEDED_CODE, format!("Paymaster error: {}", e), None::<()>),
+        _ => ErrorObjectOwned::owned(-32000, format!("Paymaster error: {}", e), None::<()>),
+    }
+}
+
+// Decode a parameter, naming the field that failed and why instead of jsonrpsee's generic parse error
+fn decode_param<T: DeserializeOwned>(name: &str, value: Value) -> Result<T, ErrorObjectOwned> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        let field = match e.path().to_string().as_str() {
+            "." => name.to_string(),
+            path => format!("{}.{}", name, path),
+        };
+        ErrorObjectOwned::owned(
+            INVALID_PARAMS_CODE,
+            format!("Invalid {}: {}", field, e.inner()),
+            Some(json!({ "field": field })),
+        )
+    })
+}
+
 pub fn register_methods(module: &mut RpcModule<PaymasterRpcImpl>) -> anyhow::Result<()> {
     module.register_async_method("pm_sponsorUserOperation", |params, context| async move {
-        let user_op = params.parse::<UserOperation>()?;
-        context.sponsor(user_op).await
+        let mut params = params.sequence();
+        let user_op = decode_param("userOp", params.next::<Value>()?)?;
+        let sponsorship_context = params.optional_next::<Value>()?
+            .map(|value| decode_param("context", value))
+            .transpose()?;
+        context.sponsor(user_op, sponsorship_context).await
+    })?;
+    
+    module.register_async_method("pm_validateUserOperation", |params, context| async move {
+        let mut params = params.sequence();
+        let user_op = decode_param("userOp", params.next::<Value>()?)?;
+        let sponsorship_context = params.optional_next::<Value>()?
+            .map(|value| decode_param("context", value))
+            .transpose()?;
+        context.validate(user_op, sponsorship_context).await
+    })?;
+    
+    module.register_async_method("pm_getSponsorshipQuota", |params, context| async move {
+        let sponsorship_context = params.sequence().optional_next::<Value>()?
+            .map(|value| decode_param("context", value))
+            .transpose()?;
+        context.sponsorship_quota(sponsorship_context.unwrap_or_default()).await
+    })?;
+    
+    module.register_method("pm_getSponsorshipTicket", |params, context| {
+        let ticket_id = params.one::<String>()?;
+        context.sponsorship_ticket(ticket_id)
+    })?;
+    
+    module.register_async_method("pm_getSponsoredOperationStatus", |params, context| async move {
+        let user_op_hash = params.one::<H256>()?;
+        context.sponsored_operation_status(user_op_hash).await
+    })?;
+    
+    module.register_method("pm_createSessionGrant", |params, context| {
+        let mut params = params.sequence();
+        let request = decode_param("request", params.next::<Value>()?)?;
+        let sponsorship_context = params.optional_next::<Value>()?
+            .map(|value| decode_param("context", value))
+            .transpose()?;
+        context.create_session_grant(request, sponsorship_context.unwrap_or_default())
+    })?;
+    module.register_method("pm_revokeSessionGrant", |params, context| {
+        let session_token = params.one::<String>()?;
+        context.revoke_session_grant(session_token)
+    })?;
+    
+    module.register_method("pm_supportedEntryPoints", |_, context| context.supported_entry_points())?;
+    module.register_method("pm_getCapabilities", |_, context| PaymasterRpcServer::capabilities(context))?;
+    
+    module.register_method("admin_listPendingSponsorships", |_, context| context.pending_sponsorships())?;
+    module.register_async_method("admin_approveSponsorship", |params, context| async move {
+        let ticket_id = params.one::<String>()?;
+        context.approve_sponsorship(ticket_id).await
+    })?;
+    module.register_async_method("admin_rejectSponsorship", |params, context| async move {
+        let mut params = params.sequence();
+        let ticket_id = params.next::<String>()?;
+        let reason = params.optional_next::<String>()?;
+        context.reject_sponsorship(ticket_id, reason).await
+    })?;
+    module.register_async_method("admin_getSpendReport", |params, context| async move {
+        let mut params = params.sequence();
+        let period = params.optional_next::<Period>()?.unwrap_or(Period::Daily);
+        let since = params.optional_next::<NaiveDate>()?;
+        let until = params.optional_next::<NaiveDate>()?;
+        context.spend_report(period, since, until).await
+    })?;
+    module.register_async_method("admin_getDashboard", |_, context| async move { context.dashboard().await })?;
+    module.register_method("admin_getRecentSponsorships", |_, context| context.recent_sponsorships())?;
+    module.register_async_method("admin_startKeyRotation", |params, context| async move {
+        let overlap_secs = params.sequence().optional_next::<u64>()?;
+        context.start_key_rotation(overlap_secs).await
+    })?;
+    module.register_method("admin_getKeyRotation", |_, context| context.key_rotation())?;
+    module.register_method("admin_cancelKeyRotation", |_, context| context.cancel_key_rotation())?;
+    module.register_method("admin_setMaintenance", |params, context| {
+        let mut params = params.sequence();
+        let enabled = params.next::<bool>()?;
+        let eta = params.optional_next::<u64>()?;
+        context.set_maintenance(enabled, eta)
+    })?;
+    module.register_method("admin_getMaintenance", |_, context| context.maintenance())?;
+    module.register_async_method("admin_getTokenDebts", |params, context| async move {
+        let mut params = params.sequence();
+        let since = params.optional_next::<u64>()?;
+        let outstanding = params.optional_next::<bool>()?;
+        context.token_debts(since, outstanding).await
+    })?;
+    module.register_async_method("admin_createProject", |params, context| async move {
+        let project = decode_param("project", params.one::<Value>()?)?;
+        context.create_project(project).await
+    })?;
+    module.register_async_method("admin_updateProject", |params, context| async move {
+        let project = decode_param("project", params.one::<Value>()?)?;
+        context.update_project(project).await
+    })?;
+    module.register_async_method("admin_deleteProject", |params, context| async move {
+        let project_id = params.one::<String>()?;
+        context.delete_project(project_id).await
+    })?;
+    module.register_method("admin_getProject", |params, context| {
+        let project_id = params.one::<String>()?;
+        context.project(project_id)
+    })?;
+    module.register_method("admin_listProjects", |_, context| context.projects())?;
+    
+    module.register_method("system_health", |_, context| context.health())?;
+    module.register_async_method("system_attestation", |params, context| async move {
+        let challenge = params.sequence().optional_next::<H256>()?;
+        context.attestation(challenge).await
     })?;
+    module.register_method("system_metrics", |_, context| SystemRpcServer::metrics(context))?;
     
     Ok(())
 }
\ No newline at end of file
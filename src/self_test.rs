@@ -0,0 +1,136 @@
+// src/self_test.rs
+use ethers::abi::{self, ParamType, Token};
+use ethers::types::{Address, Bytes, H256, U256};
+use ethers::utils::id;
+
+use crate::contracts::EntryPointVersion;
+use crate::error::PaymasterError;
+use crate::types::{PaymasterAndData, PaymasterGasLimits, UserOperation};
+use crate::user_op_hash::pack_uints;
+
+const VALIDATE_PAYMASTER_USER_OP_V06: &str =
+    "validatePaymasterUserOp((address,uint256,bytes,bytes,uint256,uint256,uint256,uint256,uint256,bytes,bytes),bytes32,uint256)";
+const VALIDATE_PAYMASTER_USER_OP_V07: &str =
+    "validatePaymasterUserOp((address,uint256,bytes,bytes,bytes32,uint256,bytes32,bytes,bytes),bytes32,uint256)";
+
+// Sender of the synthetic operation; no account is deployed there, and the paymaster's own
+// validation does not look at it
+const SYNTHETIC_SENDER: &str = "0x5e1f7e5700000000000000000000000000000001";
+
+// An operation that exercises every field the paymaster data is hashed over
+pub fn synthetic_user_op() -> UserOperation {
+    UserOperation {
+        sender: SYNTHETIC_SENDER.parse().unwrap(),
+        nonce: U256::zero(),
+        init_code: Bytes::default(),
+        call_data: Bytes::default(),
+        call_gas_limit: U256::from(100_000),
+        verification_gas_limit: U256::from(100_000),
+        pre_verification_gas: U256::from(50_000),
+        max_fee_per_gas: U256::from(1_000_000_000u64),
+        max_priority_fee_per_gas: U256::from(1_000_000_000u64),
+        paymaster_and_data: Bytes::default(),
+        signature: Bytes::default(),
+    }
+}
+
+// Read back verifying-mode paymasterAndData as the contract does: paymaster address,
+// [verification and postOp gas limits], validUntil and validAfter as 32-byte words, then the
+// signature
+pub fn decode_paymaster_data(data: &[u8], with_gas_limits: bool) -> Result<PaymasterAndData, PaymasterError> {
+    let malformed = || PaymasterError::SelfTestFailed(format!("paymasterAndData of {} bytes is too short", data.len()));
+    let (paymaster, rest) = split(data, 20).ok_or_else(malformed)?;
+    let (gas_limits, rest) = match with_gas_limits {
+        true => {
+            let (packed, rest) = split(rest, 32).ok_or_else(malformed)?;
+            let gas_limits = PaymasterGasLimits {
+                verification_gas_limit: u128::from_be_bytes(packed[..16].try_into().unwrap()) as u64,
+                post_op_gas_limit: u128::from_be_bytes(packed[16..].try_into().unwrap()) as u64,
+            };
+            (Some(gas_limits), rest)
+        }
+        false => (None, rest),
+    };
+    let (valid_until, rest) = split(rest, 32).ok_or_else(malformed)?;
+    let (valid_after, signature) = split(rest, 32).ok_or_else(malformed)?;
+
+    Ok(PaymasterAndData {
+        paymaster: Address::from_slice(paymaster),
+        gas_limits,
+        valid_until: timestamp(valid_until)?,
+        valid_after: timestamp(valid_after)?,
+        token_quote: None,
+        signature: Bytes::from(signature.to_vec()),
+    })
+}
+
+// Calldata of validatePaymasterUserOp(userOp, userOpHash, maxCost) in the EntryPoint version's
+// struct layout
+pub fn validate_paymaster_call(version: EntryPointVersion, user_op: &UserOperation, user_op_hash: H256) -> Bytes {
+    let bytes = |bytes: &Bytes| Token::Bytes(bytes.to_vec());
+    let (signature, user_op) = match version {
+        EntryPointVersion::V06 => (VALIDATE_PAYMASTER_USER_OP_V06, Token::Tuple(vec![
+            Token::Address(user_op.sender),
+            Token::Uint(user_op.nonce),
+            bytes(&user_op.init_code),
+            bytes(&user_op.call_data),
+            Token::Uint(user_op.call_gas_limit),
+            Token::Uint(user_op.verification_gas_limit),
+            Token::Uint(user_op.pre_verification_gas),
+            Token::Uint(user_op.max_fee_per_gas),
+            Token::Uint(user_op.max_priority_fee_per_gas),
+            bytes(&user_op.paymaster_and_data),
+            bytes(&user_op.signature),
+        ])),
+        EntryPointVersion::V07 | EntryPointVersion::V08 => (VALIDATE_PAYMASTER_USER_OP_V07, Token::Tuple(vec![
+            Token::Address(user_op.sender),
+            Token::Uint(user_op.nonce),
+            bytes(&user_op.init_code),
+            bytes(&user_op.call_data),
+            Token::FixedBytes(pack_uints(user_op.verification_gas_limit, user_op.call_gas_limit).to_vec()),
+            Token::Uint(user_op.pre_verification_gas),
+            Token::FixedBytes(pack_uints(user_op.max_priority_fee_per_gas, user_op.max_fee_per_gas).to_vec()),
+            bytes(&user_op.paymaster_and_data),
+            bytes(&user_op.signature),
+        ])),
+    };
+
+    let mut data = id(signature).to_vec();
+    data.extend(abi::encode(&[user_op, Token::FixedBytes(user_op_hash.as_bytes().to_vec()), Token::Uint(U256::zero())]));
+    Bytes::from(data)
+}
+
+// The validationData returned by validatePaymasterUserOp must report a valid signature and the
+// window that was signed: sigFailed in the low 160 bits, then validUntil and validAfter (48 bits each)
+pub fn check_validation_data(output: &[u8], valid_until: u64, valid_after: u64) -> Result<(), PaymasterError> {
+    let tokens = abi::decode(&[ParamType::Bytes, ParamType::Uint(256)], output)
+        .map_err(|e| PaymasterError::SelfTestFailed(format!("Undecodable validatePaymasterUserOp result: {}", e)))?;
+    let Some(validation_data) = tokens.get(1).cloned().and_then(Token::into_uint) else {
+        return Err(PaymasterError::SelfTestFailed("validatePaymasterUserOp returned no validationData".to_string()));
+    };
+
+    let window = |shift: usize| ((validation_data >> shift) & U256::from(0xffff_ffff_ffffu64)).low_u64();
+    if !(validation_data & ((U256::one() << 160) - 1)).is_zero() {
+        return Err(PaymasterError::SelfTestFailed("The paymaster contract rejected the signature".to_string()));
+    }
+    if (window(160), window(208)) != (valid_until, valid_after) {
+        return Err(PaymasterError::SelfTestFailed(format!(
+            "The paymaster contract read validity window {}-{}, expected {}-{}",
+            window(208), window(160), valid_after, valid_until
+        )));
+    }
+    Ok(())
+}
+
+fn split(data: &[u8], at: usize) -> Option<(&[u8], &[u8])> {
+    (data.len() >= at).then(|| data.split_at(at))
+}
+
+// A timestamp the contract reads as a 32-byte word, which must fit the uint48 it is cast to
+fn timestamp(word: &[u8]) -> Result<u64, PaymasterError> {
+    let value = U256::from_big_endian(word);
+    match value <= U256::from(0xffff_ffff_ffffu64) {
+        true => Ok(value.low_u64()),
+        false => Err(PaymasterError::SelfTestFailed(format!("Timestamp {} does not fit a uint48", value))),
+    }
+}
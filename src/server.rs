@@ -0,0 +1,524 @@
+// src/server.rs
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::Http;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use jsonrpsee::server::middleware::ProxyGetRequestLayer;
+use jsonrpsee::server::{ServerBuilder, ServerHandle};
+use jsonrpsee::types::error::{reject_too_big_request, ErrorCode, ErrorObjectOwned};
+use jsonrpsee::Methods;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tower::util::BoxCloneService;
+use tower::{Service, ServiceExt};
+use tracing::{debug, error, info, warn};
+
+use crate::auth::ApiKeyLayer;
+use crate::config::{AccessConfig, ServerConfig};
+use crate::dashboard::{self, DashboardLayer};
+use crate::deadline::DeadlineLayer;
+use crate::metrics::Metrics;
+use crate::rest::RestLayer;
+
+// The HTTP middleware in front of the RPC methods, for the jsonrpsee server and the listeners
+// served in-process alike
+macro_rules! middleware {
+    ($config:expr, $access:expr, $metrics:expr) => {{
+        let [summary_route, sponsorships_route] = dashboard::API_ROUTES;
+        let mut routes = vec![
+            (Method::GET, "/health", "system_health"),
+            (Method::GET, "/attestation", "system_attestation"),
+            (Method::GET, "/metrics", "system_metrics"),
+        ];
+        // The dashboard's endpoints are only routed while it is served
+        if $config.dashboard {
+            routes.extend([summary_route, sponsorships_route].map(|(path, method)| (Method::GET, path, method)));
+        }
+        let dashboard_route = |(path, method): (&'static str, &'static str)| {
+            $config.dashboard.then(|| ProxyGetRequestLayer::new(path, method)).transpose()
+        };
+
+        tower::ServiceBuilder::new()
+            // REST routes reach the API key checks as the JSON-RPC calls they are translated into,
+            // and the other HTTP routes are checked as the methods they call
+            .layer(RestLayer::new($config.rest_api))
+            .layer(ApiKeyLayer::new(&$access.api_keys, routes, $config.max_request_body_size))
+            .layer(DeadlineLayer::new($config.max_request_timeout_ms.map(Duration::from_millis), $metrics.clone()))
+            .layer(ProxyGetRequestLayer::new("/health", "system_health")?)
+            .layer(ProxyGetRequestLayer::new("/attestation", "system_attestation")?)
+            .layer(ProxyGetRequestLayer::new("/metrics", "system_metrics")?)
+            .layer(DashboardLayer::new($config.dashboard))
+            .option_layer(dashboard_route(summary_route)?)
+            .option_layer(dashboard_route(sponsorships_route)?)
+    }};
+}
+
+// An address the RPC server listens on: a TCP socket (IPv4 or IPv6) or a Unix domain socket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(Self::Unix(PathBuf::from(path))),
+            Some(_) => Err("Unix socket path is empty".to_string()),
+            None => value
+                .parse()
+                .map(Self::Tcp)
+                .map_err(|e| format!("Invalid listen address {:?}: {}", value, e)),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{}", addr),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+// All listeners serving the RPC module
+pub struct RunningServer {
+    handles: Vec<ServerHandle>,
+    unix_listeners: Vec<(PathBuf, JoinHandle<()>)>,
+    tcp_listeners: Vec<JoinHandle<()>>,
+    // Tells the connections accepted in-process to finish their requests and close
+    stop: watch::Sender<()>,
+    // Closed once every connection accepted in-process is gone
+    connections: mpsc::Receiver<()>,
+}
+
+impl RunningServer {
+    // Stop accepting connections and give in-flight requests up to `grace_period` to finish.
+    // Returns false if requests were still running when the grace period ran out.
+    pub async fn shutdown(mut self, grace_period: Duration) -> Result<bool> {
+        for (path, task) in self.unix_listeners {
+            task.abort();
+            let _ = std::fs::remove_file(&path);
+        }
+        for task in self.tcp_listeners {
+            task.abort();
+        }
+
+        for handle in &self.handles {
+            handle.stop()?;
+        }
+        let _ = self.stop.send(());
+
+        let drained = tokio::time::timeout(grace_period, async {
+            for handle in self.handles {
+                handle.stopped().await;
+            }
+            while self.connections.recv().await.is_some() {}
+        });
+
+        Ok(drained.await.is_ok())
+    }
+}
+
+// Errors are boxed in a concrete type, as a boxed trait object trips up the Send bounds of the
+// spawned connections
+type RpcService = BoxCloneService<Request<Body>, Response<Body>, io::Error>;
+
+// In-process services answering all methods, or just one side of the admin namespace
+#[derive(Clone)]
+struct Services {
+    all: RpcService,
+    public: RpcService,
+    admin: RpcService,
+}
+
+pub async fn start(
+    listen_addrs: &[ListenAddr],
+    methods: Methods,
+    config: &ServerConfig,
+    access: &AccessConfig,
+    metrics: &Arc<Metrics>,
+) -> Result<RunningServer> {
+    let mut handles = Vec::new();
+
+    // Some clients are accepted here and served in-process, as the jsonrpsee server only accepts
+    // TCP connections of its own: Unix socket clients, and TCP clients under access lists because
+    // RPC middleware does not see the client address
+    let restricted = access.enabled();
+    let in_process = restricted || listen_addrs.iter().any(|addr| matches!(addr, ListenAddr::Unix(_)));
+    let services = match in_process {
+        true => Some(Services {
+            all: in_process_service(methods.clone(), config, access, metrics)?,
+            public: in_process_service(select_methods(&methods, |name| !is_admin_method(name))?, config, access, metrics)?,
+            admin: in_process_service(select_methods(&methods, is_admin_method)?, config, access, metrics)?,
+        }),
+        false => None,
+    };
+
+    // Without API keys or admin access lists any TCP client could call the admin namespace, so it is
+    // only served on Unix sockets, where the socket file permissions decide who connects
+    let tcp_methods = match access.guards_admin() {
+        true => methods.clone(),
+        false => {
+            if listen_addrs.iter().any(|addr| matches!(addr, ListenAddr::Tcp(_))) {
+                warn!("Not serving admin_ methods over TCP: configure access.api_keys or access.admin lists to enable them");
+            }
+            select_methods(&methods, |name| !is_admin_method(name))?
+        }
+    };
+
+    let (stop, _) = watch::channel(());
+    let (guard, connections) = mpsc::channel(1);
+    let mut unix_listeners = Vec::new();
+    let mut tcp_listeners = Vec::new();
+
+    for addr in listen_addrs {
+        match (addr, &services) {
+            (ListenAddr::Tcp(addr), Some(services)) if restricted => {
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to bind {}", addr))?;
+                info!("Listening on {} with access lists", listener.local_addr()?);
+
+                let connections = Connections { stop: stop.subscribe(), guard: guard.clone() };
+                tcp_listeners.push(tokio::spawn(serve_tcp(listener, access.clone(), services.clone(), connections)));
+            }
+            (ListenAddr::Tcp(addr), _) => {
+                let (handle, local_addr) = start_tcp(*addr, tcp_methods.clone(), config, access, metrics).await?;
+                info!("Listening on {}", local_addr);
+                handles.push(handle);
+            }
+            (ListenAddr::Unix(path), Some(services)) => {
+                // Remove a socket left behind by an unclean shutdown
+                if path.exists() {
+                    std::fs::remove_file(path)
+                        .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+                }
+
+                let listener = UnixListener::bind(path)
+                    .with_context(|| format!("Failed to bind Unix socket {}", path.display()))?;
+                info!("Listening on unix:{}", path.display());
+
+                let connections = Connections { stop: stop.subscribe(), guard: guard.clone() };
+                unix_listeners.push((path.clone(), tokio::spawn(serve_unix(listener, services.all.clone(), connections))));
+            }
+            (ListenAddr::Unix(_), None) => unreachable!("Unix sockets are always served in-process"),
+        }
+    }
+
+    Ok(RunningServer { handles, unix_listeners, tcp_listeners, stop, connections })
+}
+
+fn in_process_service(
+    methods: Methods,
+    config: &ServerConfig,
+    access: &AccessConfig,
+    metrics: &Arc<Metrics>,
+) -> Result<RpcService> {
+    let service = middleware!(config, access, metrics)
+        .service(InProcess { methods, max_request_body_size: config.max_request_body_size });
+    Ok(BoxCloneService::new(service.map_err(io::Error::other)))
+}
+
+fn is_admin_method(name: &str) -> bool {
+    name.starts_with("admin_")
+}
+
+fn select_methods(methods: &Methods, filter: impl Fn(&str) -> bool) -> Result<Methods> {
+    let mut selected = Methods::new();
+    for name in methods.method_names().filter(|name| filter(name)) {
+        if let Some((name, callback)) = methods.method_with_name(name) {
+            selected.verify_and_insert(name, callback.clone())?;
+        }
+    }
+    Ok(selected)
+}
+
+async fn start_tcp(
+    addr: SocketAddr,
+    methods: Methods,
+    config: &ServerConfig,
+    access: &AccessConfig,
+    metrics: &Arc<Metrics>,
+) -> Result<(ServerHandle, SocketAddr)> {
+    let server = ServerBuilder::default()
+        .max_request_body_size(config.max_request_body_size)
+        .set_middleware(middleware!(config, access, metrics))
+        .build(addr)
+        .await
+        .with_context(|| format!("Failed to bind {}", addr))?;
+
+    let local_addr = server.local_addr()?;
+    Ok((server.start(methods), local_addr))
+}
+
+// What the connections accepted in-process hold on to: the shutdown signal, and a guard that is
+// dropped once the connection is gone
+struct Connections {
+    stop: watch::Receiver<()>,
+    guard: mpsc::Sender<()>,
+}
+
+// Serve each client the namespaces its address may call
+async fn serve_tcp(listener: TcpListener, access: AccessConfig, services: Services, connections: Connections) {
+    loop {
+        let (client, remote_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                error!("Failed to accept TCP connection: {}", e);
+                continue;
+            }
+        };
+
+        let ip = remote_addr.ip();
+        let service = match (access.public.permits(ip), access.permits_admin(ip)) {
+            (true, true) => services.all.clone(),
+            (true, false) => services.public.clone(),
+            (false, true) => services.admin.clone(),
+            (false, false) => {
+                debug!("Refused connection from {}", remote_addr);
+                continue;
+            }
+        };
+
+        tokio::spawn(serve_connection(client, service, connections.stop.clone(), connections.guard.clone()));
+    }
+}
+
+async fn serve_unix(listener: UnixListener, service: RpcService, connections: Connections) {
+    loop {
+        match listener.accept().await {
+            Ok((client, _)) => {
+                tokio::spawn(serve_connection(client, service.clone(), connections.stop.clone(), connections.guard.clone()));
+            }
+            Err(e) => error!("Failed to accept Unix socket connection: {}", e),
+        }
+    }
+}
+
+async fn serve_connection<S>(client: S, service: RpcService, mut stop: watch::Receiver<()>, _guard: mpsc::Sender<()>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let connection = Http::new().serve_connection(client, service);
+    tokio::pin!(connection);
+
+    let result = tokio::select! {
+        result = connection.as_mut() => result,
+        _ = stop.changed() => {
+            connection.as_mut().graceful_shutdown();
+            connection.await
+        }
+    };
+    if let Err(e) = result {
+        debug!("RPC connection closed: {}", e);
+    }
+}
+
+// Answers JSON-RPC calls over HTTP from the methods themselves, single or batched. WebSocket
+// upgrades are not served in-process.
+#[derive(Clone)]
+struct InProcess {
+    methods: Methods,
+    max_request_body_size: u32,
+}
+
+impl Service<Request<Body>> for InProcess {
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync>;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let methods = self.methods.clone();
+        let max_request_body_size = self.max_request_body_size;
+
+        Box::pin(async move {
+            if request.method() != Method::POST {
+                return Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .body(Body::from("Only JSON-RPC calls over POST are served"))
+                    .map_err(Into::into);
+            }
+
+            let body = hyper::body::to_bytes(request.into_body()).await?;
+            if body.len() > max_request_body_size as usize {
+                let error = error_response(Value::Null, reject_too_big_request(max_request_body_size));
+                return respond(StatusCode::PAYLOAD_TOO_LARGE, error);
+            }
+
+            let calls = match serde_json::from_slice::<Value>(&body) {
+                Ok(Value::Array(calls)) if !calls.is_empty() => calls,
+                Ok(Value::Array(_)) => return respond(StatusCode::OK, error_response(Value::Null, ErrorCode::InvalidRequest.into())),
+                Ok(call) => return respond(StatusCode::OK, answer(&methods, call).await.unwrap_or_default()),
+                Err(_) => return respond(StatusCode::OK, error_response(Value::Null, ErrorCode::ParseError.into())),
+            };
+
+            let mut responses = Vec::new();
+            for call in calls {
+                responses.extend(answer(&methods, call).await);
+            }
+            let body = match responses.is_empty() {
+                true => String::new(),
+                false => format!("[{}]", responses.join(",")),
+            };
+            respond(StatusCode::OK, body)
+        })
+    }
+}
+
+// The serialized response to one call, or None for a notification, which runs with a null id
+async fn answer(methods: &Methods, mut call: Value) -> Option<String> {
+    let notification = call.get("id").is_none() && call.get("method").is_some();
+    if notification {
+        call["id"] = Value::Null;
+    }
+
+    let id = call.get("id").cloned().unwrap_or(Value::Null);
+    let response = match methods.raw_json_request(&call.to_string(), 1).await {
+        Ok((response, _)) => response.result,
+        Err(_) => error_response(id, ErrorCode::InvalidRequest.into()),
+    };
+    (!notification).then_some(response)
+}
+
+fn error_response(id: Value, error: ErrorObjectOwned) -> String {
+    json!({ "jsonrpc": "2.0", "error": error, "id": id }).to_string()
+}
+
+fn respond(status: StatusCode, body: String) -> Result<Response<Body>, Box<dyn Error + Send + Sync>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body))
+        .map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AccessList;
+    use jsonrpsee::RpcModule;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpStream, UnixStream};
+
+    fn methods() -> Methods {
+        let mut module = RpcModule::new(());
+        module.register_method("echo", |params, _| params.one::<u64>()).unwrap();
+        module.register_method("admin_echo", |params, _| params.one::<u64>()).unwrap();
+        module.into()
+    }
+
+    // Send one call over a raw HTTP/1.1 connection and read the whole response
+    async fn post<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, body: &str) -> String {
+        let request = format!(
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    async fn call(body: &str) -> (StatusCode, String) {
+        let mut service = InProcess { methods: methods(), max_request_body_size: 256 };
+        let request = Request::post("/").body(Body::from(body.to_string())).unwrap();
+        let response = service.call(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn answers_calls_batches_and_notifications() {
+        let (status, body) = call(r#"{"jsonrpc":"2.0","id":1,"method":"echo","params":[7]}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"jsonrpc":"2.0","result":7,"id":1}"#);
+
+        let (_, body) = call(r#"[{"jsonrpc":"2.0","id":1,"method":"echo","params":[1]},{"jsonrpc":"2.0","method":"echo","params":[2]},{"id":3}]"#).await;
+        let responses: Vec<Value> = serde_json::from_str(&body).unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["result"], 1);
+        assert_eq!(responses[1]["error"]["code"], ErrorCode::InvalidRequest.code());
+        assert_eq!(responses[1]["id"], 3);
+
+        let (_, body) = call(r#"{"jsonrpc":"2.0","method":"echo","params":[2]}"#).await;
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn refuses_malformed_and_oversized_requests() {
+        let (_, body) = call("{").await;
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["error"]["code"], ErrorCode::ParseError.code());
+
+        let (_, body) = call("[]").await;
+        assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["error"]["code"], ErrorCode::InvalidRequest.code());
+
+        let (status, _) = call(&format!(r#"{{"jsonrpc":"2.0","id":1,"method":"echo","params":[{}]}}"#, " ".repeat(256))).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn serves_unix_sockets_in_process() {
+        let path = std::env::temp_dir().join(format!("arka-light-{}.sock", std::process::id()));
+        let listen_addrs = [ListenAddr::Unix(path.clone())];
+        let server = start(&listen_addrs, methods(), &ServerConfig::default(), &AccessConfig::default(), &Arc::new(Metrics::default()))
+            .await
+            .unwrap();
+
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"echo","params":[7]}"#;
+        let response = post(UnixStream::connect(&path).await.unwrap(), body).await;
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with(r#"{"jsonrpc":"2.0","result":7,"id":1}"#));
+        assert!(server.shutdown(Duration::from_secs(1)).await.unwrap());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn public_lists_alone_do_not_admit_admin_calls() {
+        let access = AccessConfig {
+            public: AccessList { allow: vec!["127.0.0.0/8".parse().unwrap()], deny: Vec::new() },
+            ..Default::default()
+        };
+        assert!(!access.guards_admin());
+        assert!(!access.permits_admin("127.0.0.1".parse().unwrap()));
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let server = start(&[ListenAddr::Tcp(addr)], methods(), &ServerConfig::default(), &access, &Arc::new(Metrics::default()))
+            .await
+            .unwrap();
+
+        let response = post(TcpStream::connect(addr).await.unwrap(), r#"{"jsonrpc":"2.0","id":1,"method":"echo","params":[7]}"#).await;
+        assert!(response.ends_with(r#"{"jsonrpc":"2.0","result":7,"id":1}"#));
+
+        let response = post(TcpStream::connect(addr).await.unwrap(), r#"{"jsonrpc":"2.0","id":1,"method":"admin_echo","params":[7]}"#).await;
+        let body: Value = serde_json::from_str(&response[response.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        assert_eq!(body["error"]["code"], ErrorCode::MethodNotFound.code());
+
+        assert!(server.shutdown(Duration::from_secs(1)).await.unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+// src/session.rs
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+
+use crate::calldata;
+use crate::config::SessionConfig;
+use crate::error::PaymasterError;
+use crate::types::UserOperation;
+
+// What a dapp asks for when opening a session
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionGrantRequest {
+    pub sender: Address,
+    // Contracts the session's operations may call
+    pub allowed_targets: Vec<Address>,
+    pub max_ops: u64,
+    // Lifetime in seconds, capped by `sessions.max_ttl_secs`
+    pub ttl_secs: u64,
+}
+
+// A minted session; the token is presented as `sessionToken` in the sponsorship context
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionGrant {
+    pub session_token: String,
+    pub sender: Address,
+    pub allowed_targets: Vec<Address>,
+    pub max_ops: u64,
+    pub expires_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+}
+
+struct Session {
+    grant: SessionGrant,
+    ops_used: u64,
+}
+
+// Short-lived grants letting one sender call a fixed set of targets under the policy they were opened with
+pub struct SessionRegistry {
+    config: SessionConfig,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionRegistry {
+    pub fn new(config: SessionConfig) -> Self {
+        Self {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // `policy_id` is the policy the minting request resolved to; session operations are charged to it
+    pub fn mint(
+        &self,
+        request: SessionGrantRequest,
+        policy_id: Option<String>,
+        now: u64,
+    ) -> Result<SessionGrant, PaymasterError> {
+        if request.allowed_targets.is_empty() {
+            return Err(PaymasterError::InvalidParameters("A session needs at least one allowed target".to_string()));
+        }
+        if request.max_ops == 0 || request.max_ops > self.config.max_ops {
+            return Err(PaymasterError::InvalidParameters(format!(
+                "maxOps must be between 1 and {}", self.config.max_ops
+            )));
+        }
+        if request.ttl_secs == 0 || request.ttl_secs > self.config.max_ttl_secs {
+            return Err(PaymasterError::InvalidParameters(format!(
+                "ttlSecs must be between 1 and {}", self.config.max_ttl_secs
+            )));
+        }
+
+        let grant = SessionGrant {
+            session_token: format!("{:x}", H256::random()),
+            sender: request.sender,
+            allowed_targets: request.allowed_targets,
+            max_ops: request.max_ops,
+            expires_at: now + request.ttl_secs,
+            policy_id,
+        };
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.grant.expires_at > now);
+        sessions.insert(grant.session_token.clone(), Session { grant: grant.clone(), ops_used: 0 });
+        Ok(grant)
+    }
+
+    // Check an operation against its session and count it against the session's operations.
+    // Returns the policy the operation is charged to.
+    pub fn authorize(&self, token: &str, user_op: &UserOperation, now: u64) -> Result<Option<String>, PaymasterError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.grant.expires_at > now);
+        let session = sessions
+            .get_mut(token)
+            .ok_or_else(|| PaymasterError::InvalidParameters("Unknown or expired session token".to_string()))?;
+
+        if user_op.sender != session.grant.sender {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "Session was granted to {:?}, not {:?}", session.grant.sender, user_op.sender
+            )));
+        }
+
+        let calls = calldata::decode_calls(&user_op.call_data).ok_or_else(|| {
+            PaymasterError::InvalidUserOperation("Session operations must use a known execute function".to_string())
+        })?;
+        if let Some(call) = calls.iter().find(|call| !session.grant.allowed_targets.contains(&call.target)) {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "Session does not allow calls to {:?}", call.target
+            )));
+        }
+
+        if session.ops_used >= session.grant.max_ops {
+            return Err(PaymasterError::PolicyLimitExceeded(format!(
+                "Session has used all {} operations", session.grant.max_ops
+            )));
+        }
+        session.ops_used += 1;
+
+        Ok(session.grant.policy_id.clone())
+    }
+
+    // Give back an operation counted by `authorize` that was not sponsored
+    pub fn refund(&self, token: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(token) {
+            session.ops_used = session.ops_used.saturating_sub(1);
+        }
+    }
+
+    pub fn revoke(&self, token: &str) -> bool {
+        self.sessions.lock().unwrap().remove(token).is_some()
+    }
+}
@@ -0,0 +1,99 @@
+// src/settlement.rs
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use ethers::prelude::*;
+use ethers::utils::{format_units, parse_units};
+use tracing::{error, info};
+
+use crate::config::{SettlementConfig, TokenConfig};
+use crate::contracts::{TokenPaymaster, ERC20};
+use crate::deposit::{self, SignerClient};
+use crate::error::PaymasterError;
+use crate::metrics::Metrics;
+use crate::provider::EthProvider;
+
+// Sweeps the tokens collected by each paymaster from senders paying in tokens to the treasury
+pub struct Settlement {
+    client: Arc<SignerClient>,
+    paymasters: Vec<Address>,
+    tokens: HashMap<String, TokenConfig>,
+    treasury: Address,
+    metrics: Arc<Metrics>,
+}
+
+impl Settlement {
+    pub fn new(
+        config: &SettlementConfig,
+        client: EthProvider,
+        owner: LocalWallet,
+        paymasters: Vec<Address>,
+        tokens: HashMap<String, TokenConfig>,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let Some(treasury) = config.treasury else {
+            bail!("Settlement needs a treasury to sweep tokens to");
+        };
+        if tokens.is_empty() {
+            bail!("Settlement needs at least one accepted token");
+        }
+
+        Ok(Self {
+            client: Arc::new(SignerMiddleware::new(client, owner)),
+            paymasters,
+            tokens,
+            treasury,
+            metrics,
+        })
+    }
+
+    pub async fn run(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            for paymaster in &self.paymasters {
+                for (symbol, token) in &self.tokens {
+                    if let Err(e) = self.sweep(*paymaster, symbol, token).await {
+                        error!("Failed to sweep {} collected by paymaster {:?}: {}", symbol, paymaster, e);
+                        Metrics::increment(&self.metrics.settlement_failures);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn sweep(&self, paymaster: Address, symbol: &str, token: &TokenConfig) -> Result<()> {
+        let erc20 = ERC20::new(token.address, self.client.clone());
+        let balance = erc20.balance_of(paymaster)
+            .call()
+            .await
+            .map_err(|e| PaymasterError::EthereumProviderError(e.to_string()))?;
+        if balance.is_zero() {
+            return Ok(());
+        }
+
+        let decimals = match token.decimals {
+            Some(decimals) => decimals,
+            None => erc20.decimals()
+                .call()
+                .await
+                .map_err(|e| PaymasterError::EthereumProviderError(e.to_string()))?,
+        };
+        if let Some(min_sweep) = &token.min_sweep {
+            if balance < parse_units(min_sweep, decimals as u32)?.into() {
+                return Ok(());
+            }
+        }
+
+        let call = TokenPaymaster::new(paymaster, self.client.clone()).withdraw_token(token.address, self.treasury, balance);
+        let tx_hash = deposit::send_and_confirm(call).await?;
+        info!(
+            "Swept {} {} collected by paymaster {:?} to {:?} in transaction {:?}",
+            format_units(balance, decimals as u32)?, symbol, paymaster, self.treasury, tx_hash,
+        );
+        Metrics::increment(&self.metrics.token_sweeps);
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+// src/shard.rs
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ethers::prelude::*;
+
+use crate::error::PaymasterError;
+use crate::signer::PaymasterSigner;
+
+// A funded paymaster contract and the key that signs for it
+pub struct Shard {
+    pub paymaster_address: Address,
+    pub signer: Arc<dyn PaymasterSigner>,
+}
+
+// Operations are identified by sender and nonce, as in the policy reservations
+type OperationKey = (Address, U256);
+
+struct InFlight {
+    shard: usize,
+    max_cost: U256,
+    valid_until: u64,
+}
+
+// Paymaster deposits that sponsorships are spread across. Each signed operation holds its max
+// cost against its shard's deposit until it is included or its signature expires.
+pub struct ShardSet {
+    shards: Vec<Shard>,
+    in_flight: Mutex<HashMap<OperationKey, InFlight>>,
+}
+
+impl ShardSet {
+    pub fn new(primary: Shard) -> Self {
+        Self {
+            shards: vec![primary],
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn push(&mut self, shard: Shard) {
+        self.shards.push(shard);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Shard> {
+        self.shards.iter()
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        self.shards.iter().map(|shard| shard.paymaster_address).collect()
+    }
+
+    // With a single deposit there is nothing to route, so in-flight operations are not tracked
+    pub fn is_sharded(&self) -> bool {
+        self.shards.len() > 1
+    }
+
+    // Check that some shard can still cover `max_cost`, given each shard's deposit in order, and
+    // return the paymaster that would be picked
+    pub fn check(&self, deposits: &[U256], sender: Address, nonce: U256, max_cost: U256) -> Result<Address, PaymasterError> {
+        let in_flight = self.in_flight.lock().unwrap();
+        pick(&in_flight, (sender, nonce), deposits, max_cost).map(|shard| self.shards[shard].paymaster_address)
+    }
+
+    // Pick the shard with the most deposit left after in-flight operations and hold the
+    // operation's max cost against it
+    pub fn reserve(
+        &self,
+        deposits: &[U256],
+        sender: Address,
+        nonce: U256,
+        max_cost: U256,
+        valid_until: u64,
+    ) -> Result<&Shard, PaymasterError> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let shard = pick(&in_flight, (sender, nonce), deposits, max_cost)?;
+
+        if self.is_sharded() {
+            in_flight.insert((sender, nonce), InFlight { shard, max_cost, valid_until });
+        }
+        Ok(&self.shards[shard])
+    }
+
+    // Max cost held against each shard's deposit, in shard order
+    pub fn in_flight(&self) -> Vec<U256> {
+        let in_flight = self.in_flight.lock().unwrap();
+        exposure(in_flight.iter(), self.shards.len())
+    }
+
+    // Drop an operation's hold, once it was included or its signing failed
+    pub fn release(&self, sender: Address, nonce: U256) {
+        self.in_flight.lock().unwrap().remove(&(sender, nonce));
+    }
+
+    // Drop holds of operations that can no longer be included at `block_timestamp`
+    pub fn release_expired(&self, block_timestamp: u64) -> usize {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let before = in_flight.len();
+        in_flight.retain(|_, operation| operation.valid_until >= block_timestamp);
+        before - in_flight.len()
+    }
+}
+
+// A new signature for an operation replaces any earlier one, so its own hold is left out
+fn pick(
+    in_flight: &HashMap<OperationKey, InFlight>,
+    key: OperationKey,
+    deposits: &[U256],
+    max_cost: U256,
+) -> Result<usize, PaymasterError> {
+    let others = in_flight.iter().filter(|(operation_key, _)| **operation_key != key);
+
+    deposits
+        .iter()
+        .zip(exposure(others, deposits.len()))
+        .map(|(deposit, exposure)| deposit.saturating_sub(exposure))
+        .enumerate()
+        .filter(|(_, available)| *available > max_cost)
+        // Ties go to the earlier shard, so the primary deposit is preferred
+        .min_by_key(|(_, available)| Reverse(*available))
+        .map(|(shard, _)| shard)
+        .ok_or(PaymasterError::InsufficientFunds)
+}
+
+fn exposure<'a>(in_flight: impl Iterator<Item = (&'a OperationKey, &'a InFlight)>, shards: usize) -> Vec<U256> {
+    let mut exposure = vec![U256::zero(); shards];
+    for (_, operation) in in_flight {
+        exposure[operation.shard] = exposure[operation.shard].saturating_add(operation.max_cost);
+    }
+    exposure
+}
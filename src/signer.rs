@@ -0,0 +1,488 @@
+// src/signer.rs
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use ethers::abi::{self, Token};
+use ethers::prelude::*;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::utils::keccak256;
+use tracing::{error, info, warn};
+
+use crate::config::{ContractSignatureScheme, SignerConfig, SigningConfig};
+use crate::metrics::Metrics;
+use crate::types::SignerStatus;
+
+/// A key able to sign sponsorships on behalf of the paymaster.
+///
+/// Implement this to plug in hardware wallets, threshold/MPC signers or HSMs.
+#[async_trait]
+pub trait PaymasterSigner: Send + Sync {
+    /// Address whose signatures the paymaster contract accepts.
+    fn address(&self) -> Address;
+
+    /// Signs `hash` as an EIP-191 personal message
+    /// (`"\x19Ethereum Signed Message:\n32" ++ hash`), which is what verifying paymasters check.
+    ///
+    /// Returns the signature as the paymaster contract receives it: a 65-byte ECDSA signature,
+    /// or an ERC-1271 signature blob for contract signers.
+    async fn sign_hash(&self, hash: H256) -> anyhow::Result<Bytes>;
+
+    /// Signs several hashes, returning the results in the same order.
+    ///
+    /// Backends with a batch signing API can override this; by default the hashes are signed
+    /// concurrently with [`sign_hash`](Self::sign_hash).
+    async fn sign_batch(&self, hashes: &[H256]) -> Vec<anyhow::Result<Bytes>> {
+        futures::future::join_all(hashes.iter().map(|hash| self.sign_hash(*hash))).await
+    }
+
+    /// Short description of the backend, reported by `system_health`.
+    fn kind(&self) -> &str {
+        "custom"
+    }
+
+    /// Checks that the signer is reachable and usable.
+    async fn health_check(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Health of every key behind this signer.
+    fn status(&self) -> Vec<SignerStatus> {
+        vec![SignerStatus {
+            address: self.address(),
+            kind: self.kind().to_string(),
+            primary: true,
+            healthy: true,
+        }]
+    }
+}
+
+#[async_trait]
+impl PaymasterSigner for LocalWallet {
+    fn address(&self) -> Address {
+        Signer::address(self)
+    }
+
+    async fn sign_hash(&self, hash: H256) -> anyhow::Result<Bytes> {
+        Ok(self.sign_message(hash.as_bytes()).await?.to_vec().into())
+    }
+
+    fn kind(&self) -> &str {
+        "local"
+    }
+}
+
+// Remote signing service (KMS bridge, Web3Signer, Clef) speaking `eth_sign`
+pub struct RemoteSigner {
+    provider: Provider<Http>,
+    address: Address,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: Address) -> Result<Self> {
+        Ok(Self {
+            provider: Provider::<Http>::try_from(url)?,
+            address,
+        })
+    }
+}
+
+#[async_trait]
+impl PaymasterSigner for RemoteSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    async fn sign_hash(&self, hash: H256) -> anyhow::Result<Bytes> {
+        let signature = self.provider.sign(hash.as_bytes().to_vec(), &self.address).await?;
+        Ok(signature.to_vec().into())
+    }
+
+    fn kind(&self) -> &str {
+        "remote"
+    }
+
+    // The service must still expose our address
+    async fn health_check(&self) -> anyhow::Result<()> {
+        let accounts = self.provider.get_accounts().await?;
+        if !accounts.contains(&self.address) {
+            bail!("Remote signer no longer exposes {:?}", self.address);
+        }
+        Ok(())
+    }
+}
+
+// EIP-712 type hashes of a Safe's domain and of the messages its owners sign
+const SAFE_DOMAIN_TYPE: &str = "EIP712Domain(uint256 chainId,address verifyingContract)";
+const SAFE_MESSAGE_TYPE: &str = "SafeMessage(bytes message)";
+// Added to `v` to mark an owner signature as made with eth_sign
+const SAFE_ETH_SIGN_V_OFFSET: u8 = 4;
+
+// Smart contract wallet whose signatures are checked with ERC-1271 `isValidSignature`.
+// The paymaster contract must validate through ERC-1271 (e.g. OpenZeppelin `SignatureChecker`)
+// rather than plain `ecrecover`.
+pub struct ContractSigner {
+    address: Address,
+    threshold: usize,
+    co_signers: Vec<Arc<dyn PaymasterSigner>>,
+    scheme: ContractSignatureScheme,
+    // Part of a Safe's EIP-712 domain
+    chain_id: u64,
+}
+
+impl ContractSigner {
+    pub fn new(address: Address, threshold: usize, co_signers: Vec<Arc<dyn PaymasterSigner>>) -> Result<Self> {
+        if threshold == 0 || threshold > co_signers.len() {
+            bail!(
+                "Contract signer {:?} needs a threshold between 1 and its {} co-signers, got {}",
+                address, co_signers.len(), threshold,
+            );
+        }
+
+        Ok(Self { address, threshold, co_signers, scheme: ContractSignatureScheme::Concatenated, chain_id: 0 })
+    }
+
+    pub fn with_scheme(mut self, scheme: ContractSignatureScheme, chain_id: u64) -> Self {
+        self.scheme = scheme;
+        self.chain_id = chain_id;
+        self
+    }
+}
+
+// The hash a Safe's owners sign for `isValidSignature(hash, ...)`, where the paymaster contract
+// passes the EIP-191 hash of what it was asked to sign
+pub fn safe_message_hash(chain_id: u64, safe: Address, hash: H256) -> H256 {
+    let data_hash = ethers::utils::hash_message(hash);
+    let domain_separator = keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(SAFE_DOMAIN_TYPE).to_vec()),
+        Token::Uint(chain_id.into()),
+        Token::Address(safe),
+    ]));
+    let struct_hash = keccak256(abi::encode(&[
+        Token::FixedBytes(keccak256(SAFE_MESSAGE_TYPE).to_vec()),
+        Token::FixedBytes(keccak256(data_hash).to_vec()),
+    ]));
+
+    let mut digest = Vec::with_capacity(66);
+    digest.extend_from_slice(b"\x19\x01");
+    digest.extend_from_slice(&domain_separator);
+    digest.extend_from_slice(&struct_hash);
+    H256::from(keccak256(digest))
+}
+
+#[async_trait]
+impl PaymasterSigner for ContractSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    // Collect `threshold` co-signer signatures, over the SafeMessage hash for a Safe, and
+    // concatenate them in ascending owner address order, the layout threshold multisigs check
+    async fn sign_hash(&self, hash: H256) -> anyhow::Result<Bytes> {
+        let message = match self.scheme {
+            ContractSignatureScheme::Safe => safe_message_hash(self.chain_id, self.address, hash),
+            ContractSignatureScheme::Concatenated => hash,
+        };
+        let mut signatures = Vec::with_capacity(self.threshold);
+
+        for co_signer in &self.co_signers {
+            if signatures.len() == self.threshold {
+                break;
+            }
+
+            match co_signer.sign_hash(message).await {
+                // Co-signers sign EIP-191 messages, which a Safe checks as eth_sign signatures. Remote
+                // and KMS signers may return v as 0 or 1 rather than 27 or 28.
+                Ok(signature) if self.scheme == ContractSignatureScheme::Safe => match (signature.len(), signature.last()) {
+                    (65, Some(&v @ (0 | 1 | 27 | 28))) => {
+                        let mut signature = signature.to_vec();
+                        signature[64] = (v % 27) + 27 + SAFE_ETH_SIGN_V_OFFSET;
+                        signatures.push((co_signer.address(), Bytes::from(signature)));
+                    }
+                    (65, Some(v)) => warn!("Co-signer {:?} of Safe {:?} returned a signature with v = {}", co_signer.address(), self.address, v),
+                    (length, _) => warn!("Co-signer {:?} of Safe {:?} returned a {}-byte signature, not ECDSA", co_signer.address(), self.address, length),
+                },
+                Ok(signature) => signatures.push((co_signer.address(), signature)),
+                Err(e) => warn!("Co-signer {:?} of {:?} failed to sign: {}", co_signer.address(), self.address, e),
+            }
+        }
+
+        if signatures.len() < self.threshold {
+            bail!(
+                "Only {} of {} required co-signers of {:?} signed",
+                signatures.len(), self.threshold, self.address,
+            );
+        }
+
+        signatures.sort_by_key(|(owner, _)| *owner);
+        Ok(signatures.into_iter().flat_map(|(_, signature)| signature.to_vec()).collect::<Vec<u8>>().into())
+    }
+
+    fn kind(&self) -> &str {
+        "contract"
+    }
+
+    // Healthy while enough co-signers are reachable to reach the threshold
+    async fn health_check(&self) -> anyhow::Result<()> {
+        let mut available = 0;
+        for co_signer in &self.co_signers {
+            match co_signer.health_check().await {
+                Ok(()) => available += 1,
+                Err(e) => warn!("Co-signer {:?} of {:?} failed health check: {}", co_signer.address(), self.address, e),
+            }
+        }
+
+        if available < self.threshold {
+            bail!("Only {} of {} required co-signers are available", available, self.threshold);
+        }
+        Ok(())
+    }
+}
+
+// Build a signer from its configuration
+pub fn from_config(config: &SignerConfig, chain_id: u64) -> Result<Arc<dyn PaymasterSigner>> {
+    match config {
+        SignerConfig::Local { private_key_env } => {
+            let private_key = std::env::var(private_key_env)
+                .with_context(|| format!("Environment variable {} is not set", private_key_env))?;
+            Ok(Arc::new(private_key.parse::<LocalWallet>()?.with_chain_id(chain_id)))
+        }
+        SignerConfig::Keystore { path, password_env } => {
+            let password = std::env::var(password_env)
+                .with_context(|| format!("Environment variable {} is not set", password_env))?;
+            let wallet = LocalWallet::decrypt_keystore(path, password)
+                .with_context(|| format!("Failed to decrypt keystore {}", path.display()))?;
+            Ok(Arc::new(wallet.with_chain_id(chain_id)))
+        }
+        SignerConfig::Remote { url, address } => Ok(Arc::new(RemoteSigner::new(url, *address)?)),
+        SignerConfig::Contract { address, threshold, co_signers, scheme } => {
+            let co_signers = co_signers
+                .iter()
+                .map(|co_signer| from_config(co_signer, chain_id))
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Arc::new(ContractSigner::new(*address, *threshold, co_signers)?.with_scheme(*scheme, chain_id)))
+        }
+    }
+}
+
+struct ChainEntry {
+    signer: Arc<dyn PaymasterSigner>,
+    healthy: AtomicBool,
+}
+
+// Ordered list of signers; requests go to the first healthy one and fall through on failure
+pub struct SignerChain {
+    entries: Vec<ChainEntry>,
+    metrics: Arc<Metrics>,
+}
+
+impl SignerChain {
+    pub fn new(signers: Vec<Arc<dyn PaymasterSigner>>, metrics: Arc<Metrics>) -> Result<Self> {
+        if signers.is_empty() {
+            bail!("At least one signer must be configured");
+        }
+
+        let entries = signers
+            .into_iter()
+            .map(|signer| ChainEntry { signer, healthy: AtomicBool::new(true) })
+            .collect();
+
+        Ok(Self { entries, metrics })
+    }
+
+    pub fn from_config(
+        config: &SigningConfig,
+        private_key: Option<&str>,
+        chain_id: u64,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self> {
+        let mut signers = config.signers
+            .iter()
+            .map(|signer| from_config(signer, chain_id))
+            .collect::<Result<Vec<_>>>()?;
+
+        // A key given on the command line is used when no signers are configured
+        if signers.is_empty() {
+            if let Some(private_key) = private_key {
+                signers.push(Arc::new(private_key.parse::<LocalWallet>()?.with_chain_id(chain_id)));
+            }
+        }
+
+        Self::new(signers, metrics)
+    }
+
+    fn record_signature(&self, index: usize) {
+        Metrics::increment(&self.metrics.signatures);
+
+        let previous = self.metrics.active_signer.swap(index as u64, Ordering::Relaxed);
+        if index > 0 {
+            Metrics::increment(&self.metrics.fallback_signatures);
+            if previous != index as u64 {
+                warn!("Primary signer unavailable, signing with fallback signer {:?}", self.entries[index].signer.address());
+            }
+        } else if previous != 0 {
+            info!("Primary signer {:?} restored", self.entries[0].signer.address());
+        }
+    }
+
+    // Periodically probe every signer so that failed ones are skipped and recovered ones reused
+    pub async fn run_health_checks(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            for entry in &self.entries {
+                let healthy = match entry.signer.health_check().await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        warn!("Signer {:?} failed health check: {}", entry.signer.address(), e);
+                        false
+                    }
+                };
+                entry.healthy.store(healthy, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PaymasterSigner for SignerChain {
+    // Address of the primary signer
+    fn address(&self) -> Address {
+        self.entries[0].signer.address()
+    }
+
+    // Sign with the first healthy signer, failing over down the chain
+    async fn sign_hash(&self, hash: H256) -> anyhow::Result<Bytes> {
+        // Unhealthy signers are only retried once every healthy one has failed
+        let (healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..self.entries.len())
+            .partition(|&i| self.entries[i].healthy.load(Ordering::Relaxed));
+
+        for index in healthy.into_iter().chain(unhealthy) {
+            let entry = &self.entries[index];
+            match entry.signer.sign_hash(hash).await {
+                Ok(signature) => {
+                    entry.healthy.store(true, Ordering::Relaxed);
+                    self.record_signature(index);
+                    return Ok(signature);
+                }
+                Err(e) => {
+                    error!("Signer {:?} failed to sign: {}", entry.signer.address(), e);
+                    entry.healthy.store(false, Ordering::Relaxed);
+                    Metrics::increment(&self.metrics.signer_failures);
+                }
+            }
+        }
+
+        bail!("No signer in the chain could sign")
+    }
+
+    fn kind(&self) -> &str {
+        "chain"
+    }
+
+    fn status(&self) -> Vec<SignerStatus> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| SignerStatus {
+                address: entry.signer.address(),
+                kind: entry.signer.kind().to_string(),
+                primary: index == 0,
+                healthy: entry.healthy.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns v as 0 or 1, as some remote and KMS signers do
+    struct ZeroBasedV(LocalWallet);
+
+    #[async_trait]
+    impl PaymasterSigner for ZeroBasedV {
+        fn address(&self) -> Address {
+            PaymasterSigner::address(&self.0)
+        }
+
+        async fn sign_hash(&self, hash: H256) -> anyhow::Result<Bytes> {
+            let mut signature = PaymasterSigner::sign_hash(&self.0, hash).await?.to_vec();
+            signature[64] -= 27;
+            Ok(signature.into())
+        }
+    }
+
+    fn owners() -> Vec<Arc<dyn PaymasterSigner>> {
+        ["0x0000000000000000000000000000000000000000000000000000000000000001", "0x0000000000000000000000000000000000000000000000000000000000000002"]
+            .into_iter()
+            .map(|key| Arc::new(key.parse::<LocalWallet>().unwrap()) as Arc<dyn PaymasterSigner>)
+            .collect()
+    }
+
+    // Owner addresses recovered from each 65-byte signature of a contract signature, with `v`
+    // lowered by `v_offset`
+    fn recover_owners(blob: &Bytes, message: H256, v_offset: u8) -> Vec<Address> {
+        blob.chunks(65)
+            .map(|chunk| {
+                let mut chunk = chunk.to_vec();
+                chunk[64] -= v_offset;
+                Signature::try_from(chunk.as_slice()).unwrap().recover(message.as_bytes()).unwrap()
+            })
+            .collect()
+    }
+
+    // Expected digest follows CompatibilityFallbackHandler.getMessageHashForSafe over
+    // abi.encode of the EIP-191 hash
+    #[test]
+    fn hashes_safe_messages_as_the_safe_does() {
+        assert_eq!(
+            safe_message_hash(1, Address::repeat_byte(0x22), H256::repeat_byte(0x33)),
+            "0xec209e5f8bc3a164c97df2d9d50fe3bb6012992e163f056f817430f2e35a24d8".parse::<H256>().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn signs_safe_messages_as_eth_sign_owners_in_address_order() {
+        let safe = Address::repeat_byte(0x22);
+        let hash = H256::repeat_byte(0x33);
+        let signer = ContractSigner::new(safe, 2, owners()).unwrap().with_scheme(ContractSignatureScheme::Safe, 1);
+        let blob = signer.sign_hash(hash).await.unwrap();
+
+        assert_eq!(blob.len(), 130);
+        assert!(blob[64] > 30 && blob[129] > 30);
+        let mut expected: Vec<Address> = owners().iter().map(|owner| owner.address()).collect();
+        expected.sort();
+        assert_eq!(recover_owners(&blob, safe_message_hash(1, safe, hash), SAFE_ETH_SIGN_V_OFFSET), expected);
+    }
+
+    #[tokio::test]
+    async fn normalizes_zero_based_v_before_the_eth_sign_offset() {
+        let safe = Address::repeat_byte(0x22);
+        let hash = H256::repeat_byte(0x33);
+        let owner: LocalWallet = "0x0000000000000000000000000000000000000000000000000000000000000001".parse().unwrap();
+        let expected = Signer::address(&owner);
+        let signer = ContractSigner::new(safe, 1, vec![Arc::new(ZeroBasedV(owner)) as Arc<dyn PaymasterSigner>])
+            .unwrap()
+            .with_scheme(ContractSignatureScheme::Safe, 1);
+        let blob = signer.sign_hash(hash).await.unwrap();
+
+        assert!(blob[64] == 31 || blob[64] == 32);
+        assert_eq!(recover_owners(&blob, safe_message_hash(1, safe, hash), SAFE_ETH_SIGN_V_OFFSET), vec![expected]);
+    }
+
+    #[tokio::test]
+    async fn concatenates_signatures_over_the_hash() {
+        let hash = H256::repeat_byte(0x33);
+        let signer = ContractSigner::new(Address::repeat_byte(0x22), 1, owners())
+            .unwrap()
+            .with_scheme(ContractSignatureScheme::Concatenated, 1);
+        let blob = signer.sign_hash(hash).await.unwrap();
+
+        assert_eq!(blob.len(), 65);
+        assert_eq!(recover_owners(&blob, hash, 0), vec![owners()[0].address()]);
+    }
+}
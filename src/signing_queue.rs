@@ -0,0 +1,136 @@
+// src/signing_queue.rs
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use ethers::types::{Address, Bytes, H256};
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::time::Instant;
+
+use crate::config::SigningQueueConfig;
+use crate::metrics::Metrics;
+use crate::signer::PaymasterSigner;
+use crate::types::SignerStatus;
+
+struct Request {
+    hash: H256,
+    deadline: Instant,
+    reply: oneshot::Sender<anyhow::Result<Bytes>>,
+}
+
+// Puts a slow signer behind a bounded queue. Waiting requests are taken in batches and up to
+// `concurrency` batches are in flight at once; a request that cannot be answered before its
+// deadline fails instead of holding up the sponsorship.
+pub struct SigningQueue {
+    signer: Arc<dyn PaymasterSigner>,
+    requests: mpsc::Sender<Request>,
+    deadline: Duration,
+    metrics: Arc<Metrics>,
+}
+
+impl SigningQueue {
+    pub fn new(signer: Arc<dyn PaymasterSigner>, config: &SigningQueueConfig, metrics: Arc<Metrics>) -> Self {
+        let (requests, receiver) = mpsc::channel(config.capacity.max(1));
+        tokio::spawn(dispatch(signer.clone(), receiver, config.batch_size.max(1), config.concurrency.max(1)));
+
+        Self {
+            signer,
+            requests,
+            deadline: Duration::from_millis(config.deadline_ms),
+            metrics,
+        }
+    }
+}
+
+#[async_trait]
+impl PaymasterSigner for SigningQueue {
+    fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    async fn sign_hash(&self, hash: H256) -> anyhow::Result<Bytes> {
+        let deadline = Instant::now() + self.deadline;
+        let (reply, response) = oneshot::channel();
+        self.requests.try_send(Request { hash, deadline, reply }).map_err(|_| {
+            Metrics::increment(&self.metrics.signing_queue_full);
+            anyhow!("Signing queue is full")
+        })?;
+        let _queued = Queued::new(&self.metrics.signing_queue_depth);
+
+        match tokio::time::timeout_at(deadline, response).await {
+            Ok(Ok(signature)) => signature,
+            // The dispatcher drops requests whose deadline passed while they waited
+            Ok(Err(_)) | Err(_) => {
+                Metrics::increment(&self.metrics.signing_deadline_exceeded);
+                bail!("Signing did not finish within {}ms", self.deadline.as_millis())
+            }
+        }
+    }
+
+    fn kind(&self) -> &str {
+        self.signer.kind()
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        self.signer.health_check().await
+    }
+
+    fn status(&self) -> Vec<SignerStatus> {
+        self.signer.status()
+    }
+}
+
+async fn dispatch(
+    signer: Arc<dyn PaymasterSigner>,
+    mut receiver: mpsc::Receiver<Request>,
+    batch_size: usize,
+    concurrency: usize,
+) {
+    let slots = Arc::new(Semaphore::new(concurrency));
+    loop {
+        // Requests keep queueing while every slot is busy, so batches grow with the load
+        let Ok(slot) = slots.clone().acquire_owned().await else { return };
+        let Some(first) = receiver.recv().await else { return };
+        let mut batch = vec![first];
+        while batch.len() < batch_size {
+            match receiver.try_recv() {
+                Ok(request) => batch.push(request),
+                Err(_) => break,
+            }
+        }
+
+        let now = Instant::now();
+        batch.retain(|request| request.deadline > now && !request.reply.is_closed());
+        if batch.is_empty() {
+            continue;
+        }
+
+        let signer = signer.clone();
+        tokio::spawn(async move {
+            let hashes: Vec<H256> = batch.iter().map(|request| request.hash).collect();
+            let signatures = signer.sign_batch(&hashes).await;
+            for (request, signature) in batch.into_iter().zip(signatures) {
+                let _ = request.reply.send(signature);
+            }
+            drop(slot);
+        });
+    }
+}
+
+// Counts a request in the queue depth until its caller stops waiting
+struct Queued<'a>(&'a AtomicU64);
+
+impl<'a> Queued<'a> {
+    fn new(depth: &'a AtomicU64) -> Self {
+        depth.fetch_add(1, Ordering::Relaxed);
+        Self(depth)
+    }
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,829 @@
+// src/store.rs
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use ethers::types::{Address, Bytes, H256, U256};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::db::Database;
+use crate::error::PaymasterError;
+use crate::policy::SECONDS_PER_DAY;
+use crate::project::Project;
+
+// Sponsorships the in-memory store keeps before dropping the oldest
+const MEMORY_HISTORY: usize = 10_000;
+
+// A signed sponsorship, as kept in the history
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorshipRecord {
+    pub user_op_hash: H256,
+    pub chain_id: u64,
+    pub sender: Address,
+    pub nonce: U256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+    pub max_cost: U256,
+    pub valid_after: u64,
+    pub valid_until: u64,
+    pub paymaster_and_data: Bytes,
+    pub created_at: u64,
+    // Signature aggregator the account uses, when simulation found one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregator: Option<Address>,
+}
+
+// Spend recorded against a policy for one UTC day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DailyUsage {
+    // Actual gas cost of operations seen on-chain
+    pub spent: U256,
+    // Max cost of signed operations not yet seen on-chain
+    pub reserved: U256,
+    pub ops: u64,
+}
+
+impl DailyUsage {
+    pub fn committed(&self) -> U256 {
+        self.spent.saturating_add(self.reserved)
+    }
+
+    pub fn reserve(&mut self, amount: U256) {
+        self.reserved = self.reserved.saturating_add(amount);
+        self.ops += 1;
+    }
+
+    // Take a reservation back out; `uncount` also gives back the operation it used from the
+    // daily allowance
+    pub fn unreserve(&mut self, amount: U256, uncount: bool) {
+        self.reserved = self.reserved.saturating_sub(amount);
+        if uncount {
+            self.ops = self.ops.saturating_sub(1);
+        }
+    }
+}
+
+// Budget held for a signed operation until it is included or its signature expires.
+// Operations are identified by sender and nonce: the on-chain userOpHash covers the
+// paymaster signature, so it is not known when the reservation is made.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reservation {
+    pub policy_id: String,
+    // UTC day, counted from the Unix epoch, whose usage the reservation is held against
+    pub day: u64,
+    pub amount: U256,
+    pub valid_until: u64,
+}
+
+// Value of one asset moved by the calls of a signed operation, counted against a daily limit.
+// The zero address stands for the native currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transfer {
+    pub asset: Address,
+    pub amount: U256,
+    pub daily_limit: U256,
+}
+
+impl Transfer {
+    // Check the transfer fits under its limit with `transferred` already moved today
+    pub fn check(&self, policy_id: &str, transferred: U256) -> Result<(), PaymasterError> {
+        if transferred.saturating_add(self.amount) > self.daily_limit {
+            return Err(PaymasterError::PolicyLimitExceeded(format!(
+                "Policy {} may move {} more of {:?} today, the operation moves {}",
+                policy_id, self.daily_limit.saturating_sub(transferred), self.asset, self.amount,
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Tokens an operation paid for in an ERC-20 token owes the paymaster, and what its postOp collected.
+// Keyed by sender and nonce like reservations; `owed` and `userOpHash` are filled in once the
+// operation is included, `collected` once the paymaster's charge is seen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenDebt {
+    pub sender: Address,
+    pub nonce: U256,
+    pub token: Address,
+    // Token base units per 1 ETH, as signed into paymasterAndData
+    pub exchange_rate: U256,
+    pub max_token_cost: U256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owed: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collected: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_op_hash: Option<H256>,
+    pub created_at: u64,
+}
+
+impl TokenDebt {
+    // Tokens owed but not collected, once the operation has been included
+    pub fn shortfall(&self) -> Option<U256> {
+        let owed = self.owed?;
+        Some(owed.saturating_sub(self.collected.unwrap_or_default())).filter(|shortfall| !shortfall.is_zero())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OperationState {
+    Pending,
+    Included,
+    Failed,
+}
+
+impl OperationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Included => "included",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+// What became of a sponsored operation, keyed by the userOpHash it is submitted under, that is
+// with the paymaster's paymasterAndData in place. The transaction and gas are filled in from the
+// bundler's receipt.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationStatus {
+    pub user_op_hash: H256,
+    pub sender: Address,
+    pub nonce: U256,
+    pub status: OperationState,
+    pub valid_until: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_hash: Option<H256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual_gas_cost: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actual_gas_used: Option<U256>,
+    // Why the operation failed: the revert reason, or that it expired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub created_at: u64,
+}
+
+// Budget check run by `SponsorshipStore::reserve` against the day's usage
+pub type UsageCheck<'a> = dyn Fn(&DailyUsage) -> Result<(), PaymasterError> + Send + Sync + 'a;
+
+/// Where sponsorship state is kept: the history of signed operations, what each policy has used
+/// per day, the budget held for operations not yet included, and sets of keys already seen.
+///
+/// Implement this to keep the state in another database.
+#[async_trait]
+pub trait SponsorshipStore: Send + Sync {
+    /// Adds a signed sponsorship to the history, replacing any earlier one with the same userOpHash.
+    async fn record_sponsorship(&self, record: &SponsorshipRecord) -> Result<(), PaymasterError>;
+
+    /// Looks up a sponsorship in the history.
+    async fn sponsorship(&self, user_op_hash: H256) -> Result<Option<SponsorshipRecord>, PaymasterError>;
+
+    /// What a policy has used on a UTC day, counted from the Unix epoch.
+    async fn usage(&self, policy_id: &str, day: u64) -> Result<DailyUsage, PaymasterError>;
+
+    /// Holds `reservation.amount` of the policy's budget for the operation with the given sender
+    /// and nonce, releasing any earlier reservation for it first.
+    ///
+    /// `check` is passed the day's usage with the earlier reservation released, and the
+    /// reservation is only made if it passes. Check and update must be atomic.
+    async fn reserve(
+        &self,
+        sender: Address,
+        nonce: U256,
+        reservation: Reservation,
+        check: &UsageCheck<'_>,
+    ) -> Result<(), PaymasterError>;
+
+    /// Drops a reservation, giving back its budget and operation.
+    async fn release(&self, sender: Address, nonce: U256) -> Result<(), PaymasterError>;
+
+    /// Replaces a reservation with what the operation actually cost. Returns the policy it was
+    /// charged to, or `None` if the operation had no reservation.
+    async fn settle(&self, sender: Address, nonce: U256, actual_cost: U256) -> Result<Option<String>, PaymasterError>;
+
+    /// Releases the reservations of operations that expired before `block_timestamp`.
+    /// Returns how many were released.
+    async fn release_expired(&self, block_timestamp: u64) -> Result<usize, PaymasterError>;
+
+    /// Adds `key` to the named set. Returns `false` if it was already there.
+    async fn insert_replay_key(&self, set: &str, key: &str, expires_at: u64) -> Result<bool, PaymasterError>;
+
+    async fn contains_replay_key(&self, set: &str, key: &str) -> Result<bool, PaymasterError>;
+
+    /// Drops replay keys that expired before `now`.
+    async fn prune(&self, now: u64) -> Result<(), PaymasterError>;
+
+    /// How much of `asset` the policy's signed operations have moved on a UTC day.
+    async fn transferred(&self, policy_id: &str, day: u64, asset: Address) -> Result<U256, PaymasterError>;
+
+    /// Adds the transfers of a signed operation to what the policy has moved on a UTC day.
+    ///
+    /// Signing the same sender and nonce again replaces the transfers recorded for the earlier
+    /// signature, since only one of them can be included. Either all are recorded or, when any
+    /// would go over its daily limit, none are and the [`Transfer::check`] error is returned.
+    /// Check and update must be atomic.
+    async fn record_transfers(
+        &self,
+        sender: Address,
+        nonce: U256,
+        policy_id: &str,
+        day: u64,
+        transfers: &[Transfer],
+    ) -> Result<(), PaymasterError>;
+
+    /// Records what an operation paid for in tokens owes, replacing any earlier record for its
+    /// sender and nonce.
+    async fn record_token_debt(&self, debt: &TokenDebt) -> Result<(), PaymasterError>;
+
+    async fn token_debt(&self, sender: Address, nonce: U256) -> Result<Option<TokenDebt>, PaymasterError>;
+
+    /// Token debts recorded at or after `since`, oldest first.
+    async fn token_debts(&self, since: u64) -> Result<Vec<TokenDebt>, PaymasterError>;
+
+    /// Records what became of a sponsored operation, replacing any earlier status for its hash.
+    async fn record_operation_status(&self, status: &OperationStatus) -> Result<(), PaymasterError>;
+
+    async fn operation_status(&self, user_op_hash: H256) -> Result<Option<OperationStatus>, PaymasterError>;
+
+    /// Operations still pending whose `valid_until` is at or after `valid_from`, oldest first.
+    async fn pending_operations(&self, valid_from: u64) -> Result<Vec<OperationStatus>, PaymasterError>;
+
+    /// Adds a project, or replaces the one with the same ID. Projects are shared by every chain.
+    async fn save_project(&self, project: &Project) -> Result<(), PaymasterError>;
+
+    /// Removes a project. Returns `false` if there was none with the ID.
+    async fn delete_project(&self, project_id: &str) -> Result<bool, PaymasterError>;
+
+    async fn projects(&self) -> Result<Vec<Project>, PaymasterError>;
+
+    /// The last block scanned for included operations, if any.
+    async fn scanned_block(&self) -> Result<Option<u64>, PaymasterError>;
+
+    /// Records that the blocks up to `block_number` were scanned. A block number below the one
+    /// already recorded is ignored.
+    async fn record_scanned_block(&self, block_number: u64) -> Result<(), PaymasterError>;
+}
+
+#[derive(Default)]
+struct MemoryState {
+    sponsorships: HashMap<H256, SponsorshipRecord>,
+    // History in the order it was recorded, oldest first
+    sponsorship_order: VecDeque<H256>,
+    // Only the latest day of each policy is kept
+    usage: HashMap<String, (u64, DailyUsage)>,
+    reservations: HashMap<(Address, U256), Reservation>,
+    replay_keys: HashMap<(String, String), u64>,
+    // Only the latest day of each policy and asset is kept
+    transfers: HashMap<(String, Address), (u64, U256)>,
+    // What each signed operation was counted for in `transfers`
+    operation_transfers: HashMap<(Address, U256), OperationTransfers>,
+    token_debts: HashMap<(Address, U256), TokenDebt>,
+    // Debts in the order they were first recorded, oldest first
+    token_debt_order: VecDeque<(Address, U256)>,
+    operation_statuses: HashMap<H256, OperationStatus>,
+    // Statuses in the order they were first recorded, oldest first
+    operation_status_order: VecDeque<H256>,
+    projects: HashMap<String, Project>,
+    scanned_block: Option<u64>,
+}
+
+#[derive(Clone)]
+struct OperationTransfers {
+    policy_id: String,
+    day: u64,
+    amounts: Vec<(Address, U256)>,
+}
+
+impl OperationTransfers {
+    // Amount of `asset` already counted for the operation against the policy on the day
+    fn counted(&self, policy_id: &str, day: u64, asset: Address) -> U256 {
+        self.amounts
+            .iter()
+            .filter(|(counted, _)| self.policy_id == policy_id && self.day == day && *counted == asset)
+            .map(|(_, amount)| *amount)
+            .fold(U256::zero(), U256::saturating_add)
+    }
+}
+
+impl MemoryState {
+    fn transferred(&self, policy_id: &str, day: u64, asset: Address) -> U256 {
+        self.transfers
+            .get(&(policy_id.to_string(), asset))
+            .filter(|(transfer_day, _)| *transfer_day == day)
+            .map(|(_, amount)| *amount)
+            .unwrap_or_default()
+    }
+
+    fn unreserve(&mut self, reservation: &Reservation, uncount: bool) {
+        if let Some((_, usage)) = self.usage.get_mut(&reservation.policy_id).filter(|(day, _)| *day == reservation.day) {
+            usage.unreserve(reservation.amount, uncount);
+        }
+    }
+}
+
+// Keeps everything in process memory; lost on restart
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+#[async_trait]
+impl SponsorshipStore for MemoryStore {
+    async fn record_sponsorship(&self, record: &SponsorshipRecord) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        if state.sponsorships.insert(record.user_op_hash, record.clone()).is_none() {
+            state.sponsorship_order.push_back(record.user_op_hash);
+        }
+        if state.sponsorship_order.len() > MEMORY_HISTORY {
+            if let Some(oldest) = state.sponsorship_order.pop_front() {
+                state.sponsorships.remove(&oldest);
+            }
+        }
+        Ok(())
+    }
+
+    async fn sponsorship(&self, user_op_hash: H256) -> Result<Option<SponsorshipRecord>, PaymasterError> {
+        Ok(self.state.lock().unwrap().sponsorships.get(&user_op_hash).cloned())
+    }
+
+    async fn usage(&self, policy_id: &str, day: u64) -> Result<DailyUsage, PaymasterError> {
+        Ok(self.state
+            .lock()
+            .unwrap()
+            .usage
+            .get(policy_id)
+            .filter(|(usage_day, _)| *usage_day == day)
+            .map(|(_, usage)| *usage)
+            .unwrap_or_default())
+    }
+
+    async fn reserve(
+        &self,
+        sender: Address,
+        nonce: U256,
+        reservation: Reservation,
+        check: &UsageCheck<'_>,
+    ) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+
+        let previous = state.reservations.remove(&(sender, nonce));
+        if let Some(previous) = &previous {
+            state.unreserve(previous, true);
+        }
+
+        let entry = state.usage.entry(reservation.policy_id.clone()).or_default();
+        if entry.0 != reservation.day {
+            *entry = (reservation.day, DailyUsage::default());
+        }
+
+        if let Err(e) = check(&entry.1) {
+            if let Some(previous) = previous {
+                if let Some((_, usage)) = state.usage.get_mut(&previous.policy_id).filter(|(day, _)| *day == previous.day) {
+                    usage.reserve(previous.amount);
+                }
+                state.reservations.insert((sender, nonce), previous);
+            }
+            return Err(e);
+        }
+
+        entry.1.reserve(reservation.amount);
+        state.reservations.insert((sender, nonce), reservation);
+        Ok(())
+    }
+
+    async fn release(&self, sender: Address, nonce: U256) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(reservation) = state.reservations.remove(&(sender, nonce)) {
+            state.unreserve(&reservation, true);
+        }
+        Ok(())
+    }
+
+    async fn settle(&self, sender: Address, nonce: U256, actual_cost: U256) -> Result<Option<String>, PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(reservation) = state.reservations.remove(&(sender, nonce)) else {
+            return Ok(None);
+        };
+
+        state.unreserve(&reservation, false);
+        if let Some((_, usage)) = state.usage.get_mut(&reservation.policy_id).filter(|(day, _)| *day == reservation.day) {
+            usage.spent = usage.spent.saturating_add(actual_cost);
+        }
+        Ok(Some(reservation.policy_id))
+    }
+
+    async fn release_expired(&self, block_timestamp: u64) -> Result<usize, PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+
+        let expired: Vec<_> = state.reservations
+            .iter()
+            .filter(|(_, reservation)| reservation.valid_until < block_timestamp)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &expired {
+            if let Some(reservation) = state.reservations.remove(key) {
+                state.unreserve(&reservation, true);
+            }
+        }
+        Ok(expired.len())
+    }
+
+    async fn insert_replay_key(&self, set: &str, key: &str, expires_at: u64) -> Result<bool, PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        let key = (set.to_string(), key.to_string());
+        if state.replay_keys.contains_key(&key) {
+            return Ok(false);
+        }
+        state.replay_keys.insert(key, expires_at);
+        Ok(true)
+    }
+
+    async fn contains_replay_key(&self, set: &str, key: &str) -> Result<bool, PaymasterError> {
+        Ok(self.state.lock().unwrap().replay_keys.contains_key(&(set.to_string(), key.to_string())))
+    }
+
+    async fn prune(&self, now: u64) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        state.replay_keys.retain(|_, expires_at| *expires_at >= now);
+        state.operation_transfers.retain(|_, operation| operation.day >= now / SECONDS_PER_DAY);
+        Ok(())
+    }
+
+    async fn transferred(&self, policy_id: &str, day: u64, asset: Address) -> Result<U256, PaymasterError> {
+        Ok(self.state.lock().unwrap().transferred(policy_id, day, asset))
+    }
+
+    async fn record_transfers(
+        &self,
+        sender: Address,
+        nonce: U256,
+        policy_id: &str,
+        day: u64,
+        transfers: &[Transfer],
+    ) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        let previous = state.operation_transfers.remove(&(sender, nonce));
+        let counted = |asset: Address| previous.as_ref().map(|previous| previous.counted(policy_id, day, asset)).unwrap_or_default();
+        for transfer in transfers {
+            let transferred = state.transferred(policy_id, day, transfer.asset).saturating_sub(counted(transfer.asset));
+            if let Err(e) = transfer.check(policy_id, transferred) {
+                if let Some(previous) = previous {
+                    state.operation_transfers.insert((sender, nonce), previous);
+                }
+                return Err(e);
+            }
+        }
+
+        if let Some(previous) = &previous {
+            for (asset, amount) in &previous.amounts {
+                if let Some((_, transferred)) = state.transfers.get_mut(&(previous.policy_id.clone(), *asset)).filter(|(day, _)| *day == previous.day) {
+                    *transferred = transferred.saturating_sub(*amount);
+                }
+            }
+        }
+        for transfer in transfers {
+            let transferred = state.transferred(policy_id, day, transfer.asset).saturating_add(transfer.amount);
+            state.transfers.insert((policy_id.to_string(), transfer.asset), (day, transferred));
+        }
+        state.operation_transfers.insert((sender, nonce), OperationTransfers {
+            policy_id: policy_id.to_string(),
+            day,
+            amounts: transfers.iter().map(|transfer| (transfer.asset, transfer.amount)).collect(),
+        });
+        Ok(())
+    }
+
+    async fn record_token_debt(&self, debt: &TokenDebt) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        let key = (debt.sender, debt.nonce);
+        if state.token_debts.insert(key, debt.clone()).is_none() {
+            state.token_debt_order.push_back(key);
+        }
+        if state.token_debt_order.len() > MEMORY_HISTORY {
+            if let Some(oldest) = state.token_debt_order.pop_front() {
+                state.token_debts.remove(&oldest);
+            }
+        }
+        Ok(())
+    }
+
+    async fn token_debt(&self, sender: Address, nonce: U256) -> Result<Option<TokenDebt>, PaymasterError> {
+        Ok(self.state.lock().unwrap().token_debts.get(&(sender, nonce)).cloned())
+    }
+
+    async fn token_debts(&self, since: u64) -> Result<Vec<TokenDebt>, PaymasterError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.token_debt_order
+            .iter()
+            .filter_map(|key| state.token_debts.get(key))
+            .filter(|debt| debt.created_at >= since)
+            .cloned()
+            .collect())
+    }
+
+    async fn record_operation_status(&self, status: &OperationStatus) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        if state.operation_statuses.insert(status.user_op_hash, status.clone()).is_none() {
+            state.operation_status_order.push_back(status.user_op_hash);
+        }
+        if state.operation_status_order.len() > MEMORY_HISTORY {
+            if let Some(oldest) = state.operation_status_order.pop_front() {
+                state.operation_statuses.remove(&oldest);
+            }
+        }
+        Ok(())
+    }
+
+    async fn operation_status(&self, user_op_hash: H256) -> Result<Option<OperationStatus>, PaymasterError> {
+        Ok(self.state.lock().unwrap().operation_statuses.get(&user_op_hash).cloned())
+    }
+
+    async fn pending_operations(&self, valid_from: u64) -> Result<Vec<OperationStatus>, PaymasterError> {
+        let state = self.state.lock().unwrap();
+        Ok(state.operation_status_order
+            .iter()
+            .filter_map(|user_op_hash| state.operation_statuses.get(user_op_hash))
+            .filter(|status| status.status == OperationState::Pending && status.valid_until >= valid_from)
+            .cloned()
+            .collect())
+    }
+
+    async fn save_project(&self, project: &Project) -> Result<(), PaymasterError> {
+        self.state.lock().unwrap().projects.insert(project.id.clone(), project.clone());
+        Ok(())
+    }
+
+    async fn delete_project(&self, project_id: &str) -> Result<bool, PaymasterError> {
+        Ok(self.state.lock().unwrap().projects.remove(project_id).is_some())
+    }
+
+    async fn projects(&self) -> Result<Vec<Project>, PaymasterError> {
+        Ok(self.state.lock().unwrap().projects.values().cloned().collect())
+    }
+
+    async fn scanned_block(&self) -> Result<Option<u64>, PaymasterError> {
+        Ok(self.state.lock().unwrap().scanned_block)
+    }
+
+    async fn record_scanned_block(&self, block_number: u64) -> Result<(), PaymasterError> {
+        let mut state = self.state.lock().unwrap();
+        state.scanned_block = state.scanned_block.max(Some(block_number));
+        Ok(())
+    }
+}
+
+// Keeps everything in the SQLite or Postgres database, shared by every instance serving the chain
+pub struct DatabaseStore {
+    database: Database,
+    chain_id: u64,
+    // Serializes this process's writes; SQLite would otherwise fail them as busy
+    writes: AsyncMutex<()>,
+}
+
+impl DatabaseStore {
+    pub fn new(database: Database, chain_id: u64) -> Self {
+        Self { database, chain_id, writes: AsyncMutex::new(()) }
+    }
+}
+
+fn storage_error(e: anyhow::Error) -> PaymasterError {
+    PaymasterError::StorageError(e.to_string())
+}
+
+#[async_trait]
+impl SponsorshipStore for DatabaseStore {
+    async fn record_sponsorship(&self, record: &SponsorshipRecord) -> Result<(), PaymasterError> {
+        self.database.upsert_sponsorship(record).await.map_err(storage_error)
+    }
+
+    async fn sponsorship(&self, user_op_hash: H256) -> Result<Option<SponsorshipRecord>, PaymasterError> {
+        self.database.sponsorship(user_op_hash).await.map_err(storage_error)
+    }
+
+    async fn usage(&self, policy_id: &str, day: u64) -> Result<DailyUsage, PaymasterError> {
+        self.database.policy_usage(self.chain_id, policy_id, day).await.map_err(storage_error)
+    }
+
+    async fn reserve(
+        &self,
+        sender: Address,
+        nonce: U256,
+        reservation: Reservation,
+        check: &UsageCheck<'_>,
+    ) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.reserve(self.chain_id, sender, nonce, &reservation, check).await.map_err(storage_error)?
+    }
+
+    async fn release(&self, sender: Address, nonce: U256) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.release_reservation(self.chain_id, sender, nonce).await.map_err(storage_error)
+    }
+
+    async fn settle(&self, sender: Address, nonce: U256, actual_cost: U256) -> Result<Option<String>, PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.settle_reservation(self.chain_id, sender, nonce, actual_cost).await.map_err(storage_error)
+    }
+
+    async fn release_expired(&self, block_timestamp: u64) -> Result<usize, PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.release_expired_reservations(self.chain_id, block_timestamp).await.map_err(storage_error)
+    }
+
+    async fn insert_replay_key(&self, set: &str, key: &str, expires_at: u64) -> Result<bool, PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.insert_replay_key(self.chain_id, set, key, expires_at).await.map_err(storage_error)
+    }
+
+    async fn contains_replay_key(&self, set: &str, key: &str) -> Result<bool, PaymasterError> {
+        self.database.contains_replay_key(self.chain_id, set, key).await.map_err(storage_error)
+    }
+
+    async fn prune(&self, now: u64) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.prune_replay_keys(self.chain_id, now).await.map_err(storage_error)?;
+        self.database.prune_operation_transfers(self.chain_id, now / SECONDS_PER_DAY).await.map_err(storage_error)
+    }
+
+    async fn transferred(&self, policy_id: &str, day: u64, asset: Address) -> Result<U256, PaymasterError> {
+        self.database.transferred(self.chain_id, policy_id, day, asset).await.map_err(storage_error)
+    }
+
+    async fn record_transfers(
+        &self,
+        sender: Address,
+        nonce: U256,
+        policy_id: &str,
+        day: u64,
+        transfers: &[Transfer],
+    ) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.record_transfers(self.chain_id, sender, nonce, policy_id, day, transfers).await.map_err(storage_error)?
+    }
+
+    async fn record_token_debt(&self, debt: &TokenDebt) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.upsert_token_debt(self.chain_id, debt).await.map_err(storage_error)
+    }
+
+    async fn token_debt(&self, sender: Address, nonce: U256) -> Result<Option<TokenDebt>, PaymasterError> {
+        self.database.token_debt(self.chain_id, sender, nonce).await.map_err(storage_error)
+    }
+
+    async fn token_debts(&self, since: u64) -> Result<Vec<TokenDebt>, PaymasterError> {
+        self.database.token_debts(self.chain_id, since).await.map_err(storage_error)
+    }
+
+    async fn record_operation_status(&self, status: &OperationStatus) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.upsert_operation_status(self.chain_id, status).await.map_err(storage_error)
+    }
+
+    async fn operation_status(&self, user_op_hash: H256) -> Result<Option<OperationStatus>, PaymasterError> {
+        self.database.operation_status(self.chain_id, user_op_hash).await.map_err(storage_error)
+    }
+
+    async fn pending_operations(&self, valid_from: u64) -> Result<Vec<OperationStatus>, PaymasterError> {
+        self.database.pending_operations(self.chain_id, valid_from).await.map_err(storage_error)
+    }
+
+    async fn save_project(&self, project: &Project) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.upsert_project(project).await.map_err(storage_error)
+    }
+
+    async fn delete_project(&self, project_id: &str) -> Result<bool, PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.delete_project(project_id).await.map_err(storage_error)
+    }
+
+    async fn projects(&self) -> Result<Vec<Project>, PaymasterError> {
+        self.database.projects().await.map_err(storage_error)
+    }
+
+    async fn scanned_block(&self) -> Result<Option<u64>, PaymasterError> {
+        self.database.scanned_block(self.chain_id).await.map_err(storage_error)
+    }
+
+    async fn record_scanned_block(&self, block_number: u64) -> Result<(), PaymasterError> {
+        let _write = self.writes.lock().await;
+        self.database.record_scanned_block(self.chain_id, block_number).await.map_err(storage_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debt(nonce: u64, created_at: u64) -> TokenDebt {
+        TokenDebt {
+            sender: Address::repeat_byte(0x11),
+            nonce: U256::from(nonce),
+            token: Address::repeat_byte(0xcd),
+            exchange_rate: U256::from(2_500_000_000u64),
+            max_token_cost: U256::from(10_000_000),
+            owed: None,
+            collected: None,
+            user_op_hash: None,
+            created_at,
+        }
+    }
+
+    #[test]
+    fn reports_the_shortfall_of_included_operations() {
+        let owed = |owed: u64, collected: Option<u64>| TokenDebt {
+            owed: Some(U256::from(owed)),
+            collected: collected.map(U256::from),
+            ..debt(0, 0)
+        };
+
+        assert_eq!(debt(0, 0).shortfall(), None);
+        assert_eq!(owed(100, None).shortfall(), Some(U256::from(100)));
+        assert_eq!(owed(100, Some(40)).shortfall(), Some(U256::from(60)));
+        assert_eq!(owed(100, Some(100)).shortfall(), None);
+        assert_eq!(owed(100, Some(150)).shortfall(), None);
+    }
+
+    // Re-signing an operation replaces its transfers, and a limit is checked without them
+    async fn replaces_transfers_of_re_signed_operations(store: &dyn SponsorshipStore) {
+        let sender = Address::repeat_byte(0x11);
+        let transfer = |amount: u64| Transfer { asset: Address::zero(), amount: U256::from(amount), daily_limit: U256::from(100) };
+
+        store.record_transfers(sender, U256::zero(), "launch", 1, &[transfer(60)]).await.unwrap();
+        store.record_transfers(sender, U256::zero(), "launch", 1, &[transfer(70)]).await.unwrap();
+        assert_eq!(store.transferred("launch", 1, Address::zero()).await.unwrap(), U256::from(70));
+
+        assert!(store.record_transfers(sender, U256::one(), "launch", 1, &[transfer(40)]).await.is_err());
+        store.record_transfers(sender, U256::zero(), "launch", 1, &[transfer(50)]).await.unwrap();
+        store.record_transfers(sender, U256::one(), "launch", 1, &[transfer(40)]).await.unwrap();
+        assert_eq!(store.transferred("launch", 1, Address::zero()).await.unwrap(), U256::from(90));
+
+        // A refused re-signing leaves the earlier count in place
+        assert!(store.record_transfers(sender, U256::one(), "launch", 1, &[transfer(60)]).await.is_err());
+        assert_eq!(store.transferred("launch", 1, Address::zero()).await.unwrap(), U256::from(90));
+    }
+
+    #[tokio::test]
+    async fn replaces_transfers_in_memory() {
+        replaces_transfers_of_re_signed_operations(&MemoryStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn replaces_transfers_in_the_database() {
+        let path = std::env::temp_dir().join(format!("arka-light-transfers-{}.db", std::process::id()));
+        let database = Database::connect(&format!("sqlite:{}", path.display())).await.unwrap();
+        database.migrate().await.unwrap();
+        replaces_transfers_of_re_signed_operations(&DatabaseStore::new(database.clone(), 1)).await;
+        database.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn keeps_token_debts_by_operation() {
+        let store = MemoryStore::default();
+        store.record_token_debt(&debt(0, 100)).await.unwrap();
+        store.record_token_debt(&debt(1, 200)).await.unwrap();
+
+        let settled = TokenDebt { owed: Some(U256::from(2_500_000)), user_op_hash: Some(H256::repeat_byte(1)), ..debt(0, 100) };
+        store.record_token_debt(&settled).await.unwrap();
+
+        let sender = Address::repeat_byte(0x11);
+        assert_eq!(store.token_debt(sender, U256::zero()).await.unwrap(), Some(settled.clone()));
+        assert_eq!(store.token_debt(sender, U256::from(2)).await.unwrap(), None);
+        assert_eq!(store.token_debts(0).await.unwrap(), vec![settled, debt(1, 200)]);
+        assert_eq!(store.token_debts(150).await.unwrap(), vec![debt(1, 200)]);
+    }
+
+    // Scanning resumes where it left off, and a scan behind the recorded block does not move it back
+    async fn keeps_the_last_scanned_block(store: &dyn SponsorshipStore) {
+        assert_eq!(store.scanned_block().await.unwrap(), None);
+        store.record_scanned_block(100).await.unwrap();
+        store.record_scanned_block(90).await.unwrap();
+        assert_eq!(store.scanned_block().await.unwrap(), Some(100));
+        store.record_scanned_block(120).await.unwrap();
+        assert_eq!(store.scanned_block().await.unwrap(), Some(120));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_last_scanned_block_in_memory() {
+        keeps_the_last_scanned_block(&MemoryStore::default()).await;
+    }
+
+    #[tokio::test]
+    async fn keeps_the_last_scanned_block_in_the_database() {
+        let path = std::env::temp_dir().join(format!("arka-light-scanned-{}.db", std::process::id()));
+        let database = Database::connect(&format!("sqlite:{}", path.display())).await.unwrap();
+        database.migrate().await.unwrap();
+        keeps_the_last_scanned_block(&DatabaseStore::new(database.clone(), 1)).await;
+        database.close().await;
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,206 @@
+// src/token.rs
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ethers::prelude::*;
+use ethers::utils::parse_units;
+
+use crate::cache::TtlCache;
+use crate::config::TokenConfig;
+use crate::contracts::{PriceFeed, ERC20};
+use crate::error::PaymasterError;
+use crate::provider::{self, EthProvider};
+use crate::types::TokenQuote;
+
+const DEFAULT_MAX_PRICE_AGE_SECS: u64 = 86_400;
+const PERCENT: u64 = 100;
+
+// Decimals of a token and of its price feed, which never change
+#[derive(Debug, Clone, Copy)]
+struct Decimals {
+    token: u8,
+    feed: u8,
+}
+
+// ERC-20 tokens accepted for gas payment and their pricing
+pub struct TokenRegistry {
+    tokens: HashMap<Address, (String, TokenConfig)>,
+    client: Arc<EthProvider>,
+    decimals: Mutex<HashMap<Address, Decimals>>,
+    // Latest feed answer and its update time, per token
+    prices: TtlCache<Address, (I256, U256)>,
+}
+
+impl TokenRegistry {
+    pub fn new(tokens: HashMap<String, TokenConfig>, client: Arc<EthProvider>, price_ttl: Duration) -> Self {
+        Self {
+            tokens: tokens
+                .into_iter()
+                .map(|(symbol, token)| (token.address, (symbol, token)))
+                .collect(),
+            client,
+            decimals: Mutex::new(HashMap::new()),
+            prices: TtlCache::new(price_ttl),
+        }
+    }
+
+    pub fn set_price_ttl(&mut self, ttl: Duration) {
+        self.prices = TtlCache::new(ttl);
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        let mut addresses: Vec<Address> = self.tokens.keys().copied().collect();
+        addresses.sort();
+        addresses
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    // Price an operation costing at most `max_cost` wei in `token`, checking the sender can pay
+    // and lets `paymaster` charge it
+    pub async fn quote(
+        &self,
+        token: Address,
+        sender: Address,
+        paymaster: Address,
+        max_cost: U256,
+        now: u64,
+    ) -> Result<TokenQuote, PaymasterError> {
+        let (symbol, config) = self.tokens
+            .get(&token)
+            .ok_or_else(|| PaymasterError::InvalidParameters(format!("Token {:?} is not accepted", token)))?;
+        let decimals = self.decimals(config).await?;
+
+        // Feed answers are the native currency paid per whole token
+        let (answer, updated_at) = self.prices.get_or_fetch(token, || async {
+            let (_, answer, _, updated_at, _) = PriceFeed::new(config.price_feed, self.client.clone())
+                .latest_round_data()
+                .call()
+                .await
+                .map_err(|e| provider::unavailable(&self.client)
+                    .unwrap_or_else(|| PaymasterError::PriceFeedError(format!("{} feed: {}", symbol, e))))?;
+            Ok((answer, updated_at))
+        }).await?;
+        if answer <= I256::zero() {
+            return Err(PaymasterError::PriceFeedError(format!("{} feed returned price {}", symbol, answer)));
+        }
+        let max_age = config.max_price_age_secs.unwrap_or(DEFAULT_MAX_PRICE_AGE_SECS);
+        if updated_at.low_u64().saturating_add(max_age) < now {
+            return Err(PaymasterError::PriceFeedError(format!(
+                "{} price was last updated at {}, more than {}s ago", symbol, updated_at, max_age
+            )));
+        }
+
+        // Token base units per 1 ETH, with the markup added
+        let scale = U256::exp10(decimals.token as usize + decimals.feed as usize);
+        let exchange_rate = scale
+            .saturating_mul(U256::from(PERCENT + config.markup_percent))
+            / answer.into_raw().saturating_mul(U256::from(PERCENT));
+        let max_token_cost = ceil_div(max_cost.saturating_mul(exchange_rate), U256::exp10(18));
+
+        let min_balance = match &config.min_balance {
+            Some(amount) => parse_units(amount, decimals.token as u32)
+                .map(U256::from)
+                .map_err(|e| PaymasterError::InvalidParameters(format!("Invalid {} min_balance: {}", symbol, e)))?,
+            None => U256::zero(),
+        };
+        let required = max_token_cost.max(min_balance);
+
+        let balance = ERC20::new(token, self.client.clone())
+            .balance_of(sender)
+            .call()
+            .await
+            .map_err(|e| provider::call_error(&self.client, e))?;
+        if balance < required {
+            return Err(PaymasterError::InvalidUserOperation(format!(
+                "Sender holds {} {} base units, needs at least {}", balance, symbol, required
+            )));
+        }
+
+        let quote = TokenQuote {
+            token,
+            exchange_rate,
+            max_token_cost,
+        };
+        self.check_allowance(&quote, sender, paymaster).await?;
+        Ok(quote)
+    }
+
+    // The paymaster charges the sender in postOp with transferFrom, which fails after the
+    // operation ran when the sender has not approved the quoted maximum
+    pub async fn check_allowance(&self, quote: &TokenQuote, sender: Address, paymaster: Address) -> Result<(), PaymasterError> {
+        let symbol = self.tokens.get(&quote.token).map(|(symbol, _)| symbol.as_str()).unwrap_or_default();
+        let allowance = ERC20::new(quote.token, self.client.clone())
+            .allowance(sender, paymaster)
+            .call()
+            .await
+            .map_err(|e| provider::call_error(&self.client, e))?;
+        match allowance >= quote.max_token_cost {
+            true => Ok(()),
+            false => Err(PaymasterError::InvalidUserOperation(format!(
+                "Sender allows paymaster {:?} {} {} base units, needs at least {}",
+                paymaster, allowance, symbol, quote.max_token_cost
+            ))),
+        }
+    }
+
+    async fn decimals(&self, config: &TokenConfig) -> Result<Decimals, PaymasterError> {
+        if let Some(decimals) = self.decimals.lock().unwrap().get(&config.address) {
+            return Ok(*decimals);
+        }
+
+        let token = match config.decimals {
+            Some(decimals) => decimals,
+            None => ERC20::new(config.address, self.client.clone())
+                .decimals()
+                .call()
+                .await
+                .map_err(|e| provider::call_error(&self.client, e))?,
+        };
+        let feed = PriceFeed::new(config.price_feed, self.client.clone())
+            .decimals()
+            .call()
+            .await
+            .map_err(|e| provider::unavailable(&self.client)
+                .unwrap_or_else(|| PaymasterError::PriceFeedError(e.to_string())))?;
+
+        let decimals = Decimals { token, feed };
+        self.decimals.lock().unwrap().insert(config.address, decimals);
+        Ok(decimals)
+    }
+}
+
+// Tokens an included operation owes for `actual_gas_cost` wei at the signed exchange rate, which
+// the paymaster contract caps at the quoted maximum
+pub fn token_cost(actual_gas_cost: U256, exchange_rate: U256, max_token_cost: U256) -> U256 {
+    ceil_div(actual_gas_cost.saturating_mul(exchange_rate), U256::exp10(18)).min(max_token_cost)
+}
+
+fn ceil_div(numerator: U256, denominator: U256) -> U256 {
+    let (quotient, remainder) = numerator.div_mod(denominator);
+    if remainder.is_zero() {
+        quotient
+    } else {
+        quotient + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charges_the_gas_cost_at_the_exchange_rate_up_to_the_quote() {
+        // 2500 tokens of 6 decimals per ETH
+        let exchange_rate = U256::from(2_500_000_000u64);
+        let max_token_cost = U256::from(10_000_000);
+
+        assert_eq!(token_cost(U256::exp10(15), exchange_rate, max_token_cost), U256::from(2_500_000));
+        assert_eq!(token_cost(U256::one(), exchange_rate, max_token_cost), U256::one());
+        assert_eq!(token_cost(U256::zero(), exchange_rate, max_token_cost), U256::zero());
+        assert_eq!(token_cost(U256::exp10(16), exchange_rate, max_token_cost), max_token_cost);
+    }
+}
@@ -1,37 +1,570 @@
 // src/types.rs
+use ethers::abi::{self, Token};
 use ethers::types::{Address, Bytes, H256, U256};
-use serde::{Deserialize, Serialize};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::user_op_hash::pack_gas_limits;
+
+// Type string hashed into every receipt digest, so a receipt signature can never double as a
+// paymaster signature
+const RECEIPT_TYPE: &str = "SponsorshipReceipt(uint256 chainId,address paymaster,bytes32 userOpHash,uint64 validUntil,uint64 validAfter,string policyId)";
+// Likewise for health attestations
+const ATTESTATION_TYPE: &str = "HealthAttestation(uint256 chainId,address paymaster,uint64 blockNumber,bytes32 blockHash,uint256 deposit,string version,uint64 timestamp,bytes32 challenge)";
+
+// Field names follow the ERC-4337 JSON-RPC spec (camelCase); the snake_case names are still accepted.
+// Quantities serialize as 0x-prefixed hex and may be sent as hex, decimal strings or JSON numbers.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UserOperation {
     pub sender: Address,
+    #[serde(deserialize_with = "deserialize_quantity")]
     pub nonce: U256,
+    #[serde(default, alias = "init_code")]
     pub init_code: Bytes,
+    #[serde(alias = "call_data")]
     pub call_data: Bytes,
+    #[serde(alias = "call_gas_limit", deserialize_with = "deserialize_quantity")]
     pub call_gas_limit: U256,
+    #[serde(alias = "verification_gas_limit", deserialize_with = "deserialize_quantity")]
     pub verification_gas_limit: U256,
+    #[serde(alias = "pre_verification_gas", deserialize_with = "deserialize_quantity")]
     pub pre_verification_gas: U256,
+    #[serde(alias = "max_fee_per_gas", deserialize_with = "deserialize_quantity")]
     pub max_fee_per_gas: U256,
+    #[serde(alias = "max_priority_fee_per_gas", deserialize_with = "deserialize_quantity")]
     pub max_priority_fee_per_gas: U256,
+    #[serde(default, alias = "paymaster_and_data")]
     pub paymaster_and_data: Bytes,
+    #[serde(default)]
     pub signature: Bytes,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PaymasterAndData {
     pub paymaster: Address,
+    // Packed after the paymaster address for EntryPoint v0.7 and later
+    pub gas_limits: Option<PaymasterGasLimits>,
     pub valid_until: u64,
     pub valid_after: u64,
+    pub token_quote: Option<TokenQuote>,
     pub signature: Bytes,
 }
 
+// Gas the EntryPoint grants the paymaster's validation and postOp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymasterGasLimits {
+    pub verification_gas_limit: u64,
+    pub post_op_gas_limit: u64,
+}
+
+impl PaymasterAndData {
+    // paymaster address (20 bytes), [verification and postOp gas limits, 16 bytes each, for v0.7
+    // and later], validUntil and validAfter as 32-byte words, [token address and exchange rate as
+    // 32-byte words in token mode], then the signature
+    pub fn encode(&self) -> Bytes {
+        let mut data = self.paymaster.as_bytes().to_vec();
+        if let Some(gas_limits) = self.gas_limits {
+            data.extend_from_slice(&pack_gas_limits(gas_limits));
+        }
+
+        let mut tokens = vec![Token::Uint(self.valid_until.into()), Token::Uint(self.valid_after.into())];
+        if let Some(quote) = &self.token_quote {
+            tokens.extend([Token::Address(quote.token), Token::Uint(quote.exchange_rate)]);
+        }
+        data.extend(abi::encode(&tokens));
+
+        data.extend_from_slice(&self.signature);
+        Bytes::from(data)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PaymasterResponse {
     pub paymaster_and_data: Bytes,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub receipt: Option<SponsorshipReceipt>,
+    // Terms of the token payment, when the sender pays for gas in an ERC-20 token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_quote: Option<TokenQuote>,
+    // Gas granted to the paymaster, for EntryPoint v0.7 and later clients that set these fields
+    // apart from paymasterAndData
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_verification_gas_limit: Option<U256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paymaster_post_op_gas_limit: Option<U256>,
+    // Signed with a throwaway key by a server in dry-run mode, so the paymaster will not accept it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenQuote {
+    pub token: Address,
+    // Token base units charged per 1 ETH (10^18 wei) of gas, markup included
+    pub exchange_rate: U256,
+    // Most the sender can be charged for the operation, in token base units
+    pub max_token_cost: U256,
+}
+
+// Signed record of the terms an operation was sponsored under, so a dapp can later prove
+// the sponsorship was granted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorshipReceipt {
+    pub chain_id: u64,
+    pub paymaster: Address,
+    // Hash covered by the paymaster signature in paymasterAndData
+    pub user_op_hash: H256,
+    pub valid_until: u64,
+    pub valid_after: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+    pub signer: Address,
+    // EIP-191 signature over `digest()`
+    pub signature: Bytes,
+}
+
+impl SponsorshipReceipt {
+    // keccak256(abi.encode(keccak256(RECEIPT_TYPE), chainId, paymaster, userOpHash, validUntil,
+    // validAfter, keccak256(policyId))), where a missing policy ID hashes as the empty string
+    pub fn digest(&self) -> H256 {
+        let encoded = abi::encode(&[
+            Token::FixedBytes(keccak256(RECEIPT_TYPE).to_vec()),
+            Token::Uint(self.chain_id.into()),
+            Token::Address(self.paymaster),
+            Token::FixedBytes(self.user_op_hash.as_bytes().to_vec()),
+            Token::Uint(self.valid_until.into()),
+            Token::Uint(self.valid_after.into()),
+            Token::FixedBytes(keccak256(self.policy_id.as_deref().unwrap_or_default()).to_vec()),
+        ]);
+        H256::from(keccak256(encoded))
+    }
+}
+
+// Signed statement that the paymaster was live, saw a recent block and held a deposit, for
+// third-party operators to prove uptime to their customers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthAttestation {
+    pub chain_id: u64,
+    pub paymaster: Address,
+    // Latest block the provider returned
+    pub block_number: u64,
+    pub block_hash: H256,
+    // The paymaster's deposit in the EntryPoint, in wei
+    pub deposit: U256,
+    pub version: String,
+    pub timestamp: u64,
+    // Value chosen by the verifier to prove the attestation is fresh; zero when none was given
+    pub challenge: H256,
+    pub signer: Address,
+    // EIP-191 signature over `digest()`
+    pub signature: Bytes,
+}
+
+impl HealthAttestation {
+    // keccak256(abi.encode(keccak256(ATTESTATION_TYPE), chainId, paymaster, blockNumber, blockHash,
+    // deposit, keccak256(version), timestamp, challenge))
+    pub fn digest(&self) -> H256 {
+        let encoded = abi::encode(&[
+            Token::FixedBytes(keccak256(ATTESTATION_TYPE).to_vec()),
+            Token::Uint(self.chain_id.into()),
+            Token::Address(self.paymaster),
+            Token::Uint(self.block_number.into()),
+            Token::FixedBytes(self.block_hash.as_bytes().to_vec()),
+            Token::Uint(self.deposit),
+            Token::FixedBytes(keccak256(&self.version).to_vec()),
+            Token::Uint(self.timestamp.into()),
+            Token::FixedBytes(self.challenge.as_bytes().to_vec()),
+        ]);
+        H256::from(keccak256(encoded))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationResult {
     pub valid: bool,
     pub reason: Option<String>,
-}
\ No newline at end of file
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignerStatus {
+    pub address: Address,
+    pub kind: String,
+    pub primary: bool,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthResponse {
+    pub healthy: bool,
+    pub signers: Vec<SignerStatus>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    // As of the last deposit reading
+    #[serde(default)]
+    pub tier: OperatingTier,
+    // Only allowlisted API keys and senders are served
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub maintenance: bool,
+}
+
+// What the paymaster still sponsors, narrowed as the deposit falls through the configured levels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OperatingTier {
+    #[default]
+    Normal,
+    // Only operations under priority policies
+    PriorityOnly,
+    // Only new signatures for operations already signed
+    RenewalOnly,
+    Paused,
+}
+
+// How the paymaster covers gas: `verifying` sponsors outright after signing, `token` has the
+// sender pay in an accepted ERC-20 token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaymasterMode {
+    Verifying,
+    Token,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryPointInfo {
+    pub address: Address,
+    pub version: String,
+}
+
+// What a deployment supports, so SDKs can configure themselves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub chain_ids: Vec<u64>,
+    pub entry_points: Vec<EntryPointInfo>,
+    pub paymaster: Address,
+    pub modes: Vec<PaymasterMode>,
+    // ERC-20 tokens accepted for gas payment
+    pub tokens: Vec<Address>,
+    // arka-light release serving the requests
+    pub server_version: String,
+}
+
+// Sponsorship context sent by dapps alongside the user operation (ERC-7677 style)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorshipContext {
+    pub sponsorship_policy_id: Option<String>,
+    pub api_key: Option<String>,
+    // ERC-20 token the sender pays gas in; absent for sponsored operations
+    pub token: Option<Address>,
+    // Session grant the operation is sponsored under, in place of a policy ID or API key
+    pub session_token: Option<String>,
+    // Milliseconds the client is willing to wait for the response
+    pub timeout_ms: Option<u64>,
+    // Domain of the dapp the request comes from, which its spend is attributed to
+    pub origin: Option<String>,
+    // Signature by the origin's key, proving the origin when no API key bound to it is sent
+    pub origin_signature: Option<Bytes>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorshipQuota {
+    pub policy_id: String,
+    // Whether the policy can sponsor another operation right now
+    pub available: bool,
+    // Budget left for the current UTC day in wei; absent when unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining_budget: Option<U256>,
+    // Operations left for the current UTC day; absent when unlimited
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining_ops_today: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    // When the daily budget and operation count reset
+    pub resets_at: u64,
+}
+
+// Terms an operation would be sponsored on, from `pm_validateUserOperation`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SponsorshipCheck {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub policy_id: Option<String>,
+    pub max_cost: U256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_quote: Option<TokenQuote>,
+}
+
+// Operator overview shown on the dashboard
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DashboardSummary {
+    pub chain_id: u64,
+    pub tier: OperatingTier,
+    pub deposits: Vec<DepositStatus>,
+    pub signers: Vec<SignerStatus>,
+    pub policies: Vec<PolicyUsage>,
+    // Rejections among the recent decisions, grouped by kind
+    pub rejections: Vec<RejectionCount>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositStatus {
+    pub paymaster: Address,
+    // EntryPoint deposit in wei
+    pub deposit: U256,
+    // Max cost of signed operations not yet included; only tracked with several paymasters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_flight: Option<U256>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyUsage {
+    pub policy_id: String,
+    // Spent and reserved today, in wei
+    pub spent_today: U256,
+    pub ops_today: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub daily_budget: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ops_per_day: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectionCount {
+    pub reason: String,
+    pub count: u64,
+}
+
+// Parse a quantity given as 0x-prefixed hex or as a decimal string
+pub fn parse_quantity(value: &str) -> Result<U256, String> {
+    let value = value.trim();
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some("") => Ok(U256::zero()),
+        Some(hex) => U256::from_str_radix(hex, 16).map_err(|e| e.to_string()),
+        None => U256::from_dec_str(value).map_err(|e| e.to_string()),
+    };
+
+    parsed.map_err(|e| format!("invalid quantity {:?}: {}", value, e))
+}
+
+fn deserialize_quantity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U256, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Quantity {
+        String(String),
+        Number(u64),
+    }
+
+    match Quantity::deserialize(deserializer)? {
+        Quantity::String(value) => parse_quantity(&value).map_err(serde::de::Error::custom),
+        Quantity::Number(value) => Ok(U256::from(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::self_test;
+    use serde_json::json;
+
+    fn spec_user_op() -> serde_json::Value {
+        json!({
+            "sender": "0x1306b01bc3e4ad202612d3843387e94737673f53",
+            "nonce": "0x1a",
+            "initCode": "0x",
+            "callData": "0xb61d27f6",
+            "callGasLimit": "0x5208",
+            "verificationGasLimit": "0x186a0",
+            "preVerificationGas": "0xb5fc",
+            "maxFeePerGas": "0x59682f10",
+            "maxPriorityFeePerGas": "0x59682f00",
+            "paymasterAndData": "0x",
+            "signature": "0xdeadbeef"
+        })
+    }
+
+    #[test]
+    fn deserializes_spec_camel_case_hex() {
+        let user_op: UserOperation = serde_json::from_value(spec_user_op()).unwrap();
+
+        assert_eq!(user_op.nonce, U256::from(0x1a));
+        assert_eq!(user_op.call_gas_limit, U256::from(21_000));
+        assert_eq!(user_op.verification_gas_limit, U256::from(100_000));
+        assert_eq!(user_op.max_fee_per_gas, U256::from(1_500_000_016u64));
+        assert_eq!(user_op.call_data.as_ref(), &[0xb6, 0x1d, 0x27, 0xf6]);
+        assert!(user_op.init_code.is_empty());
+    }
+
+    #[test]
+    fn round_trips_through_camel_case_hex() {
+        let user_op: UserOperation = serde_json::from_value(spec_user_op()).unwrap();
+        let serialized = serde_json::to_value(&user_op).unwrap();
+
+        assert_eq!(serialized, spec_user_op());
+    }
+
+    #[test]
+    fn accepts_decimal_numeric_and_snake_case_input() {
+        let user_op: UserOperation = serde_json::from_value(json!({
+            "sender": "0x1306b01bc3e4ad202612d3843387e94737673f53",
+            "nonce": 26,
+            "call_data": "b61d27f6",
+            "call_gas_limit": "21000",
+            "verificationGasLimit": 100000,
+            "preVerificationGas": "0XB5FC",
+            "maxFeePerGas": "1500000016",
+            "maxPriorityFeePerGas": "0x59682f00"
+        }))
+        .unwrap();
+
+        assert_eq!(user_op.nonce, U256::from(26));
+        assert_eq!(user_op.call_gas_limit, U256::from(21_000));
+        assert_eq!(user_op.pre_verification_gas, U256::from(0xb5fc));
+        assert_eq!(user_op.max_fee_per_gas, U256::from(1_500_000_016u64));
+        assert!(user_op.paymaster_and_data.is_empty());
+        assert!(user_op.signature.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_quantities() {
+        let mut value = spec_user_op();
+        value["callGasLimit"] = json!("0xzz");
+        assert!(serde_json::from_value::<UserOperation>(value).is_err());
+
+        let mut value = spec_user_op();
+        value["nonce"] = json!("-1");
+        assert!(serde_json::from_value::<UserOperation>(value).is_err());
+    }
+
+    #[test]
+    fn receipt_digest_binds_every_term() {
+        let receipt = SponsorshipReceipt {
+            chain_id: 1,
+            paymaster: Address::repeat_byte(0x11),
+            user_op_hash: H256::repeat_byte(0x22),
+            valid_until: 1_700_003_600,
+            valid_after: 1_700_000_000,
+            policy_id: Some("launch".to_string()),
+            signer: Address::repeat_byte(0x33),
+            signature: Bytes::default(),
+        };
+        let digest = receipt.digest();
+
+        // The signer and signature are not part of the signed terms
+        let signed = SponsorshipReceipt { signature: Bytes::from(vec![1; 65]), ..receipt.clone() };
+        assert_eq!(signed.digest(), digest);
+
+        assert_ne!(SponsorshipReceipt { chain_id: 10, ..receipt.clone() }.digest(), digest);
+        assert_ne!(SponsorshipReceipt { valid_until: 1_700_003_601, ..receipt.clone() }.digest(), digest);
+        assert_ne!(SponsorshipReceipt { policy_id: None, ..receipt.clone() }.digest(), digest);
+    }
+
+    #[test]
+    fn parses_quantity_forms() {
+        assert_eq!(parse_quantity("0x").unwrap(), U256::zero());
+        assert_eq!(parse_quantity(" 0xff ").unwrap(), U256::from(255));
+        assert_eq!(parse_quantity("255").unwrap(), U256::from(255));
+        assert!(parse_quantity("ff").is_err());
+    }
+
+    #[test]
+    fn pins_the_attestation_digest() {
+        let attestation = |challenge: H256| HealthAttestation {
+            chain_id: 1,
+            paymaster: Address::repeat_byte(0xab),
+            block_number: 19_000_000,
+            block_hash: H256::repeat_byte(0x11),
+            deposit: U256::exp10(18),
+            version: "0.1.0".to_string(),
+            timestamp: 1_700_000_000,
+            challenge,
+            signer: Address::zero(),
+            signature: Bytes::default(),
+        };
+
+        assert_eq!(
+            attestation(H256::repeat_byte(0x22)).digest(),
+            "0x9b5e12ae214b4a336a53059bf4cfac31a949ac3ff1832c528a9ff35342c5dda2".parse::<H256>().unwrap()
+        );
+        assert_eq!(
+            attestation(H256::zero()).digest(),
+            "0xc3f6491952bfbefe0e2b21ce209ffaf5cc7ec39165e777895476091a0ef40a26".parse::<H256>().unwrap()
+        );
+    }
+
+    fn sponsorship_terms(gas_limits: Option<PaymasterGasLimits>) -> PaymasterAndData {
+        PaymasterAndData {
+            paymaster: Address::repeat_byte(0xab),
+            gas_limits,
+            valid_until: 1_700_003_600,
+            valid_after: 1_700_000_000,
+            token_quote: None,
+            signature: Bytes::from(vec![0x11; 65]),
+        }
+    }
+
+    fn word(value: u64) -> Vec<u8> {
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&value.to_be_bytes());
+        word.to_vec()
+    }
+
+    // The offsets VerifyingPaymasterV07 reads: gas limits at 20, timestamps at 52, signature at 116
+    #[test]
+    fn encodes_v07_paymaster_data_with_gas_limits() {
+        let gas_limits = PaymasterGasLimits { verification_gas_limit: 60_000, post_op_gas_limit: 40_000 };
+        let terms = sponsorship_terms(Some(gas_limits));
+        let encoded = terms.encode();
+
+        assert_eq!(encoded.len(), 116 + 65);
+        assert_eq!(&encoded[..20], Address::repeat_byte(0xab).as_bytes());
+        assert_eq!(u128::from_be_bytes(encoded[20..36].try_into().unwrap()), 60_000);
+        assert_eq!(u128::from_be_bytes(encoded[36..52].try_into().unwrap()), 40_000);
+        assert_eq!(&encoded[52..84], word(1_700_003_600).as_slice());
+        assert_eq!(&encoded[84..116], word(1_700_000_000).as_slice());
+        assert_eq!(&encoded[116..], [0x11; 65].as_slice());
+        assert_eq!(self_test::decode_paymaster_data(&encoded, true).unwrap(), terms);
+    }
+
+    // The offsets VerifyingPaymaster reads: timestamps at 20, signature at 84
+    #[test]
+    fn encodes_v06_paymaster_data_without_gas_limits() {
+        let terms = sponsorship_terms(None);
+        let encoded = terms.encode();
+
+        assert_eq!(encoded.len(), 84 + 65);
+        assert_eq!(&encoded[20..52], word(1_700_003_600).as_slice());
+        assert_eq!(&encoded[52..84], word(1_700_000_000).as_slice());
+        assert_eq!(self_test::decode_paymaster_data(&encoded, false).unwrap(), terms);
+    }
+
+    #[test]
+    fn encodes_the_token_quote_after_the_validity_window() {
+        let gas_limits = PaymasterGasLimits { verification_gas_limit: 60_000, post_op_gas_limit: 40_000 };
+        let token_quote = TokenQuote {
+            token: Address::repeat_byte(0xcd),
+            exchange_rate: U256::from(2_500_000_000u64),
+            max_token_cost: U256::from(1),
+        };
+        let terms = PaymasterAndData { token_quote: Some(token_quote), ..sponsorship_terms(Some(gas_limits)) };
+        let encoded = terms.encode();
+
+        assert_eq!(encoded.len(), 180 + 65);
+        assert_eq!(&encoded[128..148], Address::repeat_byte(0xcd).as_bytes());
+        assert_eq!(&encoded[148..180], word(2_500_000_000).as_slice());
+        assert_eq!(&encoded[180..], [0x11; 65].as_slice());
+    }
+}
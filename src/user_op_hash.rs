@@ -0,0 +1,306 @@
+// src/user_op_hash.rs
+use ethers::abi::{self, Token};
+use ethers::types::{Address, H256, U256};
+use ethers::utils::keccak256;
+
+use crate::contracts::EntryPointVersion;
+use crate::types::{PaymasterAndData, PaymasterGasLimits, UserOperation};
+
+// EIP-712 types of the v0.8 EntryPoint, whose userOpHash is a typed-data hash
+const PACKED_USER_OPERATION_TYPE: &str = "PackedUserOperation(address sender,uint256 nonce,bytes initCode,bytes callData,bytes32 accountGasLimits,uint256 preVerificationGas,bytes32 gasFees,bytes paymasterAndData)";
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+const DOMAIN_NAME: &str = "ERC4337";
+const DOMAIN_VERSION: &str = "1";
+
+// The hash the EntryPoint at `entry_point` computes for `user_op`, as passed to the account and
+// paymaster and emitted in UserOperationEvent
+pub fn hash(user_op: &UserOperation, version: EntryPointVersion, entry_point: Address, chain_id: u64) -> H256 {
+    match version {
+        // keccak256(abi.encode(keccak256(pack(userOp)), entryPoint, chainId))
+        EntryPointVersion::V06 | EntryPointVersion::V07 => {
+            let packed = match version {
+                EntryPointVersion::V06 => pack_v06(user_op),
+                _ => pack_v07(user_op, None),
+            };
+            H256::from(keccak256(abi::encode(&[
+                Token::FixedBytes(keccak256(packed).to_vec()),
+                Token::Address(entry_point),
+                Token::Uint(chain_id.into()),
+            ])))
+        }
+        // keccak256("\x19\x01" ++ domainSeparator ++ keccak256(abi.encode(typeHash, ...pack(userOp))))
+        EntryPointVersion::V08 => {
+            let domain_separator = keccak256(abi::encode(&[
+                Token::FixedBytes(keccak256(DOMAIN_TYPE).to_vec()),
+                Token::FixedBytes(keccak256(DOMAIN_NAME).to_vec()),
+                Token::FixedBytes(keccak256(DOMAIN_VERSION).to_vec()),
+                Token::Uint(chain_id.into()),
+                Token::Address(entry_point),
+            ]));
+            let struct_hash = keccak256(pack_v07(user_op, Some(keccak256(PACKED_USER_OPERATION_TYPE))));
+
+            let mut digest = Vec::with_capacity(66);
+            digest.extend_from_slice(b"\x19\x01");
+            digest.extend_from_slice(&domain_separator);
+            digest.extend_from_slice(&struct_hash);
+            H256::from(keccak256(digest))
+        }
+    }
+}
+
+// Hash of the terms a paymaster signs, as getHash in the bundled VerifyingPaymaster (v0.6) and
+// VerifyingPaymasterV07 (v0.7 and later) computes it:
+// the operation without its paymasterAndData, which is to carry the signature, then chainId,
+// paymaster, validUntil and validAfter. v0.7 and later hash the packed gas fields along with the
+// paymaster's gas limits, and token mode appends the token and exchange rate.
+pub fn paymaster_hash(user_op: &UserOperation, version: EntryPointVersion, chain_id: u64, terms: &PaymasterAndData) -> H256 {
+    let mut tokens = vec![
+        Token::Address(user_op.sender),
+        Token::Uint(user_op.nonce),
+        Token::FixedBytes(keccak256(&user_op.init_code).to_vec()),
+        Token::FixedBytes(keccak256(&user_op.call_data).to_vec()),
+    ];
+    match version {
+        EntryPointVersion::V06 => tokens.extend([
+            Token::Uint(user_op.call_gas_limit),
+            Token::Uint(user_op.verification_gas_limit),
+            Token::Uint(user_op.pre_verification_gas),
+            Token::Uint(user_op.max_fee_per_gas),
+            Token::Uint(user_op.max_priority_fee_per_gas),
+        ]),
+        EntryPointVersion::V07 | EntryPointVersion::V08 => tokens.extend([
+            Token::FixedBytes(pack_uints(user_op.verification_gas_limit, user_op.call_gas_limit).to_vec()),
+            Token::FixedBytes(terms.gas_limits.map(pack_gas_limits).unwrap_or_default().to_vec()),
+            Token::Uint(user_op.pre_verification_gas),
+            Token::FixedBytes(pack_uints(user_op.max_priority_fee_per_gas, user_op.max_fee_per_gas).to_vec()),
+        ]),
+    }
+    tokens.extend([
+        Token::Uint(chain_id.into()),
+        Token::Address(terms.paymaster),
+        Token::Uint(terms.valid_until.into()),
+        Token::Uint(terms.valid_after.into()),
+    ]);
+    if let Some(quote) = &terms.token_quote {
+        tokens.extend([Token::Address(quote.token), Token::Uint(quote.exchange_rate)]);
+    }
+    H256::from(keccak256(abi::encode(&tokens)))
+}
+
+// UserOperation fields with the dynamic ones hashed, everything else 32-byte words
+fn pack_v06(user_op: &UserOperation) -> Vec<u8> {
+    abi::encode(&[
+        Token::Address(user_op.sender),
+        Token::Uint(user_op.nonce),
+        Token::FixedBytes(keccak256(&user_op.init_code).to_vec()),
+        Token::FixedBytes(keccak256(&user_op.call_data).to_vec()),
+        Token::Uint(user_op.call_gas_limit),
+        Token::Uint(user_op.verification_gas_limit),
+        Token::Uint(user_op.pre_verification_gas),
+        Token::Uint(user_op.max_fee_per_gas),
+        Token::Uint(user_op.max_priority_fee_per_gas),
+        Token::FixedBytes(keccak256(&user_op.paymaster_and_data).to_vec()),
+    ])
+}
+
+// PackedUserOperation fields, where gas limits and fees are packed as two uint128 per word.
+// v0.8 prefixes the EIP-712 type hash. EIP-7702 initCode markers are hashed as sent, not
+// resolved to the sender's delegate.
+fn pack_v07(user_op: &UserOperation, type_hash: Option<[u8; 32]>) -> Vec<u8> {
+    let mut tokens: Vec<Token> = type_hash.map(|type_hash| Token::FixedBytes(type_hash.to_vec())).into_iter().collect();
+    tokens.extend([
+        Token::Address(user_op.sender),
+        Token::Uint(user_op.nonce),
+        Token::FixedBytes(keccak256(&user_op.init_code).to_vec()),
+        Token::FixedBytes(keccak256(&user_op.call_data).to_vec()),
+        Token::FixedBytes(pack_uints(user_op.verification_gas_limit, user_op.call_gas_limit).to_vec()),
+        Token::Uint(user_op.pre_verification_gas),
+        Token::FixedBytes(pack_uints(user_op.max_priority_fee_per_gas, user_op.max_fee_per_gas).to_vec()),
+        Token::FixedBytes(keccak256(&user_op.paymaster_and_data).to_vec()),
+    ]);
+    abi::encode(&tokens)
+}
+
+// Paymaster verification and postOp gas limits as two big-endian uint128, the EntryPoint's layout
+pub fn pack_gas_limits(gas_limits: PaymasterGasLimits) -> [u8; 32] {
+    pack_uints(gas_limits.verification_gas_limit.into(), gas_limits.post_op_gas_limit.into())
+}
+
+// `high` in the upper 16 bytes and `low` in the lower 16, as the EntryPoint packs them
+pub fn pack_uints(high: U256, low: U256) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    (high << 128 | low & U256::from(u128::MAX)).to_big_endian(&mut word);
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contracts::{ENTRY_POINT_V06, ENTRY_POINT_V07, ENTRY_POINT_V08};
+    use crate::types::TokenQuote;
+    use ethers::types::Bytes;
+    use serde_json::json;
+
+    // Expected hashes were computed independently of this module, with a separate Keccak-256 and
+    // hand-written ABI encoding following the EntryPoint sources
+    fn spec_user_op() -> UserOperation {
+        serde_json::from_value(json!({
+            "sender": "0x1306b01bc3e4ad202612d3843387e94737673f53",
+            "nonce": "0x1a",
+            "initCode": "0x",
+            "callData": "0xb61d27f6",
+            "callGasLimit": "0x5208",
+            "verificationGasLimit": "0x186a0",
+            "preVerificationGas": "0xb5fc",
+            "maxFeePerGas": "0x59682f10",
+            "maxPriorityFeePerGas": "0x59682f00",
+            "paymasterAndData": "0x",
+            "signature": "0xdeadbeef"
+        }))
+        .unwrap()
+    }
+
+    // Counterfactual deployment with a keyed nonce and paymaster data in place
+    fn deploy_user_op() -> UserOperation {
+        UserOperation {
+            nonce: U256::from(7) << 64 | U256::from(3),
+            init_code: format!("0x9406cc6185a346906296840746125a0e449764545fbfb9cf{}", "00".repeat(64)).parse().unwrap(),
+            paymaster_and_data: format!("0x{}{}{}", "ab".repeat(20), "00".repeat(64), "11".repeat(65)).parse().unwrap(),
+            ..spec_user_op()
+        }
+    }
+
+    fn hash_at(user_op: &UserOperation, version: EntryPointVersion, entry_point: &str, chain_id: u64) -> String {
+        format!("{:?}", hash(user_op, version, entry_point.parse().unwrap(), chain_id))
+    }
+
+    #[test]
+    fn hashes_v06_operations() {
+        assert_eq!(
+            hash_at(&spec_user_op(), EntryPointVersion::V06, ENTRY_POINT_V06, 1),
+            "0x1813175f4c1edc996fa80c242dbf946bac427140ae783f0d02850e05321c8a87",
+        );
+        assert_eq!(
+            hash_at(&spec_user_op(), EntryPointVersion::V06, ENTRY_POINT_V06, 137),
+            "0x5e5f9b28dc025b933262b55440ea9b68605935b59afd9c0fb4685e9c269a71b2",
+        );
+        assert_eq!(
+            hash_at(&deploy_user_op(), EntryPointVersion::V06, ENTRY_POINT_V06, 1),
+            "0x12a15a74024f091f9e8085e16957784e14666a3b8b364708535015f519a942cf",
+        );
+    }
+
+    #[test]
+    fn hashes_v07_operations() {
+        assert_eq!(
+            hash_at(&spec_user_op(), EntryPointVersion::V07, ENTRY_POINT_V07, 1),
+            "0xcefa32b9239d17895f4b9ee5a683745ad4fc3b4d6a1cf5eb4e22827bb5e06a0b",
+        );
+        assert_eq!(
+            hash_at(&spec_user_op(), EntryPointVersion::V07, ENTRY_POINT_V07, 137),
+            "0x13b1c1b3ff9f66a875d2a8ae7969b2dcc74f0c39deeb4cc664679d31d30de28a",
+        );
+        assert_eq!(
+            hash_at(&deploy_user_op(), EntryPointVersion::V07, ENTRY_POINT_V07, 1),
+            "0x52eae4d7b0491751f75d31b3ffefb957b305f602e11ccdbdb46a2364ff7f253f",
+        );
+    }
+
+    #[test]
+    fn hashes_v08_operations_as_typed_data() {
+        assert_eq!(
+            hash_at(&spec_user_op(), EntryPointVersion::V08, ENTRY_POINT_V08, 1),
+            "0xb60b1aecfaec124c7772db17c607480245c17231eee911abe8d32769427a48d5",
+        );
+        assert_eq!(
+            hash_at(&spec_user_op(), EntryPointVersion::V08, ENTRY_POINT_V08, 137),
+            "0xd9837d4f56b5bbf0d8c12728038d4cdf7776ae9f53d7fe2cfb1b79976f312f49",
+        );
+        assert_eq!(
+            hash_at(&deploy_user_op(), EntryPointVersion::V08, ENTRY_POINT_V08, 1),
+            "0x6a4a34b90b5704fa44486860dec559f4f1ff2bf202223801f4960725f1906830",
+        );
+    }
+
+    #[test]
+    fn ignores_the_signature() {
+        let signed = UserOperation { signature: "0x01".parse().unwrap(), ..spec_user_op() };
+
+        for (version, entry_point) in [
+            (EntryPointVersion::V06, ENTRY_POINT_V06),
+            (EntryPointVersion::V07, ENTRY_POINT_V07),
+            (EntryPointVersion::V08, ENTRY_POINT_V08),
+        ] {
+            assert_eq!(hash_at(&signed, version, entry_point, 1), hash_at(&spec_user_op(), version, entry_point, 1));
+        }
+    }
+
+    #[test]
+    fn detects_canonical_entry_points() {
+        assert_eq!(EntryPointVersion::of(ENTRY_POINT_V07.parse().unwrap()), Some(EntryPointVersion::V07));
+        assert_eq!(EntryPointVersion::of(Address::repeat_byte(1)), None);
+        assert_eq!("v0.8".parse::<EntryPointVersion>(), Ok(EntryPointVersion::V08));
+    }
+
+    fn sponsorship_terms() -> PaymasterAndData {
+        PaymasterAndData {
+            paymaster: Address::repeat_byte(0xab),
+            gas_limits: None,
+            valid_until: 1_700_003_600,
+            valid_after: 1_700_000_000,
+            token_quote: None,
+            signature: Bytes::default(),
+        }
+    }
+
+    fn paymaster_hash_of(user_op: &UserOperation, version: EntryPointVersion, chain_id: u64, terms: &PaymasterAndData) -> String {
+        format!("{:?}", paymaster_hash(user_op, version, chain_id, terms))
+    }
+
+    // Expected hashes follow the abi.encode in VerifyingPaymaster.getHash, and in
+    // VerifyingPaymasterV07.getHash for v0.7
+    #[test]
+    fn hashes_paymaster_terms_as_the_contract_does() {
+        assert_eq!(
+            paymaster_hash_of(&spec_user_op(), EntryPointVersion::V06, 1, &sponsorship_terms()),
+            "0x75573f763a7a0b9f62e47975904966b141f76e5ed8b3fadbd521911d38a19620",
+        );
+        assert_eq!(
+            paymaster_hash_of(&spec_user_op(), EntryPointVersion::V06, 137, &sponsorship_terms()),
+            "0x645e345c5840a3b317530f6b0b4b04025e63e999f1d206e9427543476d5c0bff",
+        );
+
+        let gas_limits = PaymasterGasLimits { verification_gas_limit: 60_000, post_op_gas_limit: 40_000 };
+        let terms = PaymasterAndData { gas_limits: Some(gas_limits), ..sponsorship_terms() };
+        assert_eq!(
+            paymaster_hash_of(&spec_user_op(), EntryPointVersion::V07, 1, &terms),
+            "0xba447ae954766a1c2d31df8c3df46210b3f4a45c449fb76d4ad706e7bcc341f1",
+        );
+
+        let token_quote = TokenQuote {
+            token: Address::repeat_byte(0xcd),
+            exchange_rate: U256::from(2_500_000_000u64),
+            max_token_cost: U256::from(1),
+        };
+        let terms = PaymasterAndData { token_quote: Some(token_quote), ..sponsorship_terms() };
+        assert_eq!(
+            paymaster_hash_of(&spec_user_op(), EntryPointVersion::V06, 1, &terms),
+            "0xda33733428572eaa171e74049bed44563b85535aab1c3581a99024e314e7bdee",
+        );
+    }
+
+    // The signature is only known once the hash is, so neither paymasterAndData nor the signature
+    // may be part of it
+    #[test]
+    fn paymaster_hash_ignores_paymaster_and_data() {
+        let sponsored = UserOperation {
+            paymaster_and_data: format!("0x{}", "ab".repeat(149)).parse().unwrap(),
+            ..spec_user_op()
+        };
+        let signed = PaymasterAndData { signature: Bytes::from(vec![0x11; 65]), ..sponsorship_terms() };
+        assert_eq!(
+            paymaster_hash_of(&sponsored, EntryPointVersion::V06, 1, &signed),
+            paymaster_hash_of(&spec_user_op(), EntryPointVersion::V06, 1, &sponsorship_terms()),
+        );
+    }
+}